@@ -1,5 +1,8 @@
 use crate::{
-    definitions::{api_v2::Timestamp, Chain},
+    definitions::{
+        api_v2::Timestamp, ApiKey, Chain, DatabaseBackend, Merchant, ModificationPolicy,
+        UnderpaidPolicy, WebhookConfig,
+    },
     error::{Error, SeedEnvError},
     utils::logger,
 };
@@ -28,6 +31,7 @@ pub const OLD_SEED: &str = env_var_prefix!("OLD_SEED_");
 
 const SOCKET_DEFAULT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 16726);
 pub const DATABASE_DEFAULT: &str = "kalatori.db";
+pub const SQLITE_DATABASE_DEFAULT: &str = "kalatori.sqlite";
 
 #[derive(Parser)]
 #[command(
@@ -86,8 +90,22 @@ pub struct CliArgs {
     #[arg(long, env(env_var_prefix!("REMARK")), visible_alias("rmrk"), value_name("STRING"))]
     pub remark: Option<String>,
 
-    #[arg(short, long, env(env_var_prefix!("RECIPIENT")), value_name("HEX/SS58 ADDRESS"))]
-    pub recipient: String,
+    #[arg(
+        short,
+        long,
+        env(env_var_prefix!("RECIPIENT")),
+        value_name("HEX/SS58 ADDRESS"),
+        required_unless_present_any(["migrate_dry_run", "migrate_to_sqlite"])
+    )]
+    pub recipient: Option<String>,
+
+    /// Rehearse pending database migrations on a temporary copy of the database and exit.
+    #[arg(long)]
+    pub migrate_dry_run: bool,
+
+    /// Copy all records of the sled database to a new SQLite database at the given path and exit.
+    #[arg(long, value_name("PATH"))]
+    pub migrate_to_sqlite: Option<String>,
 }
 
 pub struct SeedEnvVars {
@@ -150,9 +168,22 @@ pub struct Config {
     #[serde(default = "get_host")]
     pub host: SocketAddr,
     pub database: Option<String>,
+    #[serde(default)]
+    pub database_backend: DatabaseBackend,
     pub debug: Option<bool>,
     #[serde(default)]
     pub in_memory_db: bool,
+    pub signature_lifetime: Option<Timestamp>,
+    #[serde(default)]
+    pub api_key: Vec<ApiKey>,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    #[serde(default)]
+    pub underpaid_policy: UnderpaidPolicy,
+    #[serde(default)]
+    pub order_modification: ModificationPolicy,
+    #[serde(default)]
+    pub merchant: Vec<Merchant>,
     pub chain: Vec<Chain>,
 }
 
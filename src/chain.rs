@@ -9,7 +9,10 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    definitions::{api_v2::OrderInfo, Chain},
+    definitions::{
+        api_v2::{BlockNumber, OrderInfo},
+        Balance, Chain,
+    },
     error::{ChainError, Error},
     signer::Signer,
     state::State,
@@ -17,13 +20,18 @@ use crate::{
 };
 
 pub mod definitions;
+pub mod endpoints;
+pub mod investigate;
 pub mod payout;
 pub mod rpc;
 pub mod tracker;
 pub mod utils;
 
-use crate::definitions::api_v2::{Health, RpcInfo, ServerHealth};
-use definitions::{ChainRequest, ChainTrackerRequest, WatchAccount};
+use crate::definitions::api_v2::{CurrencyInfo, RpcInfo};
+use definitions::{
+    refunded, BalanceRequest, ChainRequest, ChainTrackerRequest, InvestigateRequest, Investigation,
+    Invoice, RefundRequest, WatchAccount,
+};
 use tracker::start_chain_watch;
 
 /// Logging filter
@@ -38,6 +46,21 @@ pub struct ChainManager {
     pub tx: mpsc::Sender<ChainRequest>,
 }
 
+/// Chain watcher of the currency.
+fn tracker_of<'a>(
+    currency_map: &HashMap<String, String>,
+    watch_chain: &'a HashMap<String, mpsc::Sender<ChainTrackerRequest>>,
+    currency: &str,
+) -> Result<&'a mpsc::Sender<ChainTrackerRequest>, ChainError> {
+    let chain = currency_map
+        .get(currency)
+        .ok_or_else(|| ChainError::InvalidCurrency(currency.to_owned()))?;
+
+    watch_chain
+        .get(chain)
+        .ok_or_else(|| ChainError::InvalidChain(chain.clone()))
+}
+
 impl ChainManager {
     /// Run once to start all chain connections; this should be very robust, if manager fails
     /// - all modules should be restarted, probably.
@@ -62,6 +85,14 @@ impl ChainManager {
             if c.endpoints.is_empty() {
                 return Err(Error::EmptyEndpoints(c.name));
             }
+            if c.quorum
+                .is_some_and(|quorum| quorum == 0 || quorum > c.endpoints.len())
+            {
+                return Err(Error::InvalidQuorum(c.name));
+            }
+            if c.query_batch_size == 0 {
+                return Err(Error::InvalidQueryBatchSize(c.name));
+            }
             let (chain_tx, chain_rx) = mpsc::channel(1024);
             watch_chain.insert(c.name.clone(), chain_tx.clone());
 
@@ -92,7 +123,7 @@ impl ChainManager {
         task_tracker
             .clone()
             .spawn("Blockchain connections manager", async move {
-                let mut rpc_statuses: HashMap<(String, String), Health> = HashMap::new();
+                let mut rpc_statuses: HashMap<(String, String), RpcInfo> = HashMap::new();
 
 
                 // start requests engine
@@ -100,37 +131,83 @@ impl ChainManager {
                     tokio::select! {
                         Some(request) = rx.recv() => {
                             match request {
-                                ChainRequest::WatchAccount(request) => {
-                                    if let Some(chain) = currency_map.get(&request.currency.currency) {
-                                        if let Some(receiver) = watch_chain.get(chain) {
-                                            let _unused = receiver
-                                                .send(ChainTrackerRequest::WatchAccount(request))
-                                                .await;
-                                        } else {
-                                            let _unused = request
-                                                .res
-                                                .send(Err(ChainError::InvalidChain(chain.to_string())));
+                                ChainRequest::WatchAccount(watch) => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &watch.currency.currency);
+
+                                    match tracker {
+                                        Ok(receiver) => {
+                                            let _unused = receiver.send(ChainTrackerRequest::WatchAccount(watch)).await;
+                                        }
+                                        Err(e) => {
+                                            let _unused = watch.res.send(Err(e));
+                                        }
+                                    }
+                                }
+                                ChainRequest::UnwatchAccount { id, currency } => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &currency);
+
+                                    if let Ok(receiver) = tracker {
+                                        let _unused = receiver.send(ChainTrackerRequest::UnwatchAccount(id)).await;
+                                    }
+                                }
+                                ChainRequest::Reap(reap) => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &reap.currency.currency);
+
+                                    match tracker {
+                                        Ok(receiver) => {
+                                            let _unused = receiver.send(ChainTrackerRequest::Reap(reap)).await;
+                                        }
+                                        Err(e) => {
+                                            let _unused = reap.res.send(Err(e));
+                                        }
+                                    }
+                                }
+                                ChainRequest::ForceReap(force_reap) => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &force_reap.currency.currency);
+
+                                    match tracker {
+                                        Ok(receiver) => {
+                                            let _unused = receiver.send(ChainTrackerRequest::ForceReap(force_reap)).await;
+                                        }
+                                        Err(e) => {
+                                            let _unused = force_reap.res.send(Err(e));
+                                        }
+                                    }
+                                }
+                                ChainRequest::Refund(refund) => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &refund.invoice.currency.currency);
+
+                                    match tracker {
+                                        Ok(receiver) => {
+                                            let _unused = receiver.send(ChainTrackerRequest::Refund(refund)).await;
+                                        }
+                                        Err(e) => {
+                                            let _unused = refund.res.send(Err(e));
+                                        }
+                                    }
+                                }
+                                ChainRequest::Balance(balance) => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &balance.invoice.currency.currency);
+
+                                    match tracker {
+                                        Ok(receiver) => {
+                                            let _unused = receiver.send(ChainTrackerRequest::Balance(balance)).await;
+                                        }
+                                        Err(e) => {
+                                            let _unused = balance.res.send(Err(e));
                                         }
-                                    } else {
-                                        let _unused = request
-                                            .res
-                                            .send(Err(ChainError::InvalidCurrency(request.currency.currency)));
                                     }
                                 }
-                                ChainRequest::Reap(request) => {
-                                    if let Some(chain) = currency_map.get(&request.currency.currency) {
-                                        if let Some(receiver) = watch_chain.get(chain) {
-                                            let _unused =
-                                                receiver.send(ChainTrackerRequest::Reap(request)).await;
-                                        } else {
-                                            let _unused = request
-                                                .res
-                                                .send(Err(ChainError::InvalidChain(chain.to_string())));
+                                ChainRequest::Investigate(investigate) => {
+                                    let tracker = tracker_of(&currency_map, &watch_chain, &investigate.invoice.currency.currency);
+
+                                    match tracker {
+                                        Ok(receiver) => {
+                                            let _unused = receiver.send(ChainTrackerRequest::Investigate(investigate)).await;
+                                        }
+                                        Err(e) => {
+                                            let _unused = investigate.res.send(Err(e));
                                         }
-                                    } else {
-                                        let _unused = request
-                                            .res
-                                            .send(Err(ChainError::InvalidCurrency(request.currency.currency)));
                                     }
                                 }
                                 ChainRequest::Shutdown(res) => {
@@ -146,22 +223,14 @@ impl ChainManager {
                                     break;
                                 }
                                 ChainRequest::GetConnectedRpcs(res_tx) => {
-                                    // Collect the RpcInfo from rpc_statuses
-                                    let connected_rpcs: Vec<RpcInfo> = rpc_statuses.iter().map(|((chain_name, rpc_url), status)| {
-                                        RpcInfo {
-                                            chain_name: chain_name.clone(),
-                                            rpc_url: rpc_url.clone(),
-                                            status: *status,
-                                        }
-                                    }).collect();
-                                    let _ = res_tx.send(connected_rpcs);
+                                    let _ = res_tx.send(rpc_statuses.values().cloned().collect());
                                 }
                             }
                         }
                         Some(rpc_update) = rpc_update_rx.recv() => {
                             rpc_statuses.insert(
                                 (rpc_update.chain_name.clone(), rpc_update.rpc_url.clone()),
-                                rpc_update.status,
+                                rpc_update,
                             );
                         }
                         else => break,
@@ -190,6 +259,29 @@ impl ChainManager {
         rx.await.map_err(|_| ChainError::MessageDropped)?
     }
 
+    /// Watch the modified order on its new terms. It's no longer watched on the chain of its
+    /// previous currency if that's another chain.
+    pub async fn update_invoice(
+        &self,
+        id: String,
+        order: OrderInfo,
+        recipient: AccountId32,
+        previous_currency: CurrencyInfo,
+    ) -> Result<(), ChainError> {
+        if previous_currency.chain_name != order.currency.chain_name {
+            self.tx
+                .send(ChainRequest::UnwatchAccount {
+                    id: id.clone(),
+                    currency: previous_currency.currency,
+                })
+                .await
+                .map_err(|_| ChainError::MessageDropped)?;
+        }
+
+        // The tracker replaces the invoice it already watches under the same ID.
+        self.add_invoice(id, order, recipient).await
+    }
+
     pub async fn get_connected_rpcs(&self) -> Result<Vec<RpcInfo>, Error> {
         let (res_tx, res_rx) = oneshot::channel();
         self.tx
@@ -215,6 +307,88 @@ impl ChainManager {
         rx.await.map_err(|_| ChainError::MessageDropped)?
     }
 
+    /// Send the whole balance of the order payment account to the recipient regardless of the
+    /// overpayment policy.
+    pub async fn force_reap(
+        &self,
+        id: String,
+        order: OrderInfo,
+        recipient: AccountId32,
+    ) -> Result<(), ChainError> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(ChainRequest::ForceReap(WatchAccount::new(
+                id, order, recipient, res,
+            )?))
+            .await
+            .map_err(|_| ChainError::MessageDropped)?;
+        rx.await.map_err(|_| ChainError::MessageDropped)?
+    }
+
+    /// Return funds of the order payment account to the payers in proportion to what they have
+    /// paid. The whole balance is returned if the amount isn't given.
+    pub async fn refund(
+        &self,
+        id: String,
+        order: OrderInfo,
+        recipient: AccountId32,
+        amount: Option<Balance>,
+    ) -> Result<(), ChainError> {
+        let (res, rx) = oneshot::channel();
+        let refunded = refunded(&order.transactions);
+
+        self.tx
+            .send(ChainRequest::Refund(RefundRequest {
+                invoice: Invoice::from_order(id, order, recipient)?,
+                amount,
+                refunded,
+                res,
+            }))
+            .await
+            .map_err(|_| ChainError::MessageDropped)?;
+        rx.await.map_err(|_| ChainError::MessageDropped)?
+    }
+
+    /// Fetch current balance of the order payment account from the chain.
+    pub async fn balance(
+        &self,
+        id: String,
+        order: OrderInfo,
+        recipient: AccountId32,
+    ) -> Result<Balance, ChainError> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(ChainRequest::Balance(BalanceRequest {
+                invoice: Invoice::from_order(id, order, recipient)?,
+                res,
+            }))
+            .await
+            .map_err(|_| ChainError::MessageDropped)?;
+        rx.await.map_err(|_| ChainError::MessageDropped)?
+    }
+
+    /// Rescan chain history for transfers touching the order payment account.
+    pub async fn investigate(
+        &self,
+        id: String,
+        order: OrderInfo,
+        recipient: AccountId32,
+        from: Option<BlockNumber>,
+        to: Option<BlockNumber>,
+    ) -> Result<Investigation, ChainError> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(ChainRequest::Investigate(InvestigateRequest {
+                invoice: Invoice::from_order(id, order, recipient)?,
+                from,
+                to,
+                res,
+            }))
+            .await
+            .map_err(|_| ChainError::MessageDropped)?;
+        rx.await.map_err(|_| ChainError::MessageDropped)?
+    }
+
     pub async fn shutdown(&self) -> () {
         let (tx, rx) = oneshot::channel();
         let _unused = self.tx.send(ChainRequest::Shutdown(tx)).await;
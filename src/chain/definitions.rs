@@ -2,11 +2,19 @@
 
 use crate::{
     chain::{
-        rpc::{asset_balance_at_account, system_balance_at_account},
+        rpc::{
+            asset_balance_at_account, asset_balance_from_value, system_balance_at_account,
+            system_balance_from_value,
+        },
         tracker::ChainWatcher,
+        utils::{asset_balance_query, system_balance_query},
     },
+    database::TransactionInfoDb,
     definitions::{
-        api_v2::{BlockNumber, CurrencyInfo, Decimals, OrderInfo, RpcInfo, Timestamp},
+        api_v2::{
+            Amount, AssetId, BlockNumber, CurrencyInfo, OrderInfo, PayoutSplit, RpcInfo,
+            SplitShare, Timestamp, TransactionInfo, TxKind, TxStatus,
+        },
         Balance,
     },
     error::{ChainError, NotHexError},
@@ -14,6 +22,7 @@ use crate::{
 };
 use jsonrpsee::ws_client::WsClient;
 use primitive_types::H256;
+use serde_json::Value;
 use substrate_crypto_light::common::{AccountId32, AsBase58};
 use tokio::sync::oneshot;
 
@@ -48,7 +57,16 @@ pub struct EventFilter<'a> {
 
 pub enum ChainRequest {
     WatchAccount(WatchAccount),
+    /// Stop watching the order on the chain of the given currency.
+    UnwatchAccount {
+        id: String,
+        currency: String,
+    },
     Reap(WatchAccount),
+    ForceReap(WatchAccount),
+    Refund(RefundRequest),
+    Balance(BalanceRequest),
+    Investigate(InvestigateRequest),
     Shutdown(oneshot::Sender<()>),
     GetConnectedRpcs(oneshot::Sender<Vec<RpcInfo>>),
 }
@@ -58,10 +76,13 @@ pub struct WatchAccount {
     pub id: String,
     pub address: AccountId32,
     pub currency: CurrencyInfo,
-    pub amount: f64,
+    pub amount: Balance,
     pub recipient: AccountId32,
     pub res: oneshot::Sender<Result<(), ChainError>>,
     pub death: Timestamp,
+    pub received: Balance,
+    pub payers: Vec<(AccountId32, Balance)>,
+    pub splits: Vec<(AccountId32, SplitShare)>,
 }
 
 impl WatchAccount {
@@ -81,15 +102,124 @@ impl WatchAccount {
             recipient,
             res,
             death: order.death,
+            received: order.received,
+            payers: payers(&order.transactions),
+            splits: split_recipients(&order.splits)?,
         })
     }
 }
 
+/// Payout split rules with parsed recipient accounts.
+fn split_recipients(splits: &[PayoutSplit]) -> Result<Vec<(AccountId32, SplitShare)>, ChainError> {
+    splits
+        .iter()
+        .map(|split| {
+            AccountId32::from_base58_string(&split.recipient)
+                .map(|(recipient, _)| (recipient, split.share))
+                .map_err(|e| ChainError::InvoiceAccount(e.to_string()))
+        })
+        .collect()
+}
+
+/// Senders of the order payments along with the amounts they have paid, in the order of their
+/// first payment.
+pub fn payers(transactions: &[TransactionInfo]) -> Vec<(AccountId32, Balance)> {
+    totals(transactions, TxKind::Payment)
+}
+
+/// Payers along with the amounts already returned to them. Submitted refunds count even before
+/// they are finalized, so a repeated refund doesn't pay anyone twice.
+pub fn refunded(transactions: &[TransactionInfo]) -> Vec<(AccountId32, Balance)> {
+    totals(transactions, TxKind::Refund)
+}
+
+/// Amounts of the transactions of a kind summed by the counterparty, i.e. the sender of payments
+/// and the recipient of everything else.
+fn totals(transactions: &[TransactionInfo], kind: TxKind) -> Vec<(AccountId32, Balance)> {
+    let mut totals: Vec<(AccountId32, Balance)> = Vec::new();
+
+    for transaction in transactions {
+        let Amount::Exact(amount) = &transaction.amount else {
+            continue;
+        };
+
+        if transaction.kind != kind || matches!(transaction.status, TxStatus::Failed) {
+            continue;
+        }
+
+        let counterparty = if kind == TxKind::Payment {
+            &transaction.sender
+        } else {
+            &transaction.recipient
+        };
+        let Ok((account, _)) = AccountId32::from_base58_string(counterparty) else {
+            continue;
+        };
+
+        if let Some((_, total)) = totals.iter_mut().find(|(known, _)| *known == account) {
+            *total = Balance(total.saturating_add(**amount));
+        } else {
+            totals.push((account, *amount));
+        }
+    }
+
+    totals
+}
+
+/// Request for the current balance of an invoice account at the last finalized block
+#[derive(Debug)]
+pub struct BalanceRequest {
+    pub invoice: Invoice,
+    pub res: oneshot::Sender<Result<Balance, ChainError>>,
+}
+
+/// Request to return funds of an invoice account to its payers
+#[derive(Debug)]
+pub struct RefundRequest {
+    pub invoice: Invoice,
+    /// Amount to return, the whole balance if not given.
+    pub amount: Option<Balance>,
+    /// What the payers have already got back from earlier refunds of the order.
+    pub refunded: Vec<(AccountId32, Balance)>,
+    pub res: oneshot::Sender<Result<(), ChainError>>,
+}
+
+/// Request to rescan a block range for transfers touching an invoice account
+///
+/// Missing bounds are picked by the tracker, relative to the last finalized block.
+pub struct InvestigateRequest {
+    pub invoice: Invoice,
+    pub from: Option<BlockNumber>,
+    pub to: Option<BlockNumber>,
+    pub res: oneshot::Sender<Result<Investigation, ChainError>>,
+}
+
+/// Everything found on chain about an invoice account during an investigation
+pub struct Investigation {
+    pub balance: Balance,
+    pub from: BlockNumber,
+    pub to: BlockNumber,
+    pub transactions: Vec<TransactionInfoDb>,
+    pub unreadable_blocks: Vec<BlockNumber>,
+}
+
 pub enum ChainTrackerRequest {
     WatchAccount(WatchAccount),
+    UnwatchAccount(String),
     NewBlock(BlockNumber),
+    /// Changes of the watched balance storage, each as the key with the new value.
+    StorageChanges(Vec<(String, Option<String>)>),
+    /// The result of confirming the payment of an order with the quorum of endpoints.
+    PaymentConfirmation {
+        id: String,
+        block_number: BlockNumber,
+        confirmed: bool,
+    },
     Reap(WatchAccount),
+    Balance(BalanceRequest),
+    Investigate(InvestigateRequest),
     ForceReap(WatchAccount),
+    Refund(RefundRequest),
     Shutdown(oneshot::Sender<()>),
 }
 
@@ -98,9 +228,20 @@ pub struct Invoice {
     pub id: String,
     pub address: AccountId32,
     pub currency: CurrencyInfo,
-    pub amount: f64,
+    pub amount: Balance,
     pub recipient: AccountId32,
     pub death: Timestamp,
+    /// The last known balance of the payment account.
+    pub received: Balance,
+    /// The order has expired underpaid, and is still watched until it's paid in full.
+    pub overdue: bool,
+    /// Payers along with the amounts they have paid, to split refunds between them.
+    pub payers: Vec<(AccountId32, Balance)>,
+    /// Payout split rules; the rest of the payout goes to the recipient.
+    pub splits: Vec<(AccountId32, SplitShare)>,
+    /// Balance from a storage change notification that isn't seen at a finalized block yet,
+    /// along with the last finalized block number at the time of the notification.
+    pub pending_change: Option<(Balance, BlockNumber)>,
 }
 
 impl Invoice {
@@ -113,9 +254,36 @@ impl Invoice {
             amount: watch_account.amount,
             recipient: watch_account.recipient,
             death: watch_account.death,
+            received: watch_account.received,
+            overdue: false,
+            payers: watch_account.payers,
+            splits: watch_account.splits,
+            pending_change: None,
         }
     }
 
+    pub fn from_order(
+        id: String,
+        order: OrderInfo,
+        recipient: AccountId32,
+    ) -> Result<Self, ChainError> {
+        Ok(Invoice {
+            id,
+            address: AccountId32::from_base58_string(&order.payment_account)
+                .map_err(|e| ChainError::InvoiceAccount(e.to_string()))?
+                .0,
+            currency: order.currency,
+            amount: order.amount,
+            recipient,
+            death: order.death,
+            received: order.received,
+            overdue: false,
+            payers: payers(&order.transactions),
+            splits: split_recipients(&order.splits)?,
+            pending_change: None,
+        })
+    }
+
     pub async fn balance(
         &self,
         client: &WsClient,
@@ -144,13 +312,48 @@ impl Invoice {
         }
     }
 
+    fn asset_id(&self, chain_watcher: &ChainWatcher) -> Result<Option<AssetId>, ChainError> {
+        chain_watcher
+            .assets
+            .get(&self.currency.currency)
+            .map(|currency| currency.asset_id)
+            .ok_or(ChainError::InvalidCurrency(self.currency.currency.clone()))
+    }
+
+    /// Storage key of the payment account balance.
+    pub fn storage_key(&self, chain_watcher: &ChainWatcher) -> Result<String, ChainError> {
+        let query = if let Some(asset_id) = self.asset_id(chain_watcher)? {
+            asset_balance_query(&chain_watcher.metadata, &self.address, asset_id)?
+        } else {
+            system_balance_query(&chain_watcher.metadata, &self.address)?
+        };
+
+        Ok(query.key)
+    }
+
+    /// Decode the payment account balance from the storage value under its key.
+    pub fn balance_from_storage(
+        &self,
+        chain_watcher: &ChainWatcher,
+        value: String,
+    ) -> Result<Balance, ChainError> {
+        if let Some(asset_id) = self.asset_id(chain_watcher)? {
+            let query = asset_balance_query(&chain_watcher.metadata, &self.address, asset_id)?;
+
+            asset_balance_from_value(&Value::String(value), &query, &chain_watcher.metadata)
+        } else {
+            let query = system_balance_query(&chain_watcher.metadata, &self.address)?;
+
+            system_balance_from_value(&Value::String(value), &query, &chain_watcher.metadata)
+        }
+    }
+
     pub async fn check(
         &self,
         client: &WsClient,
         chain_watcher: &ChainWatcher,
         block: &BlockHash,
     ) -> Result<bool, ChainError> {
-        Ok(self.balance(client, chain_watcher, block).await?
-            >= Balance::parse(self.amount, self.currency.decimals))
+        Ok(self.balance(client, chain_watcher, block).await? >= self.amount)
     }
 }
@@ -0,0 +1,687 @@
+//! Health scores of chain RPC endpoints and the weighted random pick between them
+//!
+//! Each endpoint is scored by its response latency, recent failures, and how far its best block
+//! lags behind the other endpoints of the chain. The tracker picks the endpoint to connect to at
+//! random with the scores as weights, so a slow endpoint is still used now and then, but rarely,
+//! and a failing one is left alone for a cooldown that grows with each consecutive failure.
+//!
+//! With a quorum set for the chain, a payment is confirmed by several endpoints before the order is
+//! marked as paid, so a single faulty or malicious node can't make it look paid.
+
+use crate::{
+    chain::{
+        definitions::{BlockHash, ChainTrackerRequest, Invoice},
+        rpc::{best_block_number, finalized_block_hash},
+        tracker::ChainWatcher,
+    },
+    definitions::{
+        api_v2::{BlockNumber, Health, RpcInfo, Timestamp},
+        Balance,
+    },
+    utils::task_tracker::TaskTracker,
+};
+use jsonrpsee::ws_client::{WsClient, WsClientBuilder};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Instant, SystemTime},
+};
+use tokio::{
+    sync::mpsc,
+    task::JoinSet,
+    time::{sleep, timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+/// The best score of an endpoint.
+pub const MAX_SCORE: u64 = 100;
+/// Latency at which the latency part of the score is halved.
+const LATENCY_REFERENCE: Duration = Duration::from_millis(500);
+/// Block lag at which the lag part of the score is halved.
+const LAG_REFERENCE: BlockNumber = 3;
+/// Cooldown after the first failure, doubled with each consecutive one.
+const COOLDOWN_BASE: Duration = Duration::from_secs(5);
+const COOLDOWN_MAX: Duration = Duration::from_secs(60);
+/// Interval between probes of all endpoints of a chain.
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Weight of the latest sample in the latency moving average, in percent.
+const LATENCY_WEIGHT: u32 = 30;
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    status: Health,
+    latency: Option<Duration>,
+    /// Consecutive failures, decreased by each success.
+    failures: u32,
+    cooldown_until: Option<Instant>,
+    /// The best block number reported by the last probe.
+    best_block: Option<BlockNumber>,
+    disagreements: u32,
+}
+
+impl Endpoint {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            status: Health::Degraded,
+            latency: None,
+            failures: 0,
+            cooldown_until: None,
+            best_block: None,
+            disagreements: 0,
+        }
+    }
+
+    fn in_cooldown(&self, now: Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| until > now)
+    }
+
+    fn succeeded(&mut self, latency: Duration) {
+        self.status = Health::Ok;
+        self.failures = self.failures.saturating_sub(1);
+        self.latency = Some(match self.latency {
+            Some(average) => average
+                .saturating_mul(100_u32.saturating_sub(LATENCY_WEIGHT))
+                .saturating_add(latency.saturating_mul(LATENCY_WEIGHT))
+                .checked_div(100)
+                .unwrap_or(latency),
+            None => latency,
+        });
+    }
+
+    fn failed(&mut self) {
+        self.status = Health::Critical;
+        self.failures = self.failures.saturating_add(1);
+        self.cooldown_until = Instant::now().checked_add(cooldown(self.failures));
+    }
+}
+
+/// Endpoints of a single chain along with their health, which is reported to the chain manager on
+/// each change. Cloning gives another handle to the same endpoints.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    chain: String,
+    list: Arc<Mutex<Vec<Endpoint>>>,
+    /// Connections kept for probes and payment confirmations, by the endpoint URLs.
+    clients: Arc<Mutex<HashMap<String, Arc<WsClient>>>>,
+    quorum: Option<usize>,
+    rpc_update_tx: mpsc::Sender<RpcInfo>,
+}
+
+impl Endpoints {
+    pub fn new(
+        chain: String,
+        urls: &[String],
+        quorum: Option<usize>,
+        rpc_update_tx: mpsc::Sender<RpcInfo>,
+    ) -> Self {
+        Self {
+            chain,
+            quorum,
+            list: Arc::new(Mutex::new(
+                urls.iter().cloned().map(Endpoint::new).collect(),
+            )),
+            clients: Arc::default(),
+            rpc_update_tx,
+        }
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Vec<Endpoint>) -> T) -> T {
+        f(&mut self.list.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    async fn update(&self, url: &str, f: impl FnOnce(&mut Endpoint)) {
+        if let Some(info) = self.with_endpoint(url, f) {
+            drop(self.rpc_update_tx.send(info).await);
+        }
+    }
+
+    fn with_endpoint(&self, url: &str, f: impl FnOnce(&mut Endpoint)) -> Option<RpcInfo> {
+        self.with(|endpoints| {
+            let lag_base = best_block(endpoints);
+
+            endpoints
+                .iter_mut()
+                .find(|endpoint| endpoint.url == url)
+                .map(|endpoint| {
+                    f(endpoint);
+
+                    rpc_info(&self.chain, endpoint, lag_base)
+                })
+        })
+    }
+
+    /// Pick an endpoint at random, weighted by the scores, skipping the ones in cooldown. If all
+    /// of them are in cooldown, the time until the first one is available again is returned
+    /// instead.
+    pub fn pick(&self) -> Result<String, Duration> {
+        let now = Instant::now();
+
+        self.with(|endpoints| {
+            let lag_base = best_block(endpoints);
+            let weights: Vec<u64> = endpoints
+                .iter()
+                .map(|endpoint| {
+                    if endpoint.in_cooldown(now) {
+                        0
+                    } else {
+                        score(endpoint, lag_base).max(1)
+                    }
+                })
+                .collect();
+
+            weighted_pick(
+                &weights,
+                fastrand::u64(..weights.iter().sum::<u64>().max(1)),
+            )
+            .map(|index| endpoints[index].url.clone())
+            .ok_or_else(|| {
+                endpoints
+                    .iter()
+                    .filter_map(|endpoint| endpoint.cooldown_until)
+                    .min()
+                    .map_or(COOLDOWN_BASE, |until| until.saturating_duration_since(now))
+            })
+        })
+    }
+
+    /// Mark the endpoint as the one being connected to.
+    pub async fn connecting(&self, url: &str) {
+        self.update(url, |endpoint| endpoint.status = Health::Degraded)
+            .await;
+    }
+
+    /// Record a successful connection to the endpoint and the time it took.
+    pub async fn connected(&self, url: &str, latency: Duration) {
+        self.update(url, |endpoint| endpoint.succeeded(latency))
+            .await;
+    }
+
+    /// Record the time a request to the endpoint took. Unlike the connection, it isn't reported
+    /// right away, but with the next probes.
+    pub fn responded(&self, url: &str, latency: Duration) {
+        self.with_endpoint(url, |endpoint| endpoint.succeeded(latency));
+    }
+
+    /// Record a failure of the endpoint and put it in cooldown.
+    pub async fn failure(&self, url: &str) {
+        self.update(url, Endpoint::failed).await;
+    }
+
+    fn urls(&self) -> Vec<String> {
+        self.with(|endpoints| {
+            endpoints
+                .iter()
+                .map(|endpoint| endpoint.url.clone())
+                .collect()
+        })
+    }
+
+    /// Whether payments have to be confirmed by a quorum of endpoints before the orders are marked
+    /// as paid.
+    pub fn needs_confirmation(&self) -> bool {
+        self.quorum.is_some()
+    }
+
+    /// Confirm the payment of the order in the background, so blocks keep being processed
+    /// meanwhile. The result is sent back to the tracker as
+    /// [`ChainTrackerRequest::PaymentConfirmation`].
+    pub fn start_confirmation(
+        &self,
+        client: Arc<WsClient>,
+        watcher: ChainWatcher,
+        invoice: Invoice,
+        block_number: BlockNumber,
+        chain_tx: mpsc::Sender<ChainTrackerRequest>,
+        task_tracker: &TaskTracker,
+    ) {
+        let endpoints = self.clone();
+        let id = invoice.id.clone();
+
+        task_tracker.spawn(format!("Confirm payment of order {id}"), async move {
+            let confirmed = endpoints
+                .confirm_paid(&client, Arc::new(watcher), Arc::new(invoice))
+                .await;
+
+            drop(
+                chain_tx
+                    .send(ChainTrackerRequest::PaymentConfirmation {
+                        id: id.clone(),
+                        block_number,
+                        confirmed,
+                    })
+                    .await,
+            );
+
+            Ok(format!("Payment confirmation of order {id} finished"))
+        });
+    }
+
+    /// Confirm that the order is paid with the balances of its payment account at the finalized
+    /// block, as all endpoints of the chain report them at once. Endpoints that disagree with the
+    /// majority of them are logged and have it counted in their health. Without a quorum, the
+    /// payment is confirmed right away.
+    async fn confirm_paid(
+        &self,
+        client: &WsClient,
+        watcher: Arc<ChainWatcher>,
+        invoice: Arc<Invoice>,
+    ) -> bool {
+        let Some(quorum) = self.quorum else {
+            return true;
+        };
+        let block = match finalized_block_hash(client).await {
+            Ok(block) => block,
+            Err(e) => {
+                tracing::warn!("Failed to fetch the finalized block of {}: {e}", self.chain);
+
+                return false;
+            }
+        };
+        let mut fetches = JoinSet::new();
+
+        for url in self.urls() {
+            let endpoints = self.clone();
+            let watcher_for_fetch = watcher.clone();
+            let invoice_for_fetch = invoice.clone();
+            let block_for_fetch = block.clone();
+
+            fetches.spawn(async move {
+                let fetch = endpoints.balance(
+                    &url,
+                    &watcher_for_fetch,
+                    &invoice_for_fetch,
+                    &block_for_fetch,
+                );
+                let balance = timeout(PROBE_TIMEOUT, fetch).await.ok().flatten();
+
+                (url, balance)
+            });
+        }
+
+        let mut balances = Vec::new();
+
+        for (url, fetched) in fetches.join_all().await {
+            if let Some(balance) = fetched {
+                balances.push((url, balance));
+            } else {
+                tracing::warn!(
+                    "RPC server {url} didn't report the balance of order {} at block {}",
+                    invoice.id,
+                    block.to_string()
+                );
+            }
+        }
+
+        let (confirmed, updates) = self.tally(&invoice.id, invoice.amount, quorum, &balances);
+
+        for info in updates {
+            drop(self.rpc_update_tx.send(info).await);
+        }
+
+        confirmed
+    }
+
+    /// The balance of the payment account as the endpoint reports it, over the kept connection.
+    async fn balance(
+        &self,
+        url: &str,
+        watcher: &ChainWatcher,
+        invoice: &Invoice,
+        block: &BlockHash,
+    ) -> Option<Balance> {
+        let client = self.client(url).await?;
+        let balance = invoice.balance(&client, watcher, block).await;
+
+        if let Err(e) = &balance {
+            tracing::debug!("RPC server {url} failed to fetch the balance: {e}");
+
+            self.forget(url);
+        }
+
+        balance.ok()
+    }
+
+    /// Fetch the best block number of the endpoint over the kept connection, along with the time
+    /// the request took.
+    async fn probe(&self, url: &str) -> Option<(BlockNumber, Duration)> {
+        let client = timeout(PROBE_TIMEOUT, self.client(url)).await.ok()??;
+        let start = Instant::now();
+        let response = timeout(PROBE_TIMEOUT, best_block_number(&client)).await;
+
+        if let Ok(Ok(best_block)) = response {
+            Some((best_block, start.elapsed()))
+        } else {
+            self.forget(url);
+
+            None
+        }
+    }
+
+    /// The connection to the endpoint kept from the previous requests, or a new one if there's
+    /// none or it's closed.
+    async fn client(&self, url: &str) -> Option<Arc<WsClient>> {
+        let cached = self
+            .clients
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(url)
+            .filter(|client| client.is_connected())
+            .cloned();
+
+        if cached.is_some() {
+            return cached;
+        }
+
+        let client = Arc::new(WsClientBuilder::default().build(url).await.ok()?);
+
+        self.clients
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(url.to_owned(), client.clone());
+
+        Some(client)
+    }
+
+    /// Drop the connection to the failed endpoint, to make it anew next time.
+    fn forget(&self, url: &str) {
+        self.clients
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(url);
+    }
+
+    /// Whether enough endpoints report the amount paid. Endpoints that disagree with the majority
+    /// are marked, and their health is returned to be reported. Without a majority, none of them
+    /// is.
+    fn tally(
+        &self,
+        id: &str,
+        amount: Balance,
+        quorum: usize,
+        balances: &[(String, Balance)],
+    ) -> (bool, Vec<RpcInfo>) {
+        let mut updates = Vec::new();
+
+        if let Some(majority) = majority(balances) {
+            for (url, balance) in balances.iter().filter(|(_, balance)| *balance != majority) {
+                tracing::warn!(
+                    "RPC server {url} reports balance {} of order {id}, while the majority reports {}",
+                    balance.0,
+                    majority.0
+                );
+
+                updates.extend(self.with_endpoint(url, |endpoint| {
+                    endpoint.status = Health::Degraded;
+                    endpoint.disagreements = endpoint.disagreements.saturating_add(1);
+                }));
+            }
+        }
+
+        let confirmations = balances
+            .iter()
+            .filter(|(_, balance)| *balance >= amount)
+            .count();
+
+        if confirmations < quorum {
+            tracing::info!(
+                "Payment of order {id} is confirmed by {confirmations} RPC servers of {}, while {quorum} are required",
+                self.chain
+            );
+        }
+
+        (confirmations >= quorum, updates)
+    }
+
+    /// Health of all endpoints of the chain.
+    pub fn report(&self) -> Vec<RpcInfo> {
+        self.with(|endpoints| {
+            let lag_base = best_block(endpoints);
+
+            endpoints
+                .iter()
+                .map(|endpoint| rpc_info(&self.chain, endpoint, lag_base))
+                .collect()
+        })
+    }
+
+    /// Probe all endpoints of the chain periodically for their latency and best block, until the
+    /// shutdown.
+    pub fn start_probes(&self, task_tracker: &TaskTracker, cancellation_token: CancellationToken) {
+        let endpoints = self.clone();
+
+        task_tracker.spawn(
+            format!("Probe RPC endpoints of {}", self.chain),
+            async move {
+                loop {
+                    tokio::select! {
+                        () = cancellation_token.cancelled() => break,
+                        () = sleep(PROBE_INTERVAL) => {}
+                    }
+
+                    for url in endpoints.urls() {
+                        let probed = endpoints.probe(&url).await;
+
+                        endpoints.with_endpoint(&url, |endpoint| {
+                            endpoint.best_block = probed.map(|(best_block, _)| best_block);
+
+                            match probed {
+                                Some((_, latency)) => endpoint.succeeded(latency),
+                                None => endpoint.failed(),
+                            }
+                        });
+                    }
+
+                    for info in endpoints.report() {
+                        if endpoints.rpc_update_tx.send(info).await.is_err() {
+                            return Ok(format!("RPC probes of {} stopped", endpoints.chain));
+                        }
+                    }
+                }
+
+                Ok(format!("RPC probes of {} stopped", endpoints.chain))
+            },
+        );
+    }
+}
+
+/// The balance reported by more than half of the endpoints. Without such, there's no telling
+/// which endpoints are wrong.
+fn majority(balances: &[(String, Balance)]) -> Option<Balance> {
+    balances
+        .iter()
+        .map(|(_, balance)| *balance)
+        .find(|balance| {
+            balances
+                .iter()
+                .filter(|(_, other)| other == balance)
+                .count()
+                .saturating_mul(2)
+                > balances.len()
+        })
+}
+
+fn cooldown(failures: u32) -> Duration {
+    COOLDOWN_BASE
+        .saturating_mul(2_u32.saturating_pow(failures.saturating_sub(1)))
+        .min(COOLDOWN_MAX)
+}
+
+fn best_block(endpoints: &[Endpoint]) -> Option<BlockNumber> {
+    endpoints
+        .iter()
+        .filter_map(|endpoint| endpoint.best_block)
+        .max()
+}
+
+fn block_lag(endpoint: &Endpoint, lag_base: Option<BlockNumber>) -> Option<BlockNumber> {
+    Some(lag_base?.saturating_sub(endpoint.best_block?))
+}
+
+/// Score of the endpoint from 0 to [`MAX_SCORE`]. Each of the latency, the failures, and the block
+/// lag scales it down; an endpoint that hasn't been measured yet gets the benefit of the doubt.
+fn score(endpoint: &Endpoint, lag_base: Option<BlockNumber>) -> u64 {
+    let reference = u64::try_from(LATENCY_REFERENCE.as_millis()).unwrap_or(u64::MAX);
+    let latency = endpoint.latency.map_or(0, |latency| {
+        u64::try_from(latency.as_millis()).unwrap_or(u64::MAX)
+    });
+    let lag = block_lag(endpoint, lag_base).map_or(0, u64::from);
+    let lag_reference = u64::from(LAG_REFERENCE);
+
+    MAX_SCORE
+        .saturating_mul(reference)
+        .checked_div(reference.saturating_add(latency))
+        .and_then(|score| {
+            score
+                .saturating_mul(lag_reference)
+                .checked_div(lag_reference.saturating_add(lag))
+        })
+        .and_then(|score| score.checked_div(u64::from(endpoint.failures).saturating_add(1)))
+        .unwrap_or_default()
+}
+
+/// Index of the weight the point falls on, with the weights laid out one after another.
+fn weighted_pick(weights: &[u64], mut point: u64) -> Option<usize> {
+    weights.iter().position(|weight| {
+        if point < *weight {
+            true
+        } else {
+            point = point.saturating_sub(*weight);
+
+            false
+        }
+    })
+}
+
+fn rpc_info(chain: &str, endpoint: &Endpoint, lag_base: Option<BlockNumber>) -> RpcInfo {
+    let now = Instant::now();
+
+    RpcInfo {
+        rpc_url: endpoint.url.clone(),
+        chain_name: chain.to_string(),
+        status: endpoint.status,
+        score: score(endpoint, lag_base),
+        latency: endpoint
+            .latency
+            .map(|latency| u64::try_from(latency.as_millis()).unwrap_or(u64::MAX)),
+        failures: endpoint.failures,
+        block_lag: block_lag(endpoint, lag_base),
+        disagreements: endpoint.disagreements,
+        cooldown_until: endpoint
+            .cooldown_until
+            .filter(|until| *until > now)
+            .and_then(|until| SystemTime::now().checked_add(until.saturating_duration_since(now)))
+            .map(|at| {
+                Timestamp(
+                    at.duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis()
+                        .try_into()
+                        .unwrap_or(u64::MAX),
+                )
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_scores() {
+        let mut endpoint = Endpoint::new("wss://fast".into());
+
+        assert_eq!(score(&endpoint, None), MAX_SCORE);
+
+        endpoint.latency = Some(LATENCY_REFERENCE);
+        assert_eq!(score(&endpoint, None), 50);
+
+        endpoint.best_block = Some(100);
+        assert_eq!(score(&endpoint, Some(103)), 25);
+
+        endpoint.failures = 1;
+        assert_eq!(score(&endpoint, Some(100)), 25);
+
+        assert_eq!(cooldown(1), COOLDOWN_BASE);
+        assert_eq!(cooldown(2), COOLDOWN_BASE * 2);
+        assert_eq!(cooldown(u32::MAX), COOLDOWN_MAX);
+
+        assert_eq!(weighted_pick(&[0, 10, 5], 0), Some(1));
+        assert_eq!(weighted_pick(&[0, 10, 5], 12), Some(2));
+        assert_eq!(weighted_pick(&[0, 0], 0), None);
+
+        let balances = [
+            ("wss://a".to_owned(), Balance(5)),
+            ("wss://b".to_owned(), Balance(7)),
+            ("wss://c".to_owned(), Balance(7)),
+        ];
+
+        assert_eq!(majority(&balances), Some(Balance(7)));
+        assert_eq!(majority(&[]), None);
+
+        // A tie has no majority, whatever the order of the endpoints.
+        let tie = [
+            ("wss://a".to_owned(), Balance(5)),
+            ("wss://b".to_owned(), Balance(7)),
+        ];
+
+        assert_eq!(majority(&tie), None);
+        assert_eq!(majority(&[tie[1].clone(), tie[0].clone()]), None);
+        assert_eq!(
+            majority(&[
+                ("wss://a".to_owned(), Balance(5)),
+                ("wss://b".to_owned(), Balance(7)),
+                ("wss://c".to_owned(), Balance(9)),
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn payment_quorum() {
+        let urls = [
+            "wss://a".to_owned(),
+            "wss://b".to_owned(),
+            "wss://c".to_owned(),
+        ];
+        let endpoints = Endpoints::new("chain".into(), &urls, Some(2), mpsc::channel(1).0);
+        let disagreements = |url: &str| {
+            endpoints
+                .report()
+                .into_iter()
+                .find(|info| info.rpc_url == url)
+                .map(|info| info.disagreements)
+        };
+
+        // One endpoint disagrees, but the other two are enough.
+        let balances = [
+            (urls[0].clone(), Balance(5)),
+            (urls[1].clone(), Balance(7)),
+            (urls[2].clone(), Balance(7)),
+        ];
+        let (confirmed, updates) = endpoints.tally("order", Balance(7), 2, &balances);
+
+        assert!(confirmed);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].rpc_url, urls[0]);
+        assert!(matches!(updates[0].status, Health::Degraded));
+        assert_eq!(disagreements(&urls[0]), Some(1));
+        assert_eq!(disagreements(&urls[1]), Some(0));
+
+        // The unreachable endpoint neither confirms the payment nor disagrees.
+        let (short, agreed) = endpoints.tally("order", Balance(7), 3, &balances[1..]);
+
+        assert!(!short);
+        assert!(agreed.is_empty());
+
+        // Two endpoints that disagree are both left alone, as neither is known to be wrong.
+        let (unconfirmed, tied) = endpoints.tally("order", Balance(6), 2, &balances[..2]);
+
+        assert!(!unconfirmed);
+        assert!(tied.is_empty());
+        assert_eq!(disagreements(&urls[0]), Some(1));
+        assert_eq!(disagreements(&urls[1]), Some(0));
+    }
+}
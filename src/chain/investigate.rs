@@ -0,0 +1,115 @@
+//! On-demand rescan of chain history for a single invoice.
+//!
+//! Used to settle disputes about payments the tracker might have missed, so it runs on its own
+//! connection and never holds up the chain tracker.
+
+use crate::{
+    chain::{
+        definitions::{Investigation, Invoice},
+        rpc::{block_hash, current_block_number, finalized_block_hash, transfer_events},
+        tracker::ChainWatcher,
+        utils::transfer_transactions,
+    },
+    definitions::api_v2::BlockNumber,
+    error::ChainError,
+};
+use jsonrpsee::ws_client::WsClientBuilder;
+
+/// Number of blocks scanned back from the upper bound if the lower one isn't given.
+pub const DEFAULT_DEPTH: BlockNumber = 100;
+
+/// Largest number of blocks a single investigation is allowed to scan.
+pub const MAX_BLOCKS: BlockNumber = 1000;
+
+/// Read the invoice balance at the last finalized block and collect all transfers touching the
+/// invoice account within the given block range.
+///
+/// The range is clamped to the last finalized block. Blocks that can't be read with the current
+/// metadata (e.g., ones before a runtime upgrade) are skipped and reported back.
+pub async fn investigate(
+    rpc: String,
+    invoice: Invoice,
+    from: Option<BlockNumber>,
+    to: Option<BlockNumber>,
+    chain: ChainWatcher,
+) -> Result<Investigation, ChainError> {
+    let client = WsClientBuilder::default().build(&rpc).await?;
+    let block = finalized_block_hash(&client).await?;
+    let finalized = current_block_number(&client, &chain.metadata, &block).await?;
+    let balance = invoice.balance(&client, &chain, &block).await?;
+    let (first, last) = scan_range(from, to, finalized);
+
+    let mut transactions = Vec::new();
+    let mut unreadable_blocks = Vec::new();
+
+    for block_number in first..=last {
+        let scanned_block = block_hash(&client, Some(block_number)).await?;
+
+        match transfer_events(&client, &scanned_block, &chain.metadata).await {
+            Ok((timestamp, events)) => {
+                transactions.extend(transfer_transactions(
+                    &invoice,
+                    block_number,
+                    timestamp,
+                    &events,
+                )?);
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to read events of block {block_number} while investigating order {}: {e}",
+                    invoice.id
+                );
+
+                unreadable_blocks.push(block_number);
+            }
+        }
+    }
+
+    Ok(Investigation {
+        balance,
+        from: first,
+        to: last,
+        transactions,
+        unreadable_blocks,
+    })
+}
+
+/// First and last blocks to scan. A missing lower bound is [`DEFAULT_DEPTH`] blocks below the
+/// upper one, and a missing upper bound is [`MAX_BLOCKS`] above the lower one.
+fn scan_range(
+    from: Option<BlockNumber>,
+    to: Option<BlockNumber>,
+    finalized: BlockNumber,
+) -> (BlockNumber, BlockNumber) {
+    match (from, to) {
+        (Some(first), Some(last)) => (first, last.min(finalized)),
+        (Some(first), None) => (
+            first,
+            first
+                .saturating_add(MAX_BLOCKS.saturating_sub(1))
+                .min(finalized),
+        ),
+        (None, last_option) => {
+            let last = last_option.unwrap_or(finalized).min(finalized);
+
+            (last.saturating_sub(DEFAULT_DEPTH.saturating_sub(1)), last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_ranges() {
+        assert_eq!(scan_range(None, None, 1000), (901, 1000));
+        assert_eq!(scan_range(None, Some(500), 1000), (401, 500));
+        assert_eq!(scan_range(None, Some(50), 1000), (0, 50));
+        assert_eq!(scan_range(Some(10), None, 5000), (10, 1009));
+        assert_eq!(scan_range(Some(10), None, 500), (10, 500));
+        // Blocks above the finalized one can't be scanned yet.
+        assert_eq!(scan_range(Some(10), Some(2000), 1000), (10, 1000));
+        assert_eq!(scan_range(None, Some(2000), 1000), (901, 1000));
+    }
+}
@@ -7,35 +7,41 @@
 use crate::{
     chain::{
         definitions::Invoice,
-        rpc::{block_hash, current_block_number, send_stuff},
+        rpc::{block_hash, current_block_number, next_nonce, partial_fee, send_stuff},
         tracker::ChainWatcher,
         utils::{
             construct_batch_transaction, construct_single_asset_transfer_call,
             construct_single_balance_transfer_call, AssetTransferConstructor,
-            BalanceTransferConstructor,
+            BalanceTransferConstructor, CallToFill,
         },
     },
-    database::{TransactionInfoDb, TransactionInfoDbInner, TxKind},
+    database::{TransactionInfoDb, TransactionInfoDbInner},
     definitions::{
-        api_v2::{Amount, TokenKind, TxStatus},
-        Balance,
+        api_v2::{Amount, CurrencyProperties, SplitShare, TokenKind, TxKind, TxStatus},
+        Balance, OverpaymentPolicy,
     },
     error::ChainError,
     signer::Signer,
     state::State,
 };
 use frame_metadata::v15::RuntimeMetadataV15;
-use jsonrpsee::ws_client::WsClientBuilder;
+use jsonrpsee::ws_client::{WsClient, WsClientBuilder};
+use primitive_types::U256;
 use substrate_constructor::fill_prepare::TypeContentToFill;
-use substrate_crypto_light::common::AsBase58;
+use substrate_crypto_light::common::{AccountId32, AsBase58};
+
+/// Amount that is allowed to be lost or left behind in a payment account during payout.
+///
+/// TODO: replace with multiple of existential
+pub const LOSS_TOLERANCE: u128 = 20000;
 
 /// Single function that should completely handle payout attmept. Just do not call anything else.
 ///
 /// TODO: make this an additional runner independent from chain monitors
-#[expect(clippy::too_many_lines)]
 pub async fn payout(
     rpc: String,
     order: Invoice,
+    overpayment_policy: OverpaymentPolicy,
     state: State,
     chain: ChainWatcher,
     signer: Signer,
@@ -45,141 +51,556 @@ pub async fn payout(
     // after some retries record a failure
     if let Ok(client) = WsClientBuilder::default().build(&rpc).await {
         let block = block_hash(&client, None).await?; // TODO should retry instead
-        let block_number = current_block_number(&client, &chain.metadata, &block).await?;
         let balance = order.balance(&client, &chain, &block).await?; // TODO same
-        let loss_tolerance = 20000; // TODO: replace with multiple of existential
-                                    // TODO: add upper limit for transactions that would require manual intervention
-                                    // just because it was found to be needed with non-crypto trade, who knows why?
+        let loss_tolerance = LOSS_TOLERANCE;
+        // TODO: add upper limit for transactions that would require manual intervention
+        // just because it was found to be needed with non-crypto trade, who knows why?
         let currency = chain
             .assets
             .get(&order.currency.currency)
             .ok_or_else(|| ChainError::InvalidCurrency(order.currency.currency.clone()))?;
-        let order_amount = Balance::parse(order.amount, order.currency.decimals);
+        let order_amount = order.amount;
+        let overpaid = balance.0 > order_amount.0.saturating_add(loss_tolerance);
+        let mut outgoing = Vec::new();
 
         // Payout operation logic
-        let (transactions, final_amount) = if balance.0.abs_diff(order_amount.0) <= loss_tolerance
+        let transactions = if balance.0.abs_diff(order_amount.0) <= loss_tolerance
         // modulus(balance-order.amount) <= loss_tolerance
         {
             tracing::info!("Regular withdrawal");
-            (
-                match currency.kind {
-                    TokenKind::Native => {
-                        let balance_transfer_constructor = BalanceTransferConstructor {
-                            amount: order_amount.0,
-                            to_account: &order.recipient,
-                            is_clearing: true,
-                        };
-                        vec![construct_single_balance_transfer_call(
-                            &chain.metadata,
-                            &balance_transfer_constructor,
-                        )?]
-                    }
-                    TokenKind::Asset => {
-                        let asset_transfer_constructor = AssetTransferConstructor {
-                            asset_id: currency.asset_id.ok_or(ChainError::AssetId)?,
-                            amount: order_amount.0 - loss_tolerance,
-                            to_account: &order.recipient,
-                        };
-                        vec![construct_single_asset_transfer_call(
-                            &chain.metadata,
-                            &asset_transfer_constructor,
-                        )?]
-                    }
-                },
-                order_amount.0,
-            )
+
+            let legs = payout_legs(&order.splits, order.recipient, order_amount, order_amount);
+
+            leg_calls(&chain.metadata, currency, &legs, true, &mut outgoing)?
+        } else if overpaid && overpayment_policy != OverpaymentPolicy::Forward {
+            let excess = Balance(balance.0.saturating_sub(order_amount.0));
+            let legs = payout_legs(&order.splits, order.recipient, order_amount, order_amount);
+            let mut transactions =
+                leg_calls(&chain.metadata, currency, &legs, false, &mut outgoing)?;
+
+            if overpayment_policy == OverpaymentPolicy::Refund && !order.payers.is_empty() {
+                tracing::info!("Overpayment, returning the excess to the payers");
+
+                let shares = refund_shares(excess, &order.payers);
+                let last = shares.len().saturating_sub(1);
+
+                // The last refund clears the account, so it also pays the fee of the batch.
+                for (index, (payer, share)) in shares.iter().enumerate() {
+                    transactions.push(if index == last {
+                        clearing_transfer_call(&chain.metadata, currency, *share, payer)?
+                    } else {
+                        transfer_call(&chain.metadata, currency, *share, payer)?
+                    });
+                    outgoing.push(Outgoing {
+                        recipient: *payer,
+                        amount: *share,
+                        kind: TxKind::Refund,
+                    });
+                }
+            } else {
+                // Also the case of the refund policy if payers aren't known.
+                tracing::warn!(
+                    "Order {} is overpaid by {}, holding the excess for a manual review",
+                    order.id,
+                    excess.format(currency.decimals)
+                );
+            }
+
+            transactions
         } else {
             tracing::info!("Overpayment or forced");
-            // We will transfer all the available balance
-            // TODO smarter handling and returns probably
-
-            (
-                match currency.kind {
-                    TokenKind::Native => {
-                        let balance_transfer_constructor = BalanceTransferConstructor {
-                            amount: balance.0,
-                            to_account: &order.recipient,
-                            is_clearing: true,
-                        };
-                        vec![construct_single_balance_transfer_call(
-                            &chain.metadata,
-                            &balance_transfer_constructor,
-                        )?]
-                    }
-                    TokenKind::Asset => {
-                        let asset_transfer_constructor = AssetTransferConstructor {
-                            asset_id: currency.asset_id.ok_or(ChainError::AssetId)?,
-                            amount: balance.0 - loss_tolerance,
-                            to_account: &order.recipient,
-                        };
-                        vec![construct_single_asset_transfer_call(
-                            &chain.metadata,
-                            &asset_transfer_constructor,
-                        )?]
-                    }
-                },
-                balance.0,
-            )
+
+            // We will transfer all the available balance. Splits of an underpaid order are taken
+            // from what it has received.
+            let legs = payout_legs(
+                &order.splits,
+                order.recipient,
+                Balance(order_amount.0.min(balance.0)),
+                balance,
+            );
+
+            leg_calls(&chain.metadata, currency, &legs, true, &mut outgoing)?
         };
 
-        let mut batch_transaction = construct_batch_transaction(
-            &chain.metadata,
-            chain.genesis_hash,
-            order.address,
-            &transactions,
-            block,
-            block_number,
-            0,
-            currency.asset_id,
-        )?;
-
-        let sign_this = batch_transaction
-            .sign_this()
-            .ok_or(ChainError::TransactionNotSignable(format!(
-                "{batch_transaction:?}"
-            )))?;
-
-        let signature = signer.sign(order.id.clone(), sign_this).await?;
-
-        if let TypeContentToFill::Variant(ref mut multisig) = batch_transaction.signature.content {
-            if let TypeContentToFill::ArrayU8(ref mut sr25519) =
-                multisig.selected.fields_to_fill[0].type_to_fill.content
-            {
-                sr25519.content = signature.0.to_vec();
+        let extrinsic = sign(&client, &order, &chain, &signer, &transactions).await?;
+
+        submit(&client, &order, &state, &signer, extrinsic, outgoing).await?;
+
+        state.order_withdrawn(order.id).await;
+        // TODO obvious
+    }
+    Ok(())
+}
+
+/// Return funds of the payment account to the payers in proportion to what they have paid, the
+/// whole balance if the amount isn't given.
+///
+/// Each payer gets a separate transaction, and its fee is deducted from their share.
+pub async fn refund(
+    rpc: String,
+    order: Invoice,
+    amount: Option<Balance>,
+    earlier: &[(AccountId32, Balance)],
+    state: State,
+    chain: ChainWatcher,
+    signer: Signer,
+) -> Result<(), ChainError> {
+    let client = WsClientBuilder::default().build(&rpc).await?;
+    let block = block_hash(&client, None).await?;
+    let balance = order.balance(&client, &chain, &block).await?;
+    let currency = chain
+        .assets
+        .get(&order.currency.currency)
+        .ok_or_else(|| ChainError::InvalidCurrency(order.currency.currency.clone()))?;
+    let total = amount.map_or(balance, |requested| Balance((*requested).min(*balance)));
+    let clearing = total == balance;
+    let mut transfers = Vec::new();
+
+    tracing::info!("Refund of order {}", order.id);
+
+    for (payer, share) in refund_owed(total, &order.payers, earlier) {
+        let fee = match currency.kind {
+            TokenKind::Native => {
+                let transfer = transfer_call(&chain.metadata, currency, share, &payer)?;
+                let estimate = sign(&client, &order, &chain, &signer, &[transfer]).await?;
+
+                partial_fee(&client, &estimate).await?
             }
+            // Asset transfers pay fees in the asset itself, which can't be estimated in
+            // advance.
+            TokenKind::Asset => Balance(LOSS_TOLERANCE),
+        };
+        let Some(refunded) = share.checked_sub(*fee).filter(|refunded| *refunded > 0) else {
+            tracing::warn!(
+                "Refund to {} for order {} doesn't cover its fee",
+                payer.to_base58_string(42),
+                order.id
+            );
+
+            continue;
+        };
+
+        transfers.push((payer, share, Balance(refunded)));
+    }
+
+    let Some(last) = transfers.len().checked_sub(1) else {
+        tracing::warn!("Nothing to refund for order {}", order.id);
+
+        return Err(ChainError::NothingToSend);
+    };
+
+    for (index, (payer, share, refunded)) in transfers.into_iter().enumerate() {
+        // The last transfer of a full refund clears the account, so nothing is left behind.
+        let transfer = if clearing && index == last {
+            clearing_transfer_call(&chain.metadata, currency, share, &payer)?
+        } else {
+            transfer_call(&chain.metadata, currency, refunded, &payer)?
+        };
+        let extrinsic = sign(&client, &order, &chain, &signer, &[transfer]).await?;
+
+        submit(
+            &client,
+            &order,
+            &state,
+            &signer,
+            extrinsic,
+            vec![Outgoing {
+                recipient: payer,
+                amount: refunded,
+                kind: TxKind::Refund,
+            }],
+        )
+        .await?;
+    }
+
+    // A partial refund leaves the order refundable, so the rest can be returned later.
+    if clearing {
+        state.order_refunded(order.id).await;
+    } else {
+        tracing::info!("Order {} is partially refunded", order.id);
+    }
+
+    Ok(())
+}
+
+/// What's left to return to each payer, so the total of all refunds, earlier ones included, is
+/// split in proportion to what they have paid. Payers that have already got their share are left
+/// out, which makes a repeated refund after a failure safe.
+fn refund_owed(
+    total: Balance,
+    payers: &[(AccountId32, Balance)],
+    earlier: &[(AccountId32, Balance)],
+) -> Vec<(AccountId32, Balance)> {
+    let returned = earlier.iter().fold(0, |returned: u128, (_, amount)| {
+        returned.saturating_add(**amount)
+    });
+    let mut left = *total;
+
+    refund_shares(Balance(total.saturating_add(returned)), payers)
+        .into_iter()
+        .filter_map(|(payer, share)| {
+            let got = earlier
+                .iter()
+                .find(|(refunded_payer, _)| *refunded_payer == payer)
+                .map_or(0, |(_, amount)| **amount);
+            let owed = share.saturating_sub(got).min(left);
+
+            left = left.saturating_sub(owed);
+
+            (owed > 0).then_some((payer, Balance(owed)))
+        })
+        .collect()
+}
+
+/// Split the refund between the payers in proportion to what they have paid. Rounding leftovers
+/// go to the last payer.
+fn refund_shares(total: Balance, payers: &[(AccountId32, Balance)]) -> Vec<(AccountId32, Balance)> {
+    let paid = payers
+        .iter()
+        .fold(0, |paid: u128, (_, amount)| paid.saturating_add(**amount));
+    let mut left = *total;
+
+    payers
+        .iter()
+        .enumerate()
+        .map(|(index, (payer, amount))| {
+            let share = if index.saturating_add(1) == payers.len() {
+                left
+            } else {
+                U256::from(*total)
+                    .saturating_mul(U256::from(**amount))
+                    .checked_div(U256::from(paid))
+                    .map_or(0, |share| share.low_u128())
+                    .min(left)
+            };
+
+            left = left.saturating_sub(share);
+
+            (*payer, Balance(share))
+        })
+        .collect()
+}
+
+/// Split the payout between the split recipients and the order recipient. Splits are taken from the
+/// base amount, each capped by what's left of it, and the rest of the total goes to the order
+/// recipient.
+fn payout_legs(
+    splits: &[(AccountId32, SplitShare)],
+    recipient: AccountId32,
+    base: Balance,
+    total: Balance,
+) -> Vec<(AccountId32, Balance)> {
+    let mut left = *base;
+    let mut legs: Vec<(AccountId32, Balance)> = splits
+        .iter()
+        .filter_map(|(split_recipient, share)| {
+            let leg = share.of(base).0.min(left);
+
+            left = left.saturating_sub(leg);
+
+            (leg > 0).then_some((*split_recipient, Balance(leg)))
+        })
+        .collect();
+    let split = base.saturating_sub(left);
+    let rest = total.saturating_sub(split);
+
+    if rest > 0 || legs.is_empty() {
+        legs.push((recipient, Balance(rest)));
+    }
+
+    legs
+}
+
+/// Transfers of the payout legs, each recorded as a withdrawal of its own. The last one clears the
+/// payment account if asked to, so it also pays the fee of the batch.
+fn leg_calls(
+    metadata: &RuntimeMetadataV15,
+    currency: &CurrencyProperties,
+    legs: &[(AccountId32, Balance)],
+    clearing: bool,
+    outgoing: &mut Vec<Outgoing>,
+) -> Result<Vec<CallToFill>, ChainError> {
+    let last = legs.len().saturating_sub(1);
+
+    legs.iter()
+        .enumerate()
+        .map(|(index, (recipient, amount))| {
+            outgoing.push(Outgoing {
+                recipient: *recipient,
+                amount: *amount,
+                kind: TxKind::Withdrawal,
+            });
+
+            if clearing && index == last {
+                clearing_transfer_call(metadata, currency, *amount, recipient)
+            } else {
+                transfer_call(metadata, currency, *amount, recipient)
+            }
+        })
+        .collect()
+}
+
+/// Transfer of an exact amount that keeps the payment account alive.
+fn transfer_call(
+    metadata: &RuntimeMetadataV15,
+    currency: &CurrencyProperties,
+    amount: Balance,
+    to_account: &AccountId32,
+) -> Result<CallToFill, ChainError> {
+    match currency.kind {
+        TokenKind::Native => {
+            let balance_transfer_constructor = BalanceTransferConstructor {
+                amount: amount.0,
+                to_account,
+                is_clearing: false,
+            };
+            construct_single_balance_transfer_call(metadata, &balance_transfer_constructor)
+        }
+        TokenKind::Asset => {
+            let asset_transfer_constructor = AssetTransferConstructor {
+                asset_id: currency.asset_id.ok_or(ChainError::AssetId)?,
+                amount: amount.0,
+                to_account,
+            };
+            construct_single_asset_transfer_call(metadata, &asset_transfer_constructor)
+        }
+    }
+}
+
+/// Transfer of all the available balance of the payment account.
+fn clearing_transfer_call(
+    metadata: &RuntimeMetadataV15,
+    currency: &CurrencyProperties,
+    balance: Balance,
+    to_account: &AccountId32,
+) -> Result<CallToFill, ChainError> {
+    match currency.kind {
+        TokenKind::Native => {
+            let balance_transfer_constructor = BalanceTransferConstructor {
+                amount: balance.0,
+                to_account,
+                is_clearing: true,
+            };
+            construct_single_balance_transfer_call(metadata, &balance_transfer_constructor)
+        }
+        TokenKind::Asset => {
+            let asset_transfer_constructor = AssetTransferConstructor {
+                asset_id: currency.asset_id.ok_or(ChainError::AssetId)?,
+                amount: balance.0.saturating_sub(LOSS_TOLERANCE),
+                to_account,
+            };
+            construct_single_asset_transfer_call(metadata, &asset_transfer_constructor)
+        }
+    }
+}
+
+/// Outgoing transfer of the payment account as it's recorded on the order
+struct Outgoing {
+    recipient: AccountId32,
+    amount: Balance,
+    kind: TxKind,
+}
+
+/// Sign a batch of transfers from the payment account.
+async fn sign(
+    client: &WsClient,
+    order: &Invoice,
+    chain: &ChainWatcher,
+    signer: &Signer,
+    transactions: &[CallToFill],
+) -> Result<String, ChainError> {
+    let block = block_hash(client, None).await?;
+    let block_number = current_block_number(client, &chain.metadata, &block).await?;
+    let nonce = next_nonce(client, &order.address).await?;
+    let asset_id = chain
+        .assets
+        .get(&order.currency.currency)
+        .and_then(|currency| currency.asset_id);
+
+    let mut batch_transaction = construct_batch_transaction(
+        &chain.metadata,
+        chain.genesis_hash.clone(),
+        order.address,
+        transactions,
+        block,
+        block_number,
+        nonce,
+        asset_id,
+    )?;
+
+    let sign_this = batch_transaction
+        .sign_this()
+        .ok_or(ChainError::TransactionNotSignable(format!(
+            "{batch_transaction:?}"
+        )))?;
+
+    let signature = signer
+        .sign(order.id.clone(), order.recipient, sign_this)
+        .await?;
+
+    if let TypeContentToFill::Variant(ref mut multisig) = batch_transaction.signature.content {
+        if let TypeContentToFill::ArrayU8(ref mut sr25519) =
+            multisig.selected.fields_to_fill[0].type_to_fill.content
+        {
+            sr25519.content = signature.0.to_vec();
         }
+    }
+
+    let extrinsic = batch_transaction
+        .send_this_signed::<(), RuntimeMetadataV15>(&chain.metadata)?
+        .ok_or(ChainError::NothingToSend)?;
+
+    Ok(const_hex::encode_prefixed(extrinsic))
+}
+
+/// Record the transfers of the signed transaction on the order and send it to the chain.
+async fn submit(
+    client: &WsClient,
+    order: &Invoice,
+    state: &State,
+    signer: &Signer,
+    extrinsic: String,
+    outgoing: Vec<Outgoing>,
+) -> Result<(), ChainError> {
+    let sender = signer.public(order.id.clone(), order.recipient, 42).await?;
 
-        let extrinsic = batch_transaction
-            .send_this_signed::<(), RuntimeMetadataV15>(&chain.metadata)?
-            .ok_or(ChainError::NothingToSend)?;
-        let encoded_extrinsic = const_hex::encode_prefixed(extrinsic);
+    record_outgoing(
+        order,
+        state,
+        &sender,
+        &extrinsic,
+        &outgoing,
+        TxStatus::Pending,
+    )
+    .await?;
 
+    if let Err(e) = send_stuff(client, &extrinsic).await {
+        // Nothing went out, so the transfers mustn't count as done.
+        record_outgoing(
+            order,
+            state,
+            &sender,
+            &extrinsic,
+            &outgoing,
+            TxStatus::Failed,
+        )
+        .await?;
+
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Record outgoing transfers of a transaction on the order with the given status.
+async fn record_outgoing(
+    order: &Invoice,
+    state: &State,
+    sender: &str,
+    extrinsic: &str,
+    outgoing: &[Outgoing],
+    status: TxStatus,
+) -> Result<(), ChainError> {
+    for transfer in outgoing {
         state
             .record_transaction(
                 TransactionInfoDb {
-                    transaction_bytes: encoded_extrinsic.clone(),
+                    transaction_bytes: extrinsic.to_owned(),
                     inner: TransactionInfoDbInner {
                         finalized_tx_timestamp: None,
                         finalized_tx: None,
-                        sender: signer.public(order.id.clone(), 42).await?,
-                        recipient: order.recipient.to_base58_string(42),
-                        amount: Amount::Exact(
-                            Balance(final_amount).format(order.currency.decimals),
-                        ),
-                        currency: order.currency,
-                        status: TxStatus::Pending,
-                        kind: TxKind::Withdrawal,
+                        sender: sender.to_owned(),
+                        recipient: transfer.recipient.to_base58_string(42),
+                        amount: Amount::Exact(transfer.amount),
+                        currency: order.currency.clone(),
+                        status: status.clone(),
+                        kind: transfer.kind,
                     },
                 },
                 order.id.clone(),
             )
             .await
             .map_err(|_| ChainError::TransactionNotSaved)?;
+    }
 
-        send_stuff(&client, &encoded_extrinsic).await?;
+    Ok(())
+}
 
-        state.order_withdrawn(order.id).await;
-        // TODO obvious
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refund_split() {
+        let payers = [
+            (AccountId32([1; 32]), Balance(100)),
+            (AccountId32([2; 32]), Balance(200)),
+        ];
+        let shares = refund_shares(Balance(1000), &payers);
+
+        assert_eq!(shares[0], (AccountId32([1; 32]), Balance(333)));
+        assert_eq!(shares[1], (AccountId32([2; 32]), Balance(667)));
+        assert_eq!(
+            refund_shares(Balance(1000), &payers[..1]),
+            [(AccountId32([1; 32]), Balance(1000))]
+        );
+    }
+
+    #[test]
+    fn refund_retry() {
+        let first = AccountId32([1; 32]);
+        let second = AccountId32([2; 32]);
+        let payers = [(first, Balance(100)), (second, Balance(200))];
+
+        assert_eq!(
+            refund_owed(Balance(150), &payers, &[]),
+            [(first, Balance(50)), (second, Balance(100))]
+        );
+        // The rest of a partial refund.
+        assert_eq!(
+            refund_owed(
+                Balance(150),
+                &payers,
+                &[(first, Balance(50)), (second, Balance(100))]
+            ),
+            [(first, Balance(50)), (second, Balance(100))]
+        );
+        // A retry after the first payer has got the refund minus the fee doesn't pay them again.
+        assert_eq!(
+            refund_owed(Balance(200), &payers, &[(first, Balance(99))]),
+            [(second, Balance(200))]
+        );
+        assert!(refund_owed(Balance(0), &payers, &[(first, Balance(100))]).is_empty());
+    }
+
+    #[test]
+    fn payout_split() {
+        let recipient = AccountId32([0; 32]);
+        let splits = [
+            (AccountId32([1; 32]), SplitShare::Percent(300)),
+            (AccountId32([2; 32]), SplitShare::Fixed(Balance(50))),
+        ];
+
+        assert_eq!(
+            payout_legs(&splits, recipient, Balance(1000), Balance(1000)),
+            [
+                (AccountId32([1; 32]), Balance(30)),
+                (AccountId32([2; 32]), Balance(50)),
+                (recipient, Balance(920)),
+            ]
+        );
+        // The excess of an overpaid order goes to the recipient.
+        assert_eq!(
+            payout_legs(&splits, recipient, Balance(1000), Balance(1500))[2],
+            (recipient, Balance(1420))
+        );
+        // Splits can't take more than the base amount.
+        assert_eq!(
+            payout_legs(&splits, recipient, Balance(40), Balance(40)),
+            [
+                (AccountId32([1; 32]), Balance(1)),
+                (AccountId32([2; 32]), Balance(39)),
+            ]
+        );
     }
-    Ok(())
 }
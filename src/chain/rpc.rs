@@ -29,7 +29,8 @@ use scale_info::{form::PortableForm, PortableRegistry, TypeDef, TypeDefPrimitive
 use serde::{de, Deserialize, Deserializer};
 use serde_json::{Number, Value};
 use std::{collections::HashMap, fmt::Debug};
-use substrate_crypto_light::common::AccountId32;
+use substrate_constructor::storage_query::FinalizedStorageQuery;
+use substrate_crypto_light::common::{AccountId32, AsBase58};
 use substrate_parser::{
     cards::{
         Call, Event, ExtendedData, FieldData, PalletSpecificData, ParsedData, Sequence, VariantData,
@@ -78,6 +79,21 @@ pub async fn subscribe_blocks(client: &WsClient) -> Result<Subscription<BlockHea
         .await?)
 }
 
+/// Subscribe to changes of the storage values under the keys. The first notification holds their
+/// current values.
+pub async fn subscribe_storage(
+    client: &WsClient,
+    keys: &[String],
+) -> Result<Subscription<StorageChangeSet>, ChainError> {
+    Ok(client
+        .subscribe(
+            "state_subscribeStorage",
+            rpc_params![keys],
+            "state_unsubscribeStorage",
+        )
+        .await?)
+}
+
 pub async fn get_value_from_storage(
     client: &WsClient,
     whole_key: &str,
@@ -92,6 +108,23 @@ pub async fn get_value_from_storage(
     Ok(value)
 }
 
+/// Fetch the storage values under the keys at the block with a single request. Keys without a
+/// value are mapped to `None`.
+pub async fn values_from_storage(
+    client: &WsClient,
+    keys: &[String],
+    block: &BlockHash,
+) -> Result<HashMap<String, Option<String>>, ChainError> {
+    let change_sets: Vec<StorageChangeSet> = client
+        .request("state_queryStorageAt", rpc_params![keys, block.to_string()])
+        .await?;
+
+    Ok(change_sets
+        .into_iter()
+        .flat_map(|set| set.changes)
+        .collect())
+}
+
 pub async fn get_keys_from_storage(
     client: &WsClient,
     prefix: &str,
@@ -181,6 +214,28 @@ pub async fn block_hash(
     }
 }
 
+/// fetch hash of the last finalized block
+pub async fn finalized_block_hash(client: &WsClient) -> Result<BlockHash, ChainError> {
+    let block_hash_request: Value = client
+        .request("chain_getFinalizedHead", rpc_params![])
+        .await
+        .map_err(ChainError::Client)?;
+    match block_hash_request {
+        Value::String(x) => BlockHash::from_str(&x),
+        _ => Err(ChainError::BlockHashFormat),
+    }
+}
+
+/// fetch number of the best block
+pub async fn best_block_number(client: &WsClient) -> Result<BlockNumber, ChainError> {
+    let header: BlockHead = client
+        .request("chain_getHeader", rpc_params![])
+        .await
+        .map_err(ChainError::Client)?;
+
+    Ok(header.number)
+}
+
 /// fetch metadata at known block
 pub async fn metadata(
     client: &WsClient,
@@ -272,6 +327,12 @@ fn deserialize_block_number<'d, D: Deserializer<'d>>(d: D) -> Result<BlockNumber
         .map_err(|_| de::Error::custom("failed to convert `U256` to a block number"))
 }
 
+/// Storage changes in a block, each as the key with the new value, if it isn't removed
+#[derive(Deserialize, Debug)]
+pub struct StorageChangeSet {
+    pub changes: Vec<(String, Option<String>)>,
+}
+
 #[derive(Deserialize)]
 pub struct BlockDetails {
     block: Block,
@@ -365,21 +426,34 @@ pub async fn assets_set_at_block(
                                 }
                             }?;
                             let mut verified_sufficient = false;
+                            let mut min_balance = None;
                             if let ParsedData::Composite(fields) = storage_entry.value.data {
                                 for field_data in fields.iter() {
                                     if let Some(field_name) = &field_data.field_name {
-                                        if field_name == "is_sufficient" {
-                                            if let ParsedData::PrimitiveBool(is_it) =
-                                                field_data.data.data
-                                            {
-                                                verified_sufficient = is_it;
+                                        match field_name.as_str() {
+                                            "is_sufficient" => {
+                                                if let ParsedData::PrimitiveBool(is_it) =
+                                                    field_data.data.data
+                                                {
+                                                    verified_sufficient = is_it;
+                                                }
                                             }
-                                            break;
+                                            "min_balance" => {
+                                                if let ParsedData::PrimitiveU128 { value, .. } =
+                                                    field_data.data.data
+                                                {
+                                                    min_balance = Some(Balance(value));
+                                                }
+                                            }
+                                            _ => {}
                                         }
                                     }
                                 }
                             }
                             if verified_sufficient {
+                                let existential_deposit =
+                                    min_balance.ok_or(ChainError::AssetMinBalanceNotFound)?;
+
                                 match &assets_metadata_storage_metadata.ty {
                                     StorageEntryType::Plain(_) => {
                                         return Err(ChainError::AssetMetadataPlain)
@@ -535,6 +609,7 @@ pub async fn assets_set_at_block(
                                                                             .to_string(),
                                                                         asset_id: Some(asset_id),
                                                                         ss58: specs.base58prefix,
+                                                                        existential_deposit,
                                                                     },
                                                                 );
                                                             }
@@ -573,7 +648,17 @@ pub async fn asset_balance_at_account(
     let query = asset_balance_query(metadata_v15, account_id, asset_id)?;
 
     let value_fetch = get_value_from_storage(client, &query.key, block).await?;
-    if let Value::String(ref string_value) = value_fetch {
+
+    asset_balance_from_value(&value_fetch, &query, metadata_v15)
+}
+
+/// Decode the asset balance from the storage value fetched with the query.
+pub fn asset_balance_from_value(
+    value_fetch: &Value,
+    query: &FinalizedStorageQuery,
+    metadata_v15: &RuntimeMetadataV15,
+) -> Result<Balance, ChainError> {
+    if let Value::String(string_value) = value_fetch {
         let value_data = unhex(string_value, NotHexError::StorageValue)?;
         let value = decode_all_as_type::<&[u8], (), RuntimeMetadataV15>(
             &query.value_ty,
@@ -596,7 +681,7 @@ pub async fn asset_balance_at_account(
             Err(ChainError::AssetBalanceFormat)
         }
     } else {
-        Err(ChainError::StorageValueFormat(value_fetch))
+        Err(ChainError::StorageValueFormat(value_fetch.clone()))
     }
 }
 
@@ -609,7 +694,17 @@ pub async fn system_balance_at_account(
     let query = system_balance_query(metadata_v15, account_id)?;
 
     let value_fetch = get_value_from_storage(client, &query.key, block).await?;
-    if let Value::String(ref string_value) = value_fetch {
+
+    system_balance_from_value(&value_fetch, &query, metadata_v15)
+}
+
+/// Decode the free balance from the `System` account storage value fetched with the query.
+pub fn system_balance_from_value(
+    value_fetch: &Value,
+    query: &FinalizedStorageQuery,
+    metadata_v15: &RuntimeMetadataV15,
+) -> Result<Balance, ChainError> {
+    if let Value::String(string_value) = value_fetch {
         let value_data = unhex(string_value, NotHexError::StorageValue)?;
         let value = decode_all_as_type::<&[u8], (), RuntimeMetadataV15>(
             &query.value_ty,
@@ -868,15 +963,38 @@ pub async fn current_block_number(
     }
 }
 
-pub async fn get_nonce(
-    client: &WsClient,
-    account_id: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_params = rpc_params![account_id];
-    println!("{rpc_params:?}");
-    let nonce: Value = client.request("account_nextIndex", rpc_params).await?;
-    println!("{nonce:?}");
-    Ok(())
+/// fetch the index of the next transaction of the account, transaction pool included
+pub async fn next_nonce(client: &WsClient, account: &AccountId32) -> Result<u32, ChainError> {
+    let nonce: Value = client
+        .request(
+            "account_nextIndex",
+            rpc_params![account.to_base58_string(42)],
+        )
+        .await
+        .map_err(ChainError::Client)?;
+
+    nonce
+        .as_u64()
+        .and_then(|index| u32::try_from(index).ok())
+        .ok_or(ChainError::NonceFormat)
+}
+
+/// estimate the fee of a signed extrinsic in the native token
+pub async fn partial_fee(client: &WsClient, extrinsic: &str) -> Result<Balance, ChainError> {
+    let info: Value = client
+        .request("payment_queryInfo", rpc_params![extrinsic])
+        .await
+        .map_err(ChainError::Client)?;
+
+    // Older nodes return the fee as a number, newer ones as a string.
+    match info.get("partialFee") {
+        Some(Value::String(fee)) => fee.parse().map(Balance).map_err(|_| ChainError::FeeFormat),
+        Some(Value::Number(fee)) => fee
+            .as_u64()
+            .map(|units| Balance(units.into()))
+            .ok_or(ChainError::FeeFormat),
+        _ => Err(ChainError::FeeFormat),
+    }
 }
 
 pub async fn send_stuff(client: &WsClient, data: &str) -> Result<Value, ChainError> {
@@ -2,20 +2,25 @@
 
 use crate::{
     chain::{
-        definitions::{BlockHash, ChainTrackerRequest, Invoice},
-        payout::payout,
+        definitions::{
+            BalanceRequest, BlockHash, ChainTrackerRequest, InvestigateRequest, Invoice,
+            RefundRequest,
+        },
+        endpoints::Endpoints,
+        investigate::investigate,
+        payout::{payout, refund},
         rpc::{
-            assets_set_at_block, block_hash, genesis_hash, metadata, next_block, next_block_number,
-            runtime_version_identifier, specs, subscribe_blocks, transfer_events,
+            assets_set_at_block, block_hash, finalized_block_hash, genesis_hash, metadata,
+            next_block, next_block_number, runtime_version_identifier, specs, subscribe_blocks,
+            subscribe_storage, transfer_events, values_from_storage,
         },
-        utils::parse_transfer_event,
+        utils::{existential_deposit, transfer_transactions},
     },
-    database::{FinalizedTxDb, TransactionInfoDb, TransactionInfoDbInner, TxKind},
     definitions::{
-        api_v2::{Amount, CurrencyProperties, Health, RpcInfo, TokenKind, TxStatus},
-        Chain,
+        api_v2::{BlockNumber, ChangeOrigin, CurrencyProperties, RpcInfo, TokenKind},
+        Balance, Chain, OverpaymentPolicy,
     },
-    error::{ChainError, Error},
+    error::ChainError,
     signer::Signer,
     state::State,
     utils::task_tracker::TaskTracker,
@@ -25,17 +30,24 @@ use jsonrpsee::ws_client::{WsClient, WsClientBuilder};
 use serde_json::Value;
 use std::{
     collections::{HashMap, HashSet},
-    time::SystemTime,
+    future::pending,
+    sync::Arc,
+    time::{Instant, SystemTime},
 };
-use substrate_crypto_light::common::AsBase58;
 use substrate_parser::{AsMetadata, ShortSpecs};
-use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use tokio::{
-    sync::mpsc,
-    time::{timeout, Duration},
+    sync::{mpsc, watch},
+    time::{sleep, sleep_until, timeout, Duration},
 };
 use tokio_util::sync::CancellationToken;
 
+/// Scan all watched accounts at least once in this many blocks, besides the ones with storage
+/// changes.
+const FULL_CHECK_BLOCKS: BlockNumber = 100;
+/// Delay of the storage subscription after the watched keys change, so a burst of watched and
+/// unwatched accounts ends in a single subscription.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
 #[allow(clippy::too_many_lines)]
 pub fn start_chain_watch(
     chain: Chain,
@@ -52,26 +64,51 @@ pub fn start_chain_watch(
         .spawn(format!("Chain {} watcher", chain.name.clone()), async move {
             let watchdog = 120000;
             let mut watched_accounts = HashMap::new();
+            // Orders whose payments are being confirmed with the quorum of endpoints.
+            let mut confirming = HashSet::new();
+            // Orders whose payments the quorum hasn't confirmed yet, to be confirmed again with
+            // each block.
+            let mut unconfirmed = HashSet::new();
             let mut shutdown = false;
-            // TODO: random pick instead
-            for endpoint in chain.endpoints.clone().iter().cycle() {
+            let mut last_block = 0;
+            let endpoints = Endpoints::new(
+                chain.name.clone(),
+                &chain.endpoints,
+                chain.quorum,
+                rpc_update_tx,
+            );
+
+            endpoints.start_probes(&task_tracker, cancellation_token.clone());
+
+            loop {
                 // not restarting chain if shutdown is in progress
                 if shutdown || cancellation_token.is_cancelled() {
                     break;
                 }
 
-                let _ = rpc_update_tx.send(RpcInfo {
-                    chain_name: chain.name.clone(),
-                    rpc_url: endpoint.clone(),
-                    status: Health::Degraded,
-                }).await;
+                let endpoint = &match endpoints.pick() {
+                    Ok(endpoint) => endpoint,
+                    Err(cooldown) => {
+                        tracing::warn!(
+                            "All RPC servers of chain {} are failing, retrying in {cooldown:?}...",
+                            chain.name
+                        );
+
+                        tokio::select! {
+                            () = cancellation_token.cancelled() => break,
+                            () = sleep(cooldown) => continue,
+                        }
+                    }
+                };
+
+                endpoints.connecting(endpoint).await;
+
+                let connection_start = Instant::now();
 
-                if let Ok(client) = WsClientBuilder::default().build(endpoint).await {
-                    let _ = rpc_update_tx.send(RpcInfo {
-                        chain_name: chain.name.clone(),
-                        rpc_url: endpoint.clone(),
-                        status: Health::Ok,
-                    }).await;
+                if let Ok(connected_client) = WsClientBuilder::default().build(endpoint).await {
+                    let client = Arc::new(connected_client);
+
+                    endpoints.connected(endpoint, connection_start.elapsed()).await;
 
                     // prepare chain
                     let watcher = match ChainWatcher::prepare_chain(
@@ -79,6 +116,7 @@ pub fn start_chain_watch(
                         chain.clone(),
                         &mut watched_accounts,
                         endpoint,
+                        &endpoints,
                         chain_tx.clone(),
                         state.interface(),
                         task_tracker.clone(),
@@ -92,17 +130,35 @@ pub fn start_chain_watch(
                                 chain.name,
                                 e
                             );
+                            endpoints.failure(endpoint).await;
                             continue;
                         }
                     };
 
+                    let (keys_tx, keys_rx) = watch::channel(Vec::new());
+                    let mut storage_keys = watch_storage_keys(&watcher, &watched_accounts, &keys_tx);
+                    let mut last_full_check = None;
+
+                    start_storage_watch(client.clone(), keys_rx, chain_tx.clone(), &task_tracker, endpoint);
 
                     // fulfill requests
-                    while let Ok(Some(request)) =
-                        timeout(Duration::from_millis(watchdog), chain_rx.recv()).await
-                    {
+                    loop {
+                        let request = match timeout(Duration::from_millis(watchdog), chain_rx.recv()).await {
+                            Ok(Some(request)) => request,
+                            Ok(None) => break,
+                            Err(_) => {
+                                tracing::info!(
+                                    "No blocks from chain {} in {watchdog} ms; Switching RPC server...",
+                                    chain.name
+                                );
+                                endpoints.failure(endpoint).await;
+                                break;
+                            }
+                        };
+
                         match request {
                             ChainTrackerRequest::NewBlock(block_number) => {
+                                let request_start = Instant::now();
                                 // TODO: hide this under rpc module
                                 let block = match block_hash(&client, Some(block_number)).await {
                                     Ok(a) => a,
@@ -112,10 +168,13 @@ pub fn start_chain_watch(
                                             chain.name,
                                             e
                                         );
+                                        endpoints.failure(endpoint).await;
                                         break;
                                     },
                                 };
 
+                                endpoints.responded(endpoint, request_start.elapsed());
+
                                 tracing::debug!("Block hash {} from {}", block.to_string(), chain.name);
 
                                 if watcher.version != runtime_version_identifier(&client, &block).await? {
@@ -128,6 +187,26 @@ pub fn start_chain_watch(
                                 let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64;
 
                                 let mut id_remove_list = Vec::new();
+                                let full_check = last_full_check.is_none_or(|last: BlockNumber| {
+                                    block_number.saturating_sub(last) >= FULL_CHECK_BLOCKS
+                                });
+
+                                let (balances, complete) = batch_balances(
+                                    &client,
+                                    &watcher,
+                                    &block,
+                                    chain.query_batch_size,
+                                    &storage_keys,
+                                    &watched_accounts,
+                                    |invoice| full_check || invoice.pending_change.is_some() || unconfirmed.contains(&invoice.id) || (invoice.death.0 <= now && !invoice.overdue),
+                                )
+                                .await;
+
+                                // A full check that missed some accounts is made again with the
+                                // next block.
+                                if full_check && complete {
+                                    last_full_check = Some(block_number);
+                                }
 
                                 match transfer_events(
                                     &client,
@@ -139,60 +218,8 @@ pub fn start_chain_watch(
                                         tracing::debug!("Got a block with timestamp {timestamp:?} & events: {events:?}");
 
                                         for (id, invoice) in &watched_accounts {
-                                            for (extrinsic_option, event) in &events {
-                                                if let Some((tx_kind, another_account, transfer_amount)) = parse_transfer_event(&invoice.address, &event.0.fields) {
-                                                    tracing::debug!("Found {tx_kind:?} from/to {another_account:?} with {transfer_amount:?} token(s).");
-
-                                                    let Some((position_in_block, extrinsic)) = extrinsic_option else {
-                                                        return Err(Error::from(ChainError::TransferEventNoExtrinsic));
-                                                    };
-
-                                                    let finalized_tx_timestamp = (OffsetDateTime::UNIX_EPOCH + Duration::from_millis(timestamp.0))
-                                                        .format(&Rfc3339).unwrap().into();
-                                                    let finalized_tx = FinalizedTxDb {
-                                                            block_number,
-                                                            position_in_block: *position_in_block
-                                                        }.into();
-                                                    let amount = Amount::Exact(transfer_amount.format(invoice.currency.decimals));
-                                                    let status = TxStatus::Finalized;
-                                                    let currency = invoice.currency.clone();
-                                                    let transaction_bytes = const_hex::encode_prefixed(extrinsic);
-
-                                                    match tx_kind {
-                                                        kind @ TxKind::Payment => {
-                                                            state.record_transaction(
-                                                                TransactionInfoDb {
-                                                                    transaction_bytes,
-                                                                    inner: TransactionInfoDbInner {
-                                                                        finalized_tx,
-                                                                        finalized_tx_timestamp,
-                                                                        sender: another_account.to_base58_string(42),
-                                                                        recipient: invoice.address.to_base58_string(42),
-                                                                        amount,
-                                                                        currency,
-                                                                        status,
-                                                                        kind,
-                                                                    } },
-                                                                    id.clone()).await?;
-                                                        }
-                                                        kind @ TxKind::Withdrawal => {
-                                                            state.record_transaction(
-                                                                TransactionInfoDb {
-                                                                    transaction_bytes,
-                                                                    inner: TransactionInfoDbInner {
-                                                                        finalized_tx,
-                                                                        finalized_tx_timestamp,
-                                                                        sender: invoice.address.to_base58_string(42),
-                                                                        recipient: another_account.to_base58_string(42),
-                                                                        amount,
-                                                                        currency,
-                                                                        status,
-                                                                        kind,
-                                                                    } },
-                                                                    id.clone()).await?;
-                                                        }
-                                                    }
-                                                }
+                                            for transaction in transfer_transactions(invoice, block_number, timestamp, &events)? {
+                                                state.record_transaction(transaction, id.clone()).await?;
                                             }
                                         }
                                     }
@@ -207,33 +234,79 @@ pub fn start_chain_watch(
                                 // ways to transfer funds without emitting a transfer event (one
                                 // notable example is through asset exchange procedure directed
                                 // straight into invoice account), and probably even without any
-                                // reliably expected event (through XCM). Thus we just scan all
-                                // accounts, every time. Please submit a PR or an issue if you
-                                // figure out a reliable optimization for this.
-                                for (id, invoice) in &watched_accounts {
-                                    match invoice.check(&client, &watcher, &block).await {
-                                        Ok(true) => {
-                                            state.order_paid(id.clone()).await;
-                                        },
-                                        Err(e) => {
-                                            tracing::warn!("account fetch error: {0:?}", e);
+                                // reliably expected event (through XCM). Instead, the balance
+                                // storage of all accounts is subscribed to, and only the accounts
+                                // with changes are checked at finalized blocks. All accounts are
+                                // still scanned every `FULL_CHECK_BLOCKS` in case a notification
+                                // is lost.
+                                for (id, invoice) in &mut watched_accounts {
+                                    let checked = full_check || invoice.pending_change.is_some() || unconfirmed.contains(id);
+
+                                    if checked {
+                                        if let Some(&balance) = balances.get(id) {
+                                            settle_change(invoice, balance, block_number);
+
+                                            if balance > invoice.received {
+                                                invoice.received = balance;
+                                                state.payment_received(id.clone(), balance, block_number).await;
+                                            }
+
+                                            if balance >= invoice.amount {
+                                                if !endpoints.needs_confirmation() {
+                                                    state.order_paid(id.clone(), ChangeOrigin::tracker(Some(block_number))).await;
+                                                } else if confirming.insert(id.clone()) {
+                                                    endpoints.start_confirmation(client.clone(), watcher.clone(), invoice.clone(), block_number, chain_tx.clone(), &task_tracker);
+                                                }
+                                            } else {
+                                                unconfirmed.remove(id);
+                                            }
                                         }
-                                        _ => {}
                                     }
 
-                                    if invoice.death.0 <= now {
+                                    // The expiry of an overdue order has been handed off already,
+                                    // so it's only looked at again once its balance changes.
+                                    if invoice.death.0 <= now && (checked || !invoice.overdue) {
                                         match state.is_order_paid(id.clone()).await {
                                             Ok(paid_db) => {
                                                 if !paid_db {
-                                                    match invoice.check(&client, &watcher, &block).await {
-                                                        Ok(paid) => {
-                                                            if paid {
-                                                                state.order_paid(id.clone()).await;
-                                                            }
+                                                    // An overdue order stays watched until it's paid in full or
+                                                    // refunded.
+                                                    if invoice.overdue {
+                                                        if let Ok(true) = state.order_expired(id.clone(), block_number).await {
+                                                            continue;
                                                         }
-                                                        Err(e) => {
-                                                            tracing::warn!("account fetch error: {0:?}", e);
+                                                    } else if let Some(&balance) = balances.get(id) {
+                                                        if balance >= invoice.amount {
+                                                            // The order stays watched while the quorum confirms the
+                                                            // payment, and is removed with a later block once it's
+                                                            // marked as paid.
+                                                            if endpoints.needs_confirmation() {
+                                                                if confirming.insert(id.clone()) {
+                                                                    endpoints.start_confirmation(client.clone(), watcher.clone(), invoice.clone(), block_number, chain_tx.clone(), &task_tracker);
+                                                                }
+
+                                                                continue;
+                                                            }
+
+                                                            state.order_paid(id.clone(), ChangeOrigin::tracker(Some(block_number))).await;
+                                                        } else {
+                                                            match state.order_expired(id.clone(), block_number).await {
+                                                                Ok(true) => {
+                                                                    invoice.overdue = true;
+
+                                                                    continue;
+                                                                }
+                                                                Ok(false) => {}
+                                                                Err(e) => {
+                                                                    tracing::warn!("order expiry error: {e:?}");
+                                                                }
+                                                            }
                                                         }
+                                                    } else {
+                                                        // The order stays watched until its balance is known.
+                                                        tracing::warn!("account fetch error: no balance of order {id}");
+
+                                                        continue;
                                                     }
                                                 }
 
@@ -247,14 +320,80 @@ pub fn start_chain_watch(
                                     }
                                 }
 
+                                let mut keys_changed = false;
+
                                 for id in id_remove_list {
-                                    watched_accounts.remove(&id);
-                                };
+                                    unconfirmed.remove(&id);
+
+                                    if let Some(invoice) = watched_accounts.remove(&id) {
+                                        keys_changed |= unwatch_storage_key(&watcher, &mut storage_keys, &invoice);
+                                    }
+                                }
+
+                                if keys_changed {
+                                    publish_storage_keys(&storage_keys, &keys_tx);
+                                }
+
+                                last_block = block_number;
 
                                 tracing::debug!("Block {} from {} processed successfully", block.to_string(), chain.name);
                             }
+                            ChainTrackerRequest::StorageChanges(changes) => {
+                                for (key, change) in changes {
+                                    // A removed account holds nothing to pay the order with.
+                                    let Some(value) = change else {
+                                        continue;
+                                    };
+                                    let Some(invoice) = storage_keys.get(&key).and_then(|id| watched_accounts.get_mut(id)) else {
+                                        continue;
+                                    };
+
+                                    match invoice.balance_from_storage(&watcher, value) {
+                                        Ok(balance) => note_change(invoice, balance, last_block),
+                                        Err(e) => {
+                                            tracing::warn!("storage change decoding error: {e:?}");
+                                        }
+                                    }
+                                }
+                            }
+                            ChainTrackerRequest::PaymentConfirmation { id, block_number, confirmed } => {
+                                confirming.remove(&id);
+
+                                if confirmed {
+                                    unconfirmed.remove(&id);
+
+                                    if watched_accounts.contains_key(&id) {
+                                        state.order_paid(id, ChangeOrigin::tracker(Some(block_number))).await;
+                                    }
+                                } else if watched_accounts.contains_key(&id) {
+                                    // Check again with the next block until the quorum confirms it.
+                                    unconfirmed.insert(id);
+                                }
+                            }
                             ChainTrackerRequest::WatchAccount(request) => {
-                                watched_accounts.insert(request.id.clone(), Invoice::from_request(request));
+                                let id = request.id.clone();
+                                let invoice = Invoice::from_request(request);
+                                // The account of a modified order may have moved to another
+                                // storage key.
+                                let replaced = watched_accounts
+                                    .get(&id)
+                                    .is_some_and(|previous| unwatch_storage_key(&watcher, &mut storage_keys, previous));
+                                let added = watch_storage_key(&watcher, &mut storage_keys, &id, &invoice);
+
+                                watched_accounts.insert(id, invoice);
+
+                                if replaced || added {
+                                    publish_storage_keys(&storage_keys, &keys_tx);
+                                }
+                            }
+                            ChainTrackerRequest::UnwatchAccount(id) => {
+                                unconfirmed.remove(&id);
+
+                                if let Some(invoice) = watched_accounts.remove(&id) {
+                                    if unwatch_storage_key(&watcher, &mut storage_keys, &invoice) {
+                                        publish_storage_keys(&storage_keys, &keys_tx);
+                                    }
+                                }
                             }
                             ChainTrackerRequest::Reap(request) => {
                                 let id = request.id.clone();
@@ -262,9 +401,19 @@ pub fn start_chain_watch(
                                 let reap_state_handle = state.interface();
                                 let watcher_for_reaper = watcher.clone();
                                 let signer_for_reaper = signer.interface();
+                                let overpayment_policy = watcher
+                                    .overpayment_policies
+                                    .get(&request.currency.currency)
+                                    .copied()
+                                    .unwrap_or_default();
 
                                 task_tracker.clone().spawn(format!("Initiate payout for order {}", id.clone()), async move {
-                                    drop(payout(rpc, Invoice::from_request(request), reap_state_handle, watcher_for_reaper, signer_for_reaper).await);
+                                    let failure_state_handle = reap_state_handle.interface();
+
+                                    if let Err(e) = payout(rpc, Invoice::from_request(request), overpayment_policy, reap_state_handle, watcher_for_reaper, signer_for_reaper).await {
+                                        tracing::error!("Payout for order {id} failed: {e:?}");
+                                        failure_state_handle.payout_failed(id.clone()).await;
+                                    }
                                     Ok(format!("Payout attempt for order {id} terminated"))
                                 });
                             }
@@ -275,10 +424,63 @@ pub fn start_chain_watch(
                                 let watcher_for_reaper = watcher.clone();
                                 let signer_for_reaper = signer.interface();
                                 task_tracker.clone().spawn(format!("Initiate forced payout for order {}", id.clone()), async move {
-                                    drop(payout(rpc, Invoice::from_request(request), reap_state_handle, watcher_for_reaper, signer_for_reaper).await);
+                                    let failure_state_handle = reap_state_handle.interface();
+
+                                    // Forced payout sends everything to the recipient, including
+                                    // the held excess of overpaid orders.
+                                    if let Err(e) = payout(rpc, Invoice::from_request(request), OverpaymentPolicy::Forward, reap_state_handle, watcher_for_reaper, signer_for_reaper).await {
+                                        tracing::error!("Forced payout for order {id} failed: {e:?}");
+                                        failure_state_handle.payout_failed(id.clone()).await;
+                                    }
                                     Ok(format!("Forced payout attempt for order {id} terminated"))
                                 });
                             }
+                            ChainTrackerRequest::Refund(RefundRequest { invoice, amount, refunded, res }) => {
+                                let id = invoice.id.clone();
+                                let rpc = endpoint.clone();
+                                let refund_state_handle = state.interface();
+                                let watcher_for_refund = watcher.clone();
+                                let signer_for_refund = signer.interface();
+
+                                task_tracker.clone().spawn(format!("Initiate refund for order {}", id.clone()), async move {
+                                    // The caller learns whether the refund has been submitted, and
+                                    // can retry it after a failure.
+                                    let submitted = refund(rpc, invoice, amount, &refunded, refund_state_handle, watcher_for_refund, signer_for_refund).await;
+
+                                    if let Err(e) = &submitted {
+                                        tracing::error!("Refund for order {id} failed: {e:?}");
+                                    }
+
+                                    drop(res.send(submitted));
+                                    Ok(format!("Refund attempt for order {id} terminated"))
+                                });
+                            }
+                            ChainTrackerRequest::Balance(BalanceRequest { invoice, res }) => {
+                                let id = invoice.id.clone();
+                                let client_for_balance = client.clone();
+                                let watcher_for_balance = watcher.clone();
+
+                                // Blocks keep coming while the balance is being fetched.
+                                task_tracker.clone().spawn(format!("Fetch balance of order {id}"), async move {
+                                    let balance = match finalized_block_hash(&client_for_balance).await {
+                                        Ok(block) => invoice.balance(&client_for_balance, &watcher_for_balance, &block).await,
+                                        Err(e) => Err(e),
+                                    };
+
+                                    drop(res.send(balance));
+                                    Ok(format!("Balance of order {id} fetched"))
+                                });
+                            }
+                            ChainTrackerRequest::Investigate(InvestigateRequest { invoice, from, to, res }) => {
+                                let id = invoice.id.clone();
+                                let rpc = endpoint.clone();
+                                let watcher_for_investigation = watcher.clone();
+
+                                task_tracker.clone().spawn(format!("Investigate order {id}"), async move {
+                                    drop(res.send(investigate(rpc, invoice, from, to, watcher_for_investigation).await));
+                                    Ok(format!("Investigation of order {id} finished"))
+                                });
+                            }
                             ChainTrackerRequest::Shutdown(res) => {
                                 shutdown = true;
                                 let _ = res.send(());
@@ -287,33 +489,253 @@ pub fn start_chain_watch(
                         }
                     }
                 } else {
-                    drop(rpc_update_tx.send(RpcInfo {
-                        chain_name: chain.name.clone(),
-                        rpc_url: endpoint.clone(),
-                        status: Health::Critical,
-                    }).await);
+                    endpoints.failure(endpoint).await;
                 }
             }
             Ok(format!("Chain {} monitor shut down", chain.name))
         });
 }
 
+/// Balances of the watched accounts that need a check, by the order IDs, along with whether all
+/// of them were fetched. The balance storage is fetched in batches of keys and decoded at once, so
+/// the number of requests per block barely depends on the number of accounts. A failed batch only
+/// leaves out the accounts in it.
+async fn batch_balances(
+    client: &WsClient,
+    watcher: &ChainWatcher,
+    block: &BlockHash,
+    batch_size: usize,
+    storage_keys: &HashMap<String, String>,
+    watched_accounts: &HashMap<String, Invoice>,
+    needs_check: impl Fn(&Invoice) -> bool,
+) -> (HashMap<String, Balance>, bool) {
+    let keys: Vec<String> = storage_keys
+        .iter()
+        .filter(|(_, id)| watched_accounts.get(*id).is_some_and(&needs_check))
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut values = HashMap::with_capacity(keys.len());
+    let mut complete = true;
+
+    // The config makes sure the batch size isn't 0.
+    for batch in keys.chunks(batch_size) {
+        let fetched = values_from_storage(client, batch, block).await;
+
+        match fetched {
+            Ok(batch_values) => values.extend(batch_values),
+            Err(e) => {
+                tracing::warn!("account fetch error: {e:?}");
+
+                complete = false;
+            }
+        }
+    }
+
+    let balances = balances_by_order(values, storage_keys, watched_accounts, |invoice, value| {
+        invoice.balance_from_storage(watcher, value)
+    });
+
+    (balances, complete)
+}
+
+/// Balances decoded from the storage values, by the order IDs their keys belong to.
+fn balances_by_order(
+    values: HashMap<String, Option<String>>,
+    storage_keys: &HashMap<String, String>,
+    watched_accounts: &HashMap<String, Invoice>,
+    decode: impl Fn(&Invoice, String) -> Result<Balance, ChainError>,
+) -> HashMap<String, Balance> {
+    values
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let id = storage_keys.get(&key)?;
+            let invoice = watched_accounts.get(id)?;
+            // There's no storage for an account that holds nothing.
+            let decoded = value.map_or(Ok(Balance(0)), |storage| decode(invoice, storage));
+
+            match decoded {
+                Ok(balance) => Some((id.clone(), balance)),
+                Err(e) => {
+                    tracing::warn!("account balance decoding error of order {id}: {e:?}");
+
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Note a storage change of the account to check its balance at the next finalized block. Only a
+/// balance above the known one is worth the check.
+fn note_change(invoice: &mut Invoice, balance: Balance, block_number: BlockNumber) {
+    if balance > invoice.received {
+        invoice.pending_change = Some((balance, block_number));
+    }
+}
+
+/// Clear the noted storage change once the balance at the block has caught up with it, or once
+/// it's older than a full check, so a change that never shows up isn't checked forever.
+fn settle_change(invoice: &mut Invoice, balance: Balance, block_number: BlockNumber) {
+    if invoice.pending_change.is_some_and(|(change, since)| {
+        change == balance || block_number.saturating_sub(since) > FULL_CHECK_BLOCKS
+    }) {
+        invoice.pending_change = None;
+    }
+}
+
+/// Storage keys of the balances of the watched accounts, along with the order IDs. The storage
+/// watch is told to follow the keys.
+fn watch_storage_keys(
+    watcher: &ChainWatcher,
+    watched_accounts: &HashMap<String, Invoice>,
+    keys_tx: &watch::Sender<Vec<String>>,
+) -> HashMap<String, String> {
+    let mut storage_keys = HashMap::with_capacity(watched_accounts.len());
+
+    for (id, invoice) in watched_accounts {
+        watch_storage_key(watcher, &mut storage_keys, id, invoice);
+    }
+
+    publish_storage_keys(&storage_keys, keys_tx);
+
+    storage_keys
+}
+
+/// Add the balance storage key of the account, returning whether the keys have changed.
+fn watch_storage_key(
+    watcher: &ChainWatcher,
+    storage_keys: &mut HashMap<String, String>,
+    id: &str,
+    invoice: &Invoice,
+) -> bool {
+    match invoice.storage_key(watcher) {
+        Ok(key) => storage_keys.insert(key, id.to_owned()).is_none(),
+        Err(e) => {
+            tracing::warn!("Failed to build the balance storage key of order {id}: {e}");
+
+            false
+        }
+    }
+}
+
+/// Remove the balance storage key of the account, returning whether the keys have changed.
+fn unwatch_storage_key(
+    watcher: &ChainWatcher,
+    storage_keys: &mut HashMap<String, String>,
+    invoice: &Invoice,
+) -> bool {
+    invoice
+        .storage_key(watcher)
+        .is_ok_and(|key| storage_keys.remove(&key).is_some())
+}
+
+/// Tell the storage watch to follow the keys.
+fn publish_storage_keys(
+    storage_keys: &HashMap<String, String>,
+    keys_tx: &watch::Sender<Vec<String>>,
+) {
+    keys_tx.send_replace(storage_keys.keys().cloned().collect());
+}
+
+/// Follow the balance storage under the keys from the tracker, subscribing again a moment after
+/// they change, and pass the changes to the tracker. Stops along with the connection.
+fn start_storage_watch(
+    client: Arc<WsClient>,
+    mut keys_rx: watch::Receiver<Vec<String>>,
+    chain_tx: mpsc::Sender<ChainTrackerRequest>,
+    task_tracker: &TaskTracker,
+    rpc_url: &str,
+) {
+    let rpc = rpc_url.to_owned();
+
+    task_tracker.spawn(format!("watching storage at {rpc}"), async move {
+        loop {
+            let keys = keys_rx.borrow_and_update().clone();
+            let mut subscription = if keys.is_empty() {
+                None
+            } else {
+                match subscribe_storage(&client, &keys).await {
+                    Ok(storage_subscription) => Some(storage_subscription),
+                    Err(e) => {
+                        tracing::warn!("Storage subscription error: {e} at {rpc}");
+
+                        None
+                    }
+                }
+            };
+
+            let mut resubscribe_at = None;
+
+            loop {
+                let next_change = async {
+                    match &mut subscription {
+                        Some(storage_subscription) => storage_subscription.next().await,
+                        None => pending().await,
+                    }
+                };
+                let resubscription = async {
+                    match resubscribe_at {
+                        Some(at) => sleep_until(at).await,
+                        None => pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    keys_update = keys_rx.changed() => {
+                        if keys_update.is_err() {
+                            return Ok(format!("Storage watch at {rpc} stopped"));
+                        }
+
+                        // The current subscription is still followed until the delay ends, and the
+                        // new one starts with the current values of all keys.
+                        resubscribe_at = resubscribe_at
+                            .or_else(|| tokio::time::Instant::now().checked_add(RESUBSCRIBE_DELAY));
+                    }
+                    () = resubscription => break,
+                    change_set = next_change => match change_set {
+                        Some(Ok(notification)) => {
+                            if chain_tx
+                                .send(ChainTrackerRequest::StorageChanges(notification.changes))
+                                .await
+                                .is_err()
+                            {
+                                return Ok(format!("Storage watch at {rpc} stopped"));
+                            }
+                        }
+                        Some(Err(e)) => {
+                            tracing::warn!("Storage watch error: {e} at {rpc}");
+                            subscription = None;
+                        }
+                        None => {
+                            tracing::warn!("Storage subscription terminated at {rpc}");
+                            subscription = None;
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
 #[derive(Debug, Clone)]
 pub struct ChainWatcher {
     pub genesis_hash: BlockHash,
     pub metadata: RuntimeMetadataV15,
     pub specs: ShortSpecs,
     pub assets: HashMap<String, CurrencyProperties>,
+    /// Overpayment policies of the currencies, as they're set in the config.
+    pub overpayment_policies: HashMap<String, OverpaymentPolicy>,
     version: Value,
 }
 
 impl ChainWatcher {
-    #[expect(clippy::too_many_lines)]
+    #[expect(clippy::too_many_lines, clippy::too_many_arguments)]
     pub async fn prepare_chain(
         client: &WsClient,
         chain: Chain,
         watched_accounts: &mut HashMap<String, Invoice>,
         rpc_url: &str,
+        endpoints: &Endpoints,
         chain_tx: mpsc::Sender<ChainTrackerRequest>,
         state: State,
         task_tracker: TaskTracker,
@@ -372,11 +794,24 @@ impl ChainWatcher {
                         rpc_url: rpc_url.to_owned(),
                         asset_id: None,
                         ss58: 0,
+                        existential_deposit: existential_deposit(&metadata)?,
                     },
                 );
             }
         }
 
+        let overpayment_policies = chain
+            .native_token
+            .iter()
+            .map(|native_token| (native_token.name.clone(), native_token.overpayment_policy))
+            .chain(
+                chain
+                    .asset
+                    .iter()
+                    .map(|asset| (asset.name.clone(), asset.overpayment_policy)),
+            )
+            .collect();
+
         // Deduplication is done on chain manager level;
         // Check that we have same number of assets as requested (we've checked that we have only
         // wanted ones and performed deduplication before)
@@ -398,6 +833,7 @@ impl ChainWatcher {
             metadata,
             specs,
             assets,
+            overpayment_policies,
             version,
         };
 
@@ -405,11 +841,14 @@ impl ChainWatcher {
         let mut id_remove_list = Vec::new();
         for (id, account) in watched_accounts.iter() {
             match account.check(client, &chain, &block).await {
-                Ok(true) => {
-                    state.order_paid(id.clone()).await;
+                // With a quorum, the payment is confirmed in the background at the first block.
+                Ok(true) if !endpoints.needs_confirmation() => {
+                    state
+                        .order_paid(id.clone(), ChangeOrigin::tracker(None))
+                        .await;
                     id_remove_list.push(id.to_owned());
                 }
-                Ok(false) => (),
+                Ok(_) => (),
                 Err(e) => {
                     tracing::warn!("account fetch error: {0}", e);
                 }
@@ -446,3 +885,105 @@ impl ChainWatcher {
         Ok(chain)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::api_v2::{CurrencyInfo, Timestamp};
+    use substrate_crypto_light::common::AccountId32;
+
+    fn invoice() -> Invoice {
+        Invoice {
+            id: "order".into(),
+            address: AccountId32([1; 32]),
+            currency: CurrencyInfo {
+                currency: "DOT".into(),
+                chain_name: "polkadot".into(),
+                kind: TokenKind::Native,
+                decimals: 10,
+                rpc_url: String::new(),
+                asset_id: None,
+                ss58: 0,
+            },
+            amount: Balance(100),
+            recipient: AccountId32([2; 32]),
+            death: Timestamp(0),
+            received: Balance(40),
+            overdue: false,
+            payers: Vec::new(),
+            splits: Vec::new(),
+            pending_change: None,
+        }
+    }
+
+    #[test]
+    fn pending_changes() {
+        let mut invoice = invoice();
+
+        // Nothing above the known balance is worth a check.
+        note_change(&mut invoice, Balance(40), 10);
+        assert_eq!(invoice.pending_change, None);
+
+        note_change(&mut invoice, Balance(70), 10);
+        assert_eq!(invoice.pending_change, Some((Balance(70), 10)));
+
+        // The finalized block doesn't have the change yet.
+        settle_change(&mut invoice, Balance(40), 11);
+        assert_eq!(invoice.pending_change, Some((Balance(70), 10)));
+
+        settle_change(&mut invoice, Balance(70), 12);
+        assert_eq!(invoice.pending_change, None);
+
+        // A change that never shows up is dropped after a full check.
+        note_change(&mut invoice, Balance(90), 20);
+        settle_change(&mut invoice, Balance(40), 20 + FULL_CHECK_BLOCKS);
+        assert_eq!(invoice.pending_change, Some((Balance(90), 20)));
+
+        settle_change(&mut invoice, Balance(40), 21 + FULL_CHECK_BLOCKS);
+        assert_eq!(invoice.pending_change, None);
+
+        // Nothing to clear without a change.
+        settle_change(&mut invoice, Balance(90), 200);
+        assert_eq!(invoice.pending_change, None);
+    }
+
+    #[test]
+    fn balances_of_keys() {
+        let storage_keys = HashMap::from([
+            ("0xaa".to_owned(), "paid".to_owned()),
+            ("0xbb".to_owned(), "empty".to_owned()),
+            ("0xcc".to_owned(), "broken".to_owned()),
+            ("0xdd".to_owned(), "unwatched".to_owned()),
+        ]);
+        let watched_accounts = ["paid", "empty", "broken"]
+            .into_iter()
+            .map(|id| {
+                let mut watched = invoice();
+
+                watched.id = id.into();
+
+                (id.to_owned(), watched)
+            })
+            .collect();
+        let values = HashMap::from([
+            ("0xaa".to_owned(), Some("0x64".to_owned())),
+            ("0xbb".to_owned(), None),
+            ("0xcc".to_owned(), Some("0x".to_owned())),
+            ("0xdd".to_owned(), Some("0x10".to_owned())),
+            ("0xee".to_owned(), Some("0x20".to_owned())),
+        ]);
+        let balances = balances_by_order(values, &storage_keys, &watched_accounts, |_, value| {
+            u128::from_str_radix(value.trim_start_matches("0x"), 16)
+                .map(Balance)
+                .map_err(|_| ChainError::StorageQuery)
+        });
+
+        assert_eq!(
+            balances,
+            HashMap::from([
+                ("paid".to_owned(), Balance(100)),
+                ("empty".to_owned(), Balance(0)),
+            ])
+        );
+    }
+}
@@ -1,9 +1,12 @@
 //! Utils to process chain data without accessing the chain
 
 use crate::{
-    chain::definitions::BlockHash,
-    database::TxKind,
-    definitions::{api_v2::AssetId, Balance},
+    chain::definitions::{BlockHash, Invoice},
+    database::{FinalizedTxDb, TransactionInfoDb, TransactionInfoDbInner, TransferIndex},
+    definitions::{
+        api_v2::{Amount, AssetId, BlockNumber, ExtrinsicIndex, Timestamp, TxKind, TxStatus},
+        Balance,
+    },
     error::ChainError,
 };
 use codec::Encode;
@@ -14,6 +17,7 @@ use frame_metadata::{
 use hashing::{blake2_128, blake2_256, twox_128, twox_256, twox_64};
 use scale_info::{form::PortableForm, TypeDef, TypeDefPrimitive};
 use serde_json::{Map, Value};
+use std::collections::HashMap;
 use substrate_constructor::{
     fill_prepare::{
         prepare_type, EraToFill, PrimitiveToFill, RegularPrimitiveToFill, SpecialTypeToFill,
@@ -26,15 +30,16 @@ use substrate_constructor::{
         StorageSelector, StorageSelectorFunctional,
     },
 };
-use substrate_crypto_light::common::AccountId32;
+use substrate_crypto_light::common::{AccountId32, AsBase58};
 use substrate_parser::{
-    cards::{ExtendedData, FieldData, ParsedData},
+    cards::{Event, ExtendedData, FieldData, ParsedData},
     decode_all_as_type,
     decoding_sci::Ty,
     propagated::Propagated,
     special_indicators::SpecialtyUnsignedInteger,
     ResolveType, ShortSpecs,
 };
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
 
 pub struct AssetTransferConstructor<'a> {
     pub asset_id: u32,
@@ -689,6 +694,71 @@ pub fn parse_transfer_event(
     }
 }
 
+/// Event along with the index and bytes of the extrinsic that has emitted it
+pub type ExtrinsicEvent = (Option<(ExtrinsicIndex, Vec<u8>)>, Event);
+
+/// Collect finalized transactions of the invoice account from transfer events of a single block.
+pub fn transfer_transactions(
+    invoice: &Invoice,
+    block_number: BlockNumber,
+    timestamp: Timestamp,
+    events: &[ExtrinsicEvent],
+) -> Result<Vec<TransactionInfoDb>, ChainError> {
+    let mut transactions = Vec::new();
+    let mut transfers_in_extrinsic: HashMap<ExtrinsicIndex, TransferIndex> = HashMap::new();
+
+    for (extrinsic_option, event) in events {
+        if let Some((tx_kind, another_account, transfer_amount)) =
+            parse_transfer_event(&invoice.address, &event.0.fields)
+        {
+            tracing::debug!(
+                "Found {tx_kind:?} from/to {another_account:?} with {transfer_amount:?} token(s)."
+            );
+
+            let Some((position_in_block, extrinsic)) = extrinsic_option else {
+                return Err(ChainError::TransferEventNoExtrinsic);
+            };
+            // A batch can make several transfers from or to the account in a single extrinsic.
+            let transfer_index = transfers_in_extrinsic
+                .entry(*position_in_block)
+                .or_default();
+
+            let finalized_tx_timestamp = i64::try_from(timestamp.0)
+                .ok()
+                .and_then(|millis| {
+                    OffsetDateTime::UNIX_EPOCH.checked_add(Duration::milliseconds(millis))
+                })
+                .and_then(|datetime| datetime.format(&Rfc3339).ok());
+            let (sender, recipient) = match tx_kind {
+                TxKind::Payment => (another_account, invoice.address),
+                TxKind::Withdrawal | TxKind::Refund => (invoice.address, another_account),
+            };
+
+            transactions.push(TransactionInfoDb {
+                transaction_bytes: const_hex::encode_prefixed(extrinsic),
+                inner: TransactionInfoDbInner {
+                    finalized_tx: Some(FinalizedTxDb {
+                        block_number,
+                        position_in_block: *position_in_block,
+                        transfer_index: *transfer_index,
+                    }),
+                    finalized_tx_timestamp,
+                    sender: sender.to_base58_string(42),
+                    recipient: recipient.to_base58_string(42),
+                    amount: Amount::Exact(transfer_amount),
+                    currency: invoice.currency.clone(),
+                    status: TxStatus::Finalized,
+                    kind: tx_kind,
+                },
+            });
+
+            *transfer_index = transfer_index.saturating_add(1);
+        }
+    }
+
+    Ok(transactions)
+}
+
 pub fn asset_balance_query(
     metadata_v15: &RuntimeMetadataV15,
     account_id: &AccountId32,
@@ -1083,6 +1153,17 @@ pub fn fetch_constant(
     }
 }
 
+/// Read the existential deposit of the native token from the `Balances` pallet constants.
+pub fn existential_deposit(metadata: &RuntimeMetadataV15) -> Result<Balance, ChainError> {
+    match fetch_constant(metadata, "Balances", "ExistentialDeposit") {
+        Some(ExtendedData {
+            data: ParsedData::PrimitiveU128 { value, .. },
+            ..
+        }) => Ok(Balance(value)),
+        _ => Err(ChainError::NoExistentialDeposit),
+    }
+}
+
 pub fn system_properties_to_short_specs(
     system_properties: &Map<String, Value>,
     metadata: &RuntimeMetadataV15,
@@ -1214,3 +1295,258 @@ pub fn unit(x: &Map<String, Value>) -> Result<String, ChainError> {
         None => Err(ChainError::NoUnit),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::rpc::system_balance_from_value;
+    use crate::definitions::api_v2::{CurrencyInfo, TokenKind};
+    use frame_metadata::v15::{
+        CustomMetadata, ExtrinsicMetadata, OuterEnums, PalletConstantMetadata, PalletMetadata,
+        PalletStorageMetadata, StorageEntryMetadata, StorageEntryModifier, StorageEntryType,
+    };
+    use scale_info::{meta_type, Path, TypeInfo};
+    use std::collections::BTreeMap;
+    use substrate_parser::cards::{Info, PalletSpecificData};
+
+    fn field(name: &str, data: ParsedData) -> FieldData {
+        FieldData {
+            field_name: Some(name.into()),
+            type_name: None,
+            field_docs: String::new(),
+            data: ExtendedData {
+                data,
+                info: Vec::new(),
+            },
+        }
+    }
+
+    fn transfer(from: AccountId32, to: AccountId32, amount: u128) -> Event {
+        Event(PalletSpecificData {
+            pallet_info: Info {
+                docs: String::new(),
+                path: Path::default(),
+            },
+            variant_docs: String::new(),
+            pallet_name: "Balances".into(),
+            variant_name: "Transfer".into(),
+            fields: vec![
+                field("from", ParsedData::Id(from)),
+                field("to", ParsedData::Id(to)),
+                field(
+                    "amount",
+                    ParsedData::PrimitiveU128 {
+                        value: amount,
+                        specialty: SpecialtyUnsignedInteger::Balance,
+                    },
+                ),
+            ],
+        })
+    }
+
+    fn metadata(pallets: Vec<PalletMetadata>) -> RuntimeMetadataV15 {
+        RuntimeMetadataV15::new(
+            pallets,
+            ExtrinsicMetadata {
+                version: 4,
+                address_ty: meta_type::<()>(),
+                call_ty: meta_type::<()>(),
+                signature_ty: meta_type::<()>(),
+                extra_ty: meta_type::<()>(),
+                signed_extensions: Vec::new(),
+            },
+            meta_type::<()>(),
+            Vec::new(),
+            OuterEnums {
+                call_enum_ty: meta_type::<()>(),
+                event_enum_ty: meta_type::<()>(),
+                error_enum_ty: meta_type::<()>(),
+            },
+            CustomMetadata {
+                map: BTreeMap::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn system_balance_decoding() {
+        #[derive(TypeInfo)]
+        struct AccountId32([u8; 32]);
+
+        type Balance = u128;
+
+        #[derive(Encode, TypeInfo)]
+        struct AccountData {
+            free: Balance,
+            reserved: Balance,
+        }
+
+        #[derive(Encode, TypeInfo)]
+        struct AccountInfo {
+            nonce: u32,
+            data: AccountData,
+        }
+
+        let metadata = metadata(vec![PalletMetadata {
+            name: "System",
+            storage: Some(PalletStorageMetadata {
+                prefix: "System",
+                entries: vec![StorageEntryMetadata {
+                    name: "Account",
+                    modifier: StorageEntryModifier::Default,
+                    ty: StorageEntryType::Map {
+                        hashers: vec![StorageHasher::Blake2_128Concat],
+                        key: meta_type::<AccountId32>(),
+                        value: meta_type::<AccountInfo>(),
+                    },
+                    default: Vec::new(),
+                    docs: Vec::new(),
+                }],
+            }),
+            calls: None,
+            event: None,
+            constants: Vec::new(),
+            error: None,
+            index: 0,
+            docs: Vec::new(),
+        }]);
+        let account = super::AccountId32([1; 32]);
+        let query = system_balance_query(&metadata, &account).unwrap();
+
+        // The key ends with the account itself after its hash.
+        assert!(query.key.ends_with(&const_hex::encode(account.0)));
+
+        let value = AccountInfo {
+            nonce: 3,
+            data: AccountData {
+                free: 1_500,
+                reserved: 20,
+            },
+        };
+
+        assert_eq!(
+            system_balance_from_value(
+                &Value::String(const_hex::encode_prefixed(value.encode())),
+                &query,
+                &metadata
+            )
+            .unwrap(),
+            Balance(1_500)
+        );
+        assert!(
+            system_balance_from_value(&Value::String("0x0102".into()), &query, &metadata).is_err()
+        );
+    }
+
+    #[test]
+    fn existential_deposit_constant() {
+        let balances = |constants| PalletMetadata {
+            name: "Balances",
+            storage: None,
+            calls: None,
+            event: None,
+            constants,
+            error: None,
+            index: 10,
+            docs: Vec::new(),
+        };
+
+        assert_eq!(
+            existential_deposit(&metadata(vec![balances(vec![PalletConstantMetadata {
+                name: "ExistentialDeposit",
+                ty: meta_type::<u128>(),
+                value: 10_000_000_000u128.encode(),
+                docs: Vec::new(),
+            }])]))
+            .unwrap(),
+            Balance(10_000_000_000)
+        );
+        assert!(matches!(
+            existential_deposit(&metadata(vec![balances(Vec::new())])),
+            Err(ChainError::NoExistentialDeposit)
+        ));
+        assert!(matches!(
+            existential_deposit(&metadata(Vec::new())),
+            Err(ChainError::NoExistentialDeposit)
+        ));
+    }
+
+    #[test]
+    fn transfers_of_invoice() {
+        let address = AccountId32([1; 32]);
+        let payer = AccountId32([2; 32]);
+        let invoice = Invoice {
+            id: "order".into(),
+            address,
+            currency: CurrencyInfo {
+                currency: "DOT".into(),
+                chain_name: "polkadot".into(),
+                kind: TokenKind::Native,
+                decimals: 10,
+                rpc_url: String::new(),
+                asset_id: None,
+                ss58: 0,
+            },
+            amount: Balance(100),
+            recipient: AccountId32([3; 32]),
+            death: Timestamp(0),
+            received: Balance(0),
+            overdue: false,
+            payers: Vec::new(),
+            splits: Vec::new(),
+            pending_change: None,
+        };
+        let events = [
+            (Some((1, vec![1])), transfer(payer, address, 60)),
+            (Some((1, vec![1])), transfer(payer, address, 40)),
+            (
+                Some((2, vec![2])),
+                transfer(payer, AccountId32([4; 32]), 10),
+            ),
+            (
+                Some((3, vec![3])),
+                transfer(address, invoice.recipient, 100),
+            ),
+        ];
+
+        let transactions = transfer_transactions(&invoice, 5, Timestamp(0), &events).unwrap();
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|tx| {
+                let finalized = tx.inner.finalized_tx.as_ref().unwrap();
+
+                (
+                    tx.inner.kind,
+                    finalized.position_in_block,
+                    finalized.transfer_index,
+                    tx.inner.amount.format(0),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                (TxKind::Payment, 1, 0, "60".to_owned()),
+                (TxKind::Payment, 1, 1, "40".to_owned()),
+                (TxKind::Withdrawal, 3, 0, "100".to_owned()),
+            ]
+        );
+        assert_eq!(transactions[0].inner.sender, payer.to_base58_string(42));
+        assert_eq!(transactions[0].transaction_bytes, "0x01");
+        assert_eq!(
+            transactions[0].inner.finalized_tx_timestamp.as_deref(),
+            Some("1970-01-01T00:00:00Z")
+        );
+        // A transfer of the invoice account can't be recorded without its extrinsic.
+        assert!(matches!(
+            transfer_transactions(
+                &invoice,
+                5,
+                Timestamp(0),
+                &[(None, transfer(payer, address, 1))]
+            ),
+            Err(ChainError::TransferEventNoExtrinsic)
+        ));
+    }
+}
@@ -7,39 +7,70 @@
 use crate::{
     definitions::{
         api_v2::{
-            Amount, BlockNumber, CurrencyInfo, ExtrinsicIndex, FinalizedTx, OrderCreateResponse,
-            OrderInfo, OrderQuery, PaymentStatus, ServerInfo, Timestamp, TransactionInfo, TxStatus,
+            splits_rejection, Amount, BlockNumber, ChangeOrigin, CurrencyInfo, CurrencyProperties,
+            ExtrinsicIndex, FinalizedTx, OrderCreateResponse, OrderEvent, OrderField,
+            OrderHistoryEntry, OrderInfo, OrderList, OrderListEntry, OrderListQuery, OrderQuery,
+            PaymentStatus, PublicOrderInfo, ServerInfo, SplitShare, Timestamp, TransactionInfo,
+            TxKind, TxStatus, WebhookId, WebhookInfo, WebhookListQuery, WebhookStatus,
             WithdrawalStatus,
         },
-        Version,
+        Balance, DatabaseBackend, ModificationPolicy, UnderpaidPolicy, Version,
     },
     error::DbError,
+    state::Merchants,
     utils::task_tracker::TaskTracker,
 };
 use codec::{Decode, Encode};
 use names::Generator;
-use sled::Tree;
-use std::time::SystemTime;
-use substrate_crypto_light::common::AccountId32;
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError, Transactional},
+    Db, Error as DatabaseError, IVec, Tree,
+};
+use sqlite::{text, SqliteStorage};
+use std::{
+    mem,
+    ops::{Bound, ControlFlow},
+    path::Path,
+    time::SystemTime,
+};
+use substrate_crypto_light::common::{AccountId32, AsBase58};
 use tokio::sync::{mpsc, oneshot};
 
+mod sqlite;
+
 pub const MODULE: &str = module_path!();
 
-const DB_VERSION: Version = 0;
+/// Version of the schema that this daemon reads and writes. See [`MIGRATIONS`] for the changes
+/// between versions.
+const DB_VERSION: Version = 5;
 
 // Tables
+
+/// Reverse index from a derived payment account to the order it was derived for.
 const ACCOUNTS: &str = "accounts";
 
-//type ACCOUNTS_KEY = (Option<AssetId>, Account);
+//type ACCOUNTS_KEY = Account;
 //type ACCOUNTS_VALUE = InvoiceKey;
 
+/// Keyed by the order, the extrinsic, and the recipient of the transfer.
 const PENDING_TRANSACTIONS: &str = "pending_transactions";
+/// Keyed by the order, the block number, the extrinsic position, and the transfer index.
 const TRANSACTIONS: &str = "transactions";
 
 const HIT_LIST: &str = "hit_list";
 
-// The database version must be stored in a separate slot to be used by the not implemented yet
-// database migration logic.
+/// Outbox of callbacks to merchants, keyed by big-endian webhook IDs to keep them in order.
+const WEBHOOKS: &str = "webhooks";
+/// Events that merchants have subscribed to per order. Orders without a record get all of them.
+const SUBSCRIPTIONS: &str = "subscriptions";
+/// Append-only log of order changes, keyed by the order and a big-endian sequence number.
+const ORDER_HISTORY: &str = "order_history";
+
+/// Number of orders in a page of an order listing if the limit isn't given.
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+// The database version is stored in a separate slot of the default tree. Databases without it were
+// created before the versioning was introduced and have version 0.
 const DB_VERSION_KEY: &str = "db_version";
 const SERVER_INFO_ID: &str = "instance_id";
 
@@ -58,9 +89,10 @@ type Derivation = [u8; 32];
 pub type Account = [u8; 32];
 
 pub struct ConfigWoChains {
-    pub recipient: AccountId32,
+    pub merchants: Merchants,
     pub debug: Option<bool>,
-    pub remark: Option<String>,
+    pub underpaid_policy: UnderpaidPolicy,
+    pub modification_policy: ModificationPolicy,
     //pub depth: Option<Duration>,
 }
 
@@ -73,125 +105,143 @@ pub struct Database {
 impl Database {
     #[expect(clippy::too_many_lines)]
     pub fn init(
+        backend: DatabaseBackend,
         path_option: Option<String>,
         task_tracker: TaskTracker,
         account_lifetime: Timestamp,
     ) -> Result<Self, DbError> {
         let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
-        let database = if let Some(path) = path_option {
-            tracing::info!("Creating/Opening the database at {path:?}.");
 
-            sled::open(path).map_err(DbError::DbStartError)?
-        } else {
-            // TODO
-            /*
+        if path_option.is_none() {
             tracing::warn!(
                 "The in-memory backend for the database is selected. All saved data will be deleted after the shutdown!"
-            );*/
-            sled::open("temp.db").map_err(DbError::DbStartError)?
+            );
+        }
+
+        let storage: Box<dyn Storage> = match backend {
+            DatabaseBackend::Sled => Box::new(SledStorage::open(path_option)?),
+            DatabaseBackend::Sqlite => Box::new(SqliteStorage::open(path_option)?),
         };
-        let orders = database
-            .open_tree(ORDERS_TABLE)
-            .map_err(DbError::DbStartError)?;
-        let transactions = database
-            .open_tree(TRANSACTIONS)
-            .map_err(DbError::DbStartError)?;
-        let pending_transactions = database
-            .open_tree(PENDING_TRANSACTIONS)
-            .map_err(DbError::DbStartError)?;
 
         task_tracker.spawn("Database server", async move {
             // No process forking beyond this point!
             while let Some(request) = rx.recv().await {
                 match request {
                     DbRequest::ActiveOrderList(res) => {
-                        let _unused = res.send(Ok(orders
-                            .iter()
-                            .filter_map(|a| a.ok())
-                            .filter_map(|(a, b)| {
-                                match (String::decode(&mut &a[..]), OrderInfo::decode(&mut &b[..]))
-                                {
-                                    (Ok(a), Ok(b)) => Some((a, b)),
-                                    _ => None,
-                                }
-                            })
-                            .filter(|(_, b)| b.payment_status == PaymentStatus::Pending)
-                            .collect()));
+                        let _unused = res.send(active_orders(&*storage));
                     }
                     DbRequest::CreateOrder(request) => {
                         let _unused = request.res.send(create_order(
-                            request.order,
+                            &request.order,
                             request.query,
-                            request.currency,
+                            request.properties,
                             request.payment_account,
-                            &orders,
+                            request.recipient,
+                            request.policy,
+                            &*storage,
                             account_lifetime,
                         ));
                     }
+                    DbRequest::OrderHistory(ReadHistory { order, res }) => {
+                        let _unused = res.send(order_history(&order, &*storage));
+                    }
                     DbRequest::ReadOrder(request) => {
-                        let _unused = request.res.send(read_order(
-                            &request.order,
-                            &orders,
-                            &transactions,
-                            &pending_transactions,
-                        ));
+                        let _unused = request.res.send(read_order(&request.order, &*storage));
+                    }
+                    DbRequest::AllOrders(res) => {
+                        let _unused = res.send(all_orders(&*storage));
+                    }
+                    DbRequest::ListOrders(ListOrders { query, res }) => {
+                        let _unused = res.send(list_orders(&query, &*storage));
+                    }
+                    DbRequest::ReadPaymentAccount(ReadPaymentAccount { account, res }) => {
+                        let _unused = res.send(read_payment_account(&account, &*storage));
                     }
                     DbRequest::MarkPaid(request) => {
-                        let _unused = request.res.send(mark_paid(request.order, &orders));
+                        let _unused =
+                            request
+                                .res
+                                .send(mark_paid(request.order, request.origin, &*storage));
                     }
                     DbRequest::IsMarkedPaid(order, res) => {
-                        let _unused = res.send(is_marked_paid(&orders, order));
+                        let _unused = res.send(is_marked_paid(&*storage, order));
                     }
                     DbRequest::MarkWithdrawn(request) => {
-                        let _unused = request.res.send(mark_withdrawn(request.order, &orders));
+                        let _unused = request.res.send(mark_withdrawn(
+                            request.order,
+                            request.origin,
+                            &*storage,
+                        ));
                     }
                     DbRequest::MarkForced(request) => {
-                        let _unused = request.res.send(mark_forced(request.order, &orders));
+                        let _unused =
+                            request
+                                .res
+                                .send(mark_forced(request.order, request.origin, &*storage));
+                    }
+                    DbRequest::RecordReceived(request) => {
+                        let _unused = request.res.send(record_received(
+                            request.order,
+                            request.received,
+                            request.origin,
+                            &*storage,
+                        ));
+                    }
+                    DbRequest::MarkRefunded(request) => {
+                        let _unused = request.res.send(mark_refunded(
+                            request.order,
+                            request.origin,
+                            &*storage,
+                        ));
+                    }
+                    DbRequest::MarkTimedOut(request) => {
+                        let _unused = request.res.send(mark_timed_out(
+                            request.order,
+                            request.origin,
+                            &*storage,
+                        ));
                     }
                     DbRequest::MarkStuck(request) => {
-                        let _unused = request.res.send(mark_stuck(request.order, &orders));
+                        let _unused =
+                            request
+                                .res
+                                .send(mark_stuck(request.order, request.origin, &*storage));
                     }
                     DbRequest::RecordTransaction { order, tx, res } => {
-                        let _unused = res.send(record_transaction(
-                            &transactions,
-                            &pending_transactions,
-                            order,
-                            tx,
+                        let _unused = res.send(record_transaction(&*storage, &order, tx));
+                    }
+                    DbRequest::EnqueueWebhook(request) => {
+                        let _unused = request.res.send(enqueue_webhook(
+                            &*storage,
+                            request.order,
+                            request.event,
+                            request.url,
+                            request.payload,
                         ));
                     }
+                    DbRequest::Subscription(order, res) => {
+                        let _unused = res.send(storage.subscription(&order));
+                    }
+                    DbRequest::PendingWebhooks(res) => {
+                        let _unused = res.send(list_webhooks(
+                            &*storage,
+                            &WebhookListQuery {
+                                order: None,
+                                status: Some(WebhookStatus::Pending),
+                            },
+                        ));
+                    }
+                    DbRequest::ListWebhooks(ListWebhooks { query, res }) => {
+                        let _unused = res.send(list_webhooks(&*storage, &query));
+                    }
+                    DbRequest::SaveWebhook(webhook, res) => {
+                        let _unused = res.send(storage.save_webhook(&webhook));
+                    }
+                    DbRequest::RedeliverWebhook(ModifyWebhook { id, res }) => {
+                        let _unused = res.send(redeliver_webhook(&*storage, id));
+                    }
                     DbRequest::InitializeServerInfo(res) => {
-                        let server_info_tree = database
-                            .open_tree(SERVER_INFO_TABLE)
-                            .map_err(DbError::DbStartError);
-                        let result = server_info_tree.and_then(|tree| {
-                            if let Some(server_info_data) =
-                                tree.get(SERVER_INFO_ID).map_err(DbError::DbInternalError)?
-                            {
-                                let server_info: ServerInfo =
-                                    serde_json::from_slice(&server_info_data).map_err(|e| {
-                                        DbError::DeserializationError(e.to_string())
-                                    })?;
-                                Ok(server_info.instance_id)
-                            } else {
-                                let mut generator = Generator::default();
-                                let new_instance_id = generator
-                                    .next()
-                                    .unwrap_or_else(|| "unknown-instance".to_string());
-                                let server_info_data = ServerInfo {
-                                    version: env!("CARGO_PKG_VERSION").to_string(),
-                                    instance_id: new_instance_id.clone(),
-                                    debug: false,
-                                    kalatori_remark: None,
-                                };
-                                tree.insert(
-                                    SERVER_INFO_ID,
-                                    serde_json::to_vec(&server_info_data)
-                                        .map_err(|e| DbError::SerializationError(e.to_string()))?,
-                                )?;
-                                Ok(new_instance_id)
-                            }
-                        });
-                        let _unused = res.send(result);
+                        let _unused = res.send(initialize_server_info(&*storage));
                     }
                     DbRequest::Shutdown(res) => {
                         let _ = res.send(());
@@ -200,7 +250,7 @@ impl Database {
                 };
             }
 
-            drop(database.flush());
+            drop(storage.flush());
 
             Ok("Database server is shutting down")
         });
@@ -220,12 +270,31 @@ impl Database {
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
 
+    /// Reads every saved order with its transactions regardless of the order state.
+    pub async fn all_orders(&self) -> Result<Vec<(String, OrderInfo)>, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self.tx.send(DbRequest::AllOrders(res)).await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    /// Reads a page of saved orders matching the query, ordered by their keys.
+    pub async fn list_orders(&self, query: OrderListQuery) -> Result<OrderList, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::ListOrders(ListOrders { query, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
     pub async fn create_order(
         &self,
         order: String,
         query: OrderQuery,
-        currency: CurrencyInfo,
+        properties: CurrencyProperties,
         payment_account: String,
+        recipient: String,
+        policy: ModificationPolicy,
     ) -> Result<OrderCreateResponse, DbError> {
         let (res, rx) = oneshot::channel();
         let _unused = self
@@ -233,8 +302,10 @@ impl Database {
             .send(DbRequest::CreateOrder(CreateOrder {
                 order,
                 query,
-                currency,
+                properties,
                 payment_account,
+                recipient,
+                policy,
                 res,
             }))
             .await;
@@ -250,6 +321,34 @@ impl Database {
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
 
+    /// Returns [`None`] if the order isn't found.
+    pub async fn order_history(
+        &self,
+        order: String,
+    ) -> Result<Option<Vec<OrderHistoryEntry>>, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::OrderHistory(ReadHistory { order, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    pub async fn read_payment_account(
+        &self,
+        account: Account,
+    ) -> Result<Option<PublicOrderInfo>, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::ReadPaymentAccount(ReadPaymentAccount {
+                account,
+                res,
+            }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
     pub async fn record_transaction(
         &self,
         order: String,
@@ -264,11 +363,15 @@ impl Database {
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
 
-    pub async fn mark_paid(&self, order: String) -> Result<OrderInfo, DbError> {
+    pub async fn mark_paid(
+        &self,
+        order: String,
+        origin: ChangeOrigin,
+    ) -> Result<OrderInfo, DbError> {
         let (res, rx) = oneshot::channel();
         let _unused = self
             .tx
-            .send(DbRequest::MarkPaid(MarkPaid { order, res }))
+            .send(DbRequest::MarkPaid(MarkPaid { order, origin, res }))
             .await;
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
@@ -279,28 +382,131 @@ impl Database {
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
 
-    pub async fn mark_withdrawn(&self, order: String) -> Result<(), DbError> {
+    pub async fn mark_withdrawn(&self, order: String, origin: ChangeOrigin) -> Result<(), DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::MarkWithdrawn(ModifyOrder { order, origin, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+    pub async fn mark_forced(&self, order: String, origin: ChangeOrigin) -> Result<(), DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::MarkForced(ModifyOrder { order, origin, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    /// Saves the payment account balance of an order awaiting payment. Returns `true` if it's a
+    /// new partial payment.
+    pub async fn record_received(
+        &self,
+        order: String,
+        received: Balance,
+        origin: ChangeOrigin,
+    ) -> Result<bool, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::RecordReceived(RecordReceived {
+                order,
+                received,
+                origin,
+                res,
+            }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    pub async fn mark_refunded(&self, order: String, origin: ChangeOrigin) -> Result<(), DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::MarkRefunded(ModifyOrder { order, origin, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    pub async fn mark_timed_out(&self, order: String, origin: ChangeOrigin) -> Result<(), DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::MarkTimedOut(ModifyOrder { order, origin, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    pub async fn mark_stuck(&self, order: String, origin: ChangeOrigin) -> Result<(), DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self
+            .tx
+            .send(DbRequest::MarkStuck(ModifyOrder { order, origin, res }))
+            .await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    /// Saves a new webhook to deliver the callback of the order as soon as possible.
+    pub async fn enqueue_webhook(
+        &self,
+        order: String,
+        event: OrderEvent,
+        url: String,
+        payload: String,
+    ) -> Result<WebhookInfo, DbError> {
         let (res, rx) = oneshot::channel();
         let _unused = self
             .tx
-            .send(DbRequest::MarkWithdrawn(ModifyOrder { order, res }))
+            .send(DbRequest::EnqueueWebhook(EnqueueWebhook {
+                order,
+                event,
+                url,
+                payload,
+                res,
+            }))
             .await;
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
-    pub async fn mark_forced(&self, order: String) -> Result<(), DbError> {
+
+    /// Events the merchant has subscribed to for the order, or [`None`] if to all of them.
+    pub async fn subscription(&self, order: String) -> Result<Option<Vec<OrderEvent>>, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self.tx.send(DbRequest::Subscription(order, res)).await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    pub async fn pending_webhooks(&self) -> Result<Vec<WebhookInfo>, DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self.tx.send(DbRequest::PendingWebhooks(res)).await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    pub async fn list_webhooks(
+        &self,
+        query: WebhookListQuery,
+    ) -> Result<Vec<WebhookInfo>, DbError> {
         let (res, rx) = oneshot::channel();
         let _unused = self
             .tx
-            .send(DbRequest::MarkForced(ModifyOrder { order, res }))
+            .send(DbRequest::ListWebhooks(ListWebhooks { query, res }))
             .await;
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
 
-    pub async fn mark_stuck(&self, order: String) -> Result<(), DbError> {
+    /// Overwrites the saved webhook, e.g., to record a delivery attempt.
+    pub async fn save_webhook(&self, webhook: WebhookInfo) -> Result<(), DbError> {
+        let (res, rx) = oneshot::channel();
+        let _unused = self.tx.send(DbRequest::SaveWebhook(webhook, res)).await;
+        rx.await.map_err(|_| DbError::DbEngineDown)?
+    }
+
+    /// Makes a failed webhook pending again with the delivery due right away.
+    pub async fn redeliver_webhook(&self, id: WebhookId) -> Result<WebhookInfo, DbError> {
         let (res, rx) = oneshot::channel();
         let _unused = self
             .tx
-            .send(DbRequest::MarkStuck(ModifyOrder { order, res }))
+            .send(DbRequest::RedeliverWebhook(ModifyWebhook { id, res }))
             .await;
         rx.await.map_err(|_| DbError::DbEngineDown)?
     }
@@ -315,12 +521,28 @@ impl Database {
 enum DbRequest {
     CreateOrder(CreateOrder),
     ActiveOrderList(oneshot::Sender<Result<Vec<(String, OrderInfo)>, DbError>>),
+    AllOrders(oneshot::Sender<Result<Vec<(String, OrderInfo)>, DbError>>),
     ReadOrder(ReadOrder),
+    OrderHistory(ReadHistory),
+    ListOrders(ListOrders),
+    ReadPaymentAccount(ReadPaymentAccount),
     MarkPaid(MarkPaid),
     MarkWithdrawn(ModifyOrder),
     MarkForced(ModifyOrder),
     IsMarkedPaid(String, oneshot::Sender<Result<bool, DbError>>),
+    RecordReceived(RecordReceived),
+    MarkRefunded(ModifyOrder),
+    MarkTimedOut(ModifyOrder),
     MarkStuck(ModifyOrder),
+    EnqueueWebhook(EnqueueWebhook),
+    Subscription(
+        String,
+        oneshot::Sender<Result<Option<Vec<OrderEvent>>, DbError>>,
+    ),
+    PendingWebhooks(oneshot::Sender<Result<Vec<WebhookInfo>, DbError>>),
+    ListWebhooks(ListWebhooks),
+    SaveWebhook(WebhookInfo, oneshot::Sender<Result<(), DbError>>),
+    RedeliverWebhook(ModifyWebhook),
     InitializeServerInfo(oneshot::Sender<Result<String, DbError>>),
     Shutdown(oneshot::Sender<()>),
     RecordTransaction {
@@ -333,8 +555,11 @@ enum DbRequest {
 pub struct CreateOrder {
     pub order: String,
     pub query: OrderQuery,
-    pub currency: CurrencyInfo,
+    pub properties: CurrencyProperties,
     pub payment_account: String,
+    /// Merchant recipient that the payment account is derived for.
+    pub recipient: String,
+    pub policy: ModificationPolicy,
     pub res: oneshot::Sender<Result<OrderCreateResponse, DbError>>,
 }
 
@@ -343,260 +568,1615 @@ pub struct ReadOrder {
     pub res: oneshot::Sender<Result<Option<OrderInfo>, DbError>>,
 }
 
+pub struct ReadHistory {
+    pub order: String,
+    pub res: oneshot::Sender<Result<Option<Vec<OrderHistoryEntry>>, DbError>>,
+}
+
+pub struct ListOrders {
+    pub query: OrderListQuery,
+    pub res: oneshot::Sender<Result<OrderList, DbError>>,
+}
+
+pub struct ReadPaymentAccount {
+    pub account: Account,
+    pub res: oneshot::Sender<Result<Option<PublicOrderInfo>, DbError>>,
+}
+
 pub struct ModifyOrder {
     pub order: String,
+    pub origin: ChangeOrigin,
     pub res: oneshot::Sender<Result<(), DbError>>,
 }
 
 pub struct MarkPaid {
     pub order: String,
+    pub origin: ChangeOrigin,
     pub res: oneshot::Sender<Result<OrderInfo, DbError>>,
 }
 
-fn calculate_death_ts(account_lifetime: Timestamp) -> Timestamp {
-    let start = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64;
+pub struct RecordReceived {
+    pub order: String,
+    pub received: Balance,
+    pub origin: ChangeOrigin,
+    pub res: oneshot::Sender<Result<bool, DbError>>,
+}
 
-    Timestamp(start + account_lifetime.0)
+pub struct EnqueueWebhook {
+    pub order: String,
+    pub event: OrderEvent,
+    pub url: String,
+    pub payload: String,
+    pub res: oneshot::Sender<Result<WebhookInfo, DbError>>,
 }
 
-fn create_order(
-    order: String,
-    query: OrderQuery,
-    currency: CurrencyInfo,
-    payment_account: String,
-    orders: &Tree,
-    account_lifetime: Timestamp,
-) -> Result<OrderCreateResponse, DbError> {
-    let order_key = order.encode();
-    Ok(if let Some(record) = orders.get(&order_key)? {
-        let mut old_order_info = OrderInfo::decode(&mut &record[..])?;
-        match old_order_info.payment_status {
-            PaymentStatus::Pending => {
-                let death = calculate_death_ts(account_lifetime);
+pub struct ListWebhooks {
+    pub query: WebhookListQuery,
+    pub res: oneshot::Sender<Result<Vec<WebhookInfo>, DbError>>,
+}
 
-                old_order_info.death = death;
-                old_order_info.currency = currency;
-                old_order_info.amount = query.amount;
+pub struct ModifyWebhook {
+    pub id: WebhookId,
+    pub res: oneshot::Sender<Result<WebhookInfo, DbError>>,
+}
 
-                orders.insert(&order_key, old_order_info.encode())?;
-                OrderCreateResponse::Modified(old_order_info)
-            }
-            PaymentStatus::Paid => OrderCreateResponse::Collision(old_order_info),
-        }
-    } else {
-        let death = calculate_death_ts(account_lifetime);
-        let order_info_new = OrderInfo::new(query, currency, payment_account, death);
+type VisitOrder<'a> = dyn FnMut(String, OrderInfo) -> Result<ControlFlow<()>, DbError> + 'a;
 
-        orders.insert(&order_key, order_info_new.encode())?;
-        OrderCreateResponse::New(order_info_new)
-    })
-}
+/// Storage engine behind the database server
+///
+/// The server calls it in series and keeps the order lifecycle rules to itself, so an engine only
+/// saves and finds records.
+trait Storage: Send {
+    /// Reads the order without its transactions.
+    fn order(&self, order: &str) -> Result<Option<OrderInfo>, DbError>;
 
-fn read_order(
-    key: &str,
-    orders: &Tree,
-    tx_table: &Tree,
-    pending_tx_table: &Tree,
-) -> Result<Option<OrderInfo>, DbError> {
-    let order_key = key.encode();
-    let Some(order_encoded) = orders.get(&order_key)? else {
-        return Ok(None);
-    };
+    /// Visits orders without their transactions in the order of their keys, starting after the
+    /// given one, until the visitor breaks.
+    fn for_each_order(
+        &self,
+        after: Option<&str>,
+        visit: &mut VisitOrder<'_>,
+    ) -> Result<(), DbError>;
 
-    let mut order = OrderInfo::decode(&mut &order_encoded[..])?;
-    let transactions = tx_table
-        .scan_prefix(&order_key)
-        .map(|result| {
-            result.map_err(DbError::from).and_then(|(k, v)| {
-                let (_order_key, block_number, position_in_block) =
-                    <(String, BlockNumber, ExtrinsicIndex)>::decode(&mut k.as_ref())?;
-
-                TransactionInfoDb::decode(&mut v.as_ref())
-                    .map(|mut tx| {
-                        tx.inner.finalized_tx = Some(FinalizedTxDb {
-                            block_number,
-                            position_in_block,
-                        });
-
-                        TransactionInfo::from(tx)
-                    })
-                    .map_err(Into::into)
-            })
-        })
-        .chain(pending_tx_table.scan_prefix(order_key).map(|result| {
-            result.map_err(DbError::from).and_then(|(k, v)| {
-                let (_order_key, transaction_bytes) = <(String, String)>::decode(&mut k.as_ref())?;
-
-                TransactionInfoDbInner::decode(&mut v.as_ref())
-                    .map(|tx| TransactionInfo {
-                        finalized_tx: None,
-                        transaction_bytes,
-                        sender: tx.sender,
-                        recipient: tx.recipient,
-                        amount: tx.amount,
-                        currency: tx.currency,
-                        status: tx.status,
-                    })
-                    .map_err(Into::into)
-            })
-        }))
-        .collect::<Result<Vec<_>, _>>()?;
+    /// Saves a new order and indexes its payment account.
+    fn insert_order(
+        &self,
+        order: &str,
+        order_info: &OrderInfo,
+        account: Account,
+    ) -> Result<(), DbError>;
 
-    order.transactions = transactions;
+    /// Overwrites the saved order. Its transactions are left as they are.
+    fn update_order(&self, order: &str, order_info: &OrderInfo) -> Result<(), DbError>;
 
-    Ok(order.into())
-}
+    fn order_by_account(&self, account: &Account) -> Result<Option<String>, DbError>;
 
-fn record_transaction(
-    tx_table: &Tree,
-    pending_tx_table: &Tree,
-    order: String,
-    mut tx: TransactionInfoDb,
-) -> Result<(), DbError> {
-    let pending_tx_key = (order.clone(), tx.transaction_bytes.clone()).encode();
-    let finalized_info = tx
-        .inner
-        .finalized_tx
-        .take()
-        .zip(tx.inner.finalized_tx_timestamp.as_ref());
+    /// Finalized transactions of the order in the order of their positions in the chain, followed
+    /// by pending ones.
+    fn transactions(&self, order: &str) -> Result<Vec<TransactionInfoDb>, DbError>;
 
-    // Search the given transaction among pending ones and update it or move it to finalized
-    // transactions.
-    if let Some(_encoded_tx_inner) = pending_tx_table.get(&pending_tx_key)? {
-        if let Some((finalized_tx, _finalized_tx_timestamp)) = finalized_info {
-            tracing::debug!("moving pending tx to finalized");
+    /// Reads the pending record of the same transfer as the given one.
+    fn pending_transaction(
+        &self,
+        order: &str,
+        tx: &TransactionInfoDb,
+    ) -> Result<Option<TransactionInfoDbInner>, DbError>;
 
-            pending_tx_table.remove(pending_tx_key)?;
+    fn save_pending_transaction(&self, order: &str, tx: &TransactionInfoDb) -> Result<(), DbError>;
 
-            tx_table.insert(
-                (
-                    order,
-                    finalized_tx.block_number,
-                    finalized_tx.position_in_block,
-                )
-                    .encode(),
-                tx.encode(),
-            )?;
-        } else {
-            tracing::debug!("updating pending tx");
+    /// Saves the finalized transfer in place of its pending record if there's one.
+    fn finalize_transaction(
+        &self,
+        order: &str,
+        finalized_tx: &FinalizedTxDb,
+        tx: &TransactionInfoDb,
+    ) -> Result<(), DbError>;
 
-            pending_tx_table.insert(pending_tx_key, tx.inner.encode())?;
-        }
-    // Save the given finalized transaction.
-    } else if let Some((finalized_tx, _finalized_tx_timestamp)) = finalized_info {
-        tracing::debug!("save finalized tx");
+    /// Appends entries after the ones already saved in the history of the order.
+    fn append_history(&self, order: &str, entries: &[OrderHistoryEntry]) -> Result<(), DbError>;
 
-        tx_table.insert(
-            (
-                order,
-                finalized_tx.block_number,
-                finalized_tx.position_in_block,
-            )
-                .encode(),
-            tx.encode(),
-        )?;
+    /// Overwrites the saved order and appends entries to its history in a single transaction, so
+    /// a change is never saved without its history or the other way around.
+    fn save_change(
+        &self,
+        order: &str,
+        order_info: &OrderInfo,
+        entries: &[OrderHistoryEntry],
+    ) -> Result<(), DbError>;
 
-    // Save the pending transaction.
-    } else {
-        tracing::debug!("adding pending tx");
+    /// History of the order in the order it was appended.
+    fn history(&self, order: &str) -> Result<Vec<OrderHistoryEntry>, DbError>;
 
-        pending_tx_table.insert(pending_tx_key, tx.inner.encode())?;
-    }
+    fn subscription(&self, order: &str) -> Result<Option<Vec<OrderEvent>>, DbError>;
 
-    Ok(())
-}
+    fn subscribe(&self, order: &str, events: &[OrderEvent]) -> Result<(), DbError>;
 
-fn mark_paid(order: String, orders: &Tree) -> Result<OrderInfo, DbError> {
-    let order_key = order.encode();
-    if let Some(order_info) = orders.get(order_key)? {
-        let mut order_info = OrderInfo::decode(&mut &order_info[..])?;
-        if order_info.payment_status == PaymentStatus::Pending {
-            order_info.payment_status = PaymentStatus::Paid;
-            orders.insert(order.encode(), order_info.encode())?;
-            Ok(order_info)
-        } else {
-            Err(DbError::AlreadyPaid(order))
-        }
-    } else {
-        Err(DbError::OrderNotFound(order))
-    }
+    fn next_webhook_id(&self) -> Result<WebhookId, DbError>;
+
+    fn webhook(&self, id: WebhookId) -> Result<Option<WebhookInfo>, DbError>;
+
+    /// All webhooks in the order they were enqueued.
+    fn webhooks(&self) -> Result<Vec<WebhookInfo>, DbError>;
+
+    fn save_webhook(&self, webhook: &WebhookInfo) -> Result<(), DbError>;
+
+    fn server_info(&self) -> Result<Option<ServerInfo>, DbError>;
+
+    fn save_server_info(&self, server_info: &ServerInfo) -> Result<(), DbError>;
+
+    /// Writes buffered changes to the disk.
+    fn flush(&self) -> Result<(), DbError>;
 }
 
-fn is_marked_paid(orders: &Tree, order: String) -> Result<bool, DbError> {
-    let order_key = order.encode();
-    if let Some(order_info) = orders.get(order_key)? {
-        let order_info = OrderInfo::decode(&mut &order_info[..])?;
+/// SCALE-encoded records in sled trees
+struct SledStorage {
+    database: Db,
+    orders: Tree,
+    transactions: Tree,
+    pending_transactions: Tree,
+    accounts: Tree,
+    webhooks: Tree,
+    subscriptions: Tree,
+    order_history: Tree,
+}
 
-        Ok(order_info.payment_status == PaymentStatus::Paid)
-    } else {
-        Err(DbError::OrderNotFound(order))
+impl SledStorage {
+    /// Opens the trees of the database as they are, without migrating them.
+    fn new(database: Db) -> Result<Self, DbError> {
+        Ok(Self {
+            orders: database
+                .open_tree(ORDERS_TABLE)
+                .map_err(DbError::DbStartError)?,
+            transactions: database
+                .open_tree(TRANSACTIONS)
+                .map_err(DbError::DbStartError)?,
+            pending_transactions: database
+                .open_tree(PENDING_TRANSACTIONS)
+                .map_err(DbError::DbStartError)?,
+            accounts: database
+                .open_tree(ACCOUNTS)
+                .map_err(DbError::DbStartError)?,
+            webhooks: database
+                .open_tree(WEBHOOKS)
+                .map_err(DbError::DbStartError)?,
+            subscriptions: database
+                .open_tree(SUBSCRIPTIONS)
+                .map_err(DbError::DbStartError)?,
+            order_history: database
+                .open_tree(ORDER_HISTORY)
+                .map_err(DbError::DbStartError)?,
+            database,
+        })
     }
-}
 
-fn mark_withdrawn(order: String, orders: &Tree) -> Result<(), DbError> {
-    let order_key = order.encode();
-    if let Some(order_info) = orders.get(order_key)? {
-        let mut order_info = OrderInfo::decode(&mut &order_info[..])?;
-        if order_info.payment_status == PaymentStatus::Paid {
-            if order_info.withdrawal_status == WithdrawalStatus::Waiting {
-                order_info.withdrawal_status = WithdrawalStatus::Completed;
-                orders.insert(order.encode(), order_info.encode())?;
-                Ok(())
-            } else {
-                Err(DbError::WithdrawalWasAttempted(order))
-            }
+    /// Opens the database and brings it up to [`DB_VERSION`].
+    fn open(path_option: Option<String>) -> Result<Self, DbError> {
+        let database = if let Some(path) = path_option {
+            tracing::info!("Creating/Opening the database at {path:?}.");
+
+            sled::open(path).map_err(DbError::DbStartError)?
         } else {
-            Err(DbError::NotPaid(order))
+            // Temporary databases live in the memory until they grow too big, and are removed
+            // from the disk as soon as they're dropped.
+            sled::Config::new()
+                .temporary(true)
+                .open()
+                .map_err(DbError::DbStartError)?
+        };
+        let storage = Self::new(database)?;
+
+        migrate(&storage.database, &storage.tables())?;
+
+        // Orders saved before the reverse index was introduced have no records in it.
+        if storage.accounts.is_empty() && !storage.orders.is_empty() {
+            tracing::info!("Indexing payment accounts of saved orders.");
+
+            index_accounts(&storage.orders, &storage.accounts)?;
         }
-    } else {
+
+        Ok(storage)
+    }
+
+    fn tables(&self) -> Tables<'_> {
+        Tables {
+            orders: &self.orders,
+            transactions: &self.transactions,
+            pending_transactions: &self.pending_transactions,
+        }
+    }
+
+    /// Keys and values of the entries appended after the ones already saved in the history of the
+    /// order.
+    fn history_records(
+        &self,
+        order_key: &[u8],
+        entries: &[OrderHistoryEntry],
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let saved = self.order_history.scan_prefix(order_key).count();
+
+        (saved as u64..)
+            .zip(entries)
+            .map(|(position, entry)| {
+                let mut key = order_key.to_vec();
+
+                key.extend(position.to_be_bytes());
+
+                (key, entry.encode())
+            })
+            .collect()
+    }
+}
+
+/// A batch sends a transfer to each recipient, and the transfer events tell which is which.
+fn pending_transaction_key(order: &str, tx: &TransactionInfoDb) -> Vec<u8> {
+    (order, &tx.transaction_bytes, &tx.inner.recipient).encode()
+}
+
+impl Storage for SledStorage {
+    fn order(&self, order: &str) -> Result<Option<OrderInfo>, DbError> {
+        self.orders
+            .get(order.encode())?
+            .map(|order_encoded| OrderInfo::decode(&mut &order_encoded[..]))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn for_each_order(
+        &self,
+        after: Option<&str>,
+        visit: &mut VisitOrder<'_>,
+    ) -> Result<(), DbError> {
+        let start = after.map_or(Bound::Unbounded, |cursor| Bound::Excluded(cursor.encode()));
+
+        for record in self.orders.range((start, Bound::Unbounded)) {
+            let (order_key, order_encoded) = record?;
+            let order = String::decode(&mut &order_key[..])?;
+
+            if visit(order, OrderInfo::decode(&mut &order_encoded[..])?)?.is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_order(
+        &self,
+        order: &str,
+        order_info: &OrderInfo,
+        account: Account,
+    ) -> Result<(), DbError> {
+        let order_key = order.encode();
+
+        self.orders.insert(&order_key, order_info.encode())?;
+        self.accounts.insert(account, order_key)?;
+
+        Ok(())
+    }
+
+    fn update_order(&self, order: &str, order_info: &OrderInfo) -> Result<(), DbError> {
+        self.orders.insert(order.encode(), order_info.encode())?;
+
+        Ok(())
+    }
+
+    fn order_by_account(&self, account: &Account) -> Result<Option<String>, DbError> {
+        self.accounts
+            .get(account)?
+            .map(|order_key| String::decode(&mut &order_key[..]))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn transactions(&self, order: &str) -> Result<Vec<TransactionInfoDb>, DbError> {
+        let order_key = order.encode();
+
+        self.transactions
+            .scan_prefix(&order_key)
+            .map(|result| {
+                let (k, v) = result?;
+                let (_order_key, block_number, position_in_block, transfer_index) =
+                    <(String, BlockNumber, ExtrinsicIndex, TransferIndex)>::decode(
+                        &mut k.as_ref(),
+                    )?;
+                let mut tx = TransactionInfoDb::decode(&mut v.as_ref())?;
+
+                tx.inner.finalized_tx = Some(FinalizedTxDb {
+                    block_number,
+                    position_in_block,
+                    transfer_index,
+                });
+
+                Ok(tx)
+            })
+            .chain(
+                self.pending_transactions
+                    .scan_prefix(&order_key)
+                    .map(|result| {
+                        let (k, v) = result?;
+                        let (_order_key, transaction_bytes, _recipient) =
+                            <(String, String, String)>::decode(&mut k.as_ref())?;
+
+                        Ok(TransactionInfoDb {
+                            transaction_bytes,
+                            inner: TransactionInfoDbInner::decode(&mut v.as_ref())?,
+                        })
+                    }),
+            )
+            .collect()
+    }
+
+    fn pending_transaction(
+        &self,
+        order: &str,
+        tx: &TransactionInfoDb,
+    ) -> Result<Option<TransactionInfoDbInner>, DbError> {
+        self.pending_transactions
+            .get(pending_transaction_key(order, tx))?
+            .map(|tx_encoded| TransactionInfoDbInner::decode(&mut &tx_encoded[..]))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn save_pending_transaction(&self, order: &str, tx: &TransactionInfoDb) -> Result<(), DbError> {
+        self.pending_transactions
+            .insert(pending_transaction_key(order, tx), tx.inner.encode())?;
+
+        Ok(())
+    }
+
+    fn finalize_transaction(
+        &self,
+        order: &str,
+        finalized_tx: &FinalizedTxDb,
+        tx: &TransactionInfoDb,
+    ) -> Result<(), DbError> {
+        self.pending_transactions
+            .remove(pending_transaction_key(order, tx))?;
+        self.transactions.insert(
+            (
+                order,
+                finalized_tx.block_number,
+                finalized_tx.position_in_block,
+                finalized_tx.transfer_index,
+            )
+                .encode(),
+            tx.encode(),
+        )?;
+
+        Ok(())
+    }
+
+    fn append_history(&self, order: &str, entries: &[OrderHistoryEntry]) -> Result<(), DbError> {
+        for (key, entry) in self.history_records(&order.encode(), entries) {
+            self.order_history.insert(key, entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn save_change(
+        &self,
+        order: &str,
+        order_info: &OrderInfo,
+        entries: &[OrderHistoryEntry],
+    ) -> Result<(), DbError> {
+        let order_key = order.encode();
+        let history = self.history_records(&order_key, entries);
+
+        (&self.orders, &self.order_history)
+            .transaction(|(orders, order_history)| {
+                orders.insert(order_key.as_slice(), order_info.encode())?;
+
+                for (key, entry) in &history {
+                    order_history.insert(key.as_slice(), entry.as_slice())?;
+                }
+
+                Ok::<_, ConflictableTransactionError<DatabaseError>>(())
+            })
+            .map_err(|error| match error {
+                TransactionError::Abort(e) | TransactionError::Storage(e) => {
+                    DbError::DbInternalError(e)
+                }
+            })
+    }
+
+    fn history(&self, order: &str) -> Result<Vec<OrderHistoryEntry>, DbError> {
+        self.order_history
+            .scan_prefix(order.encode())
+            .map(|record| {
+                let (_, entry_encoded) = record?;
+
+                OrderHistoryEntry::decode(&mut &entry_encoded[..]).map_err(Into::into)
+            })
+            .collect()
+    }
+
+    fn subscription(&self, order: &str) -> Result<Option<Vec<OrderEvent>>, DbError> {
+        self.subscriptions
+            .get(order.encode())?
+            .map(|events| Vec::decode(&mut &events[..]))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn subscribe(&self, order: &str, events: &[OrderEvent]) -> Result<(), DbError> {
+        self.subscriptions.insert(order.encode(), events.encode())?;
+
+        Ok(())
+    }
+
+    fn next_webhook_id(&self) -> Result<WebhookId, DbError> {
+        self.database.generate_id().map_err(Into::into)
+    }
+
+    fn webhook(&self, id: WebhookId) -> Result<Option<WebhookInfo>, DbError> {
+        self.webhooks
+            .get(id.to_be_bytes())?
+            .map(|webhook_encoded| WebhookInfo::decode(&mut &webhook_encoded[..]))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn webhooks(&self) -> Result<Vec<WebhookInfo>, DbError> {
+        self.webhooks
+            .iter()
+            .map(|record| {
+                let (_, webhook_encoded) = record?;
+
+                WebhookInfo::decode(&mut &webhook_encoded[..]).map_err(Into::into)
+            })
+            .collect()
+    }
+
+    fn save_webhook(&self, webhook: &WebhookInfo) -> Result<(), DbError> {
+        self.webhooks
+            .insert(webhook.id.to_be_bytes(), webhook.encode())?;
+
+        Ok(())
+    }
+
+    fn server_info(&self) -> Result<Option<ServerInfo>, DbError> {
+        let Some(server_info_data) = self
+            .database
+            .open_tree(SERVER_INFO_TABLE)?
+            .get(SERVER_INFO_ID)?
+        else {
+            return Ok(None);
+        };
+
+        serde_json::from_slice(&server_info_data)
+            .map(Some)
+            .map_err(|e| DbError::DeserializationError(e.to_string()))
+    }
+
+    fn save_server_info(&self, server_info: &ServerInfo) -> Result<(), DbError> {
+        self.database.open_tree(SERVER_INFO_TABLE)?.insert(
+            SERVER_INFO_ID,
+            serde_json::to_vec(server_info)
+                .map_err(|e| DbError::SerializationError(e.to_string()))?,
+        )?;
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), DbError> {
+        self.database.flush()?;
+
+        Ok(())
+    }
+}
+
+fn calculate_death_ts(account_lifetime: Timestamp) -> Timestamp {
+    let start = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    Timestamp(start + account_lifetime.0)
+}
+
+#[expect(clippy::too_many_arguments)]
+fn create_order(
+    order: &str,
+    query: OrderQuery,
+    properties: CurrencyProperties,
+    payment_account: String,
+    recipient: String,
+    policy: ModificationPolicy,
+    storage: &dyn Storage,
+    account_lifetime: Timestamp,
+) -> Result<OrderCreateResponse, DbError> {
+    let currency = properties.info(query.currency.clone());
+    let old_order_option = read_order(order, storage)?;
+
+    if let Some(old_order_info) = &old_order_option {
+        if old_order_info.merchant != query.merchant {
+            return Ok(OrderCreateResponse::Foreign);
+        }
+
+        if let Some(reason) = modification_rejection(policy, old_order_info, &properties, &query) {
+            return Ok(OrderCreateResponse::Rejected(
+                old_order_info.clone(),
+                reason,
+            ));
+        }
+    }
+
+    if let Some(events) = &query.events {
+        // A paid order can't be modified, so its subscription stays intact.
+        if old_order_option
+            .as_ref()
+            .is_none_or(|old_order_info| old_order_info.payment_status != PaymentStatus::Paid)
+        {
+            storage.subscribe(order, events)?;
+        }
+    }
+
+    Ok(if let Some(old_order_info) = old_order_option {
+        match old_order_info.payment_status {
+            PaymentStatus::Pending | PaymentStatus::Underpaid => {
+                let death = calculate_death_ts(account_lifetime);
+                let mut order_info = old_order_info.clone();
+                // Transactions are saved separately, and the watcher is registered anew with the
+                // modified order, which needs its payers.
+                let transactions = mem::take(&mut order_info.transactions);
+
+                order_info.death = death;
+                // The same account in the address format of the new chain.
+                order_info.payment_account =
+                    AccountId32(payment_account_key(&old_order_info.payment_account)?)
+                        .to_base58_string(currency.ss58);
+                order_info.currency = currency;
+                order_info.amount = query.amount;
+
+                if let Some(splits) = query.splits {
+                    order_info.splits = splits;
+                }
+
+                if let Some(callback) = query.callback {
+                    order_info.callback = callback;
+                }
+
+                save_change(
+                    storage,
+                    order,
+                    &old_order_info,
+                    &order_info,
+                    ChangeOrigin::API,
+                )?;
+                order_info.transactions = transactions;
+                OrderCreateResponse::Modified(order_info, old_order_info.currency)
+            }
+            PaymentStatus::Paid | PaymentStatus::TimedOut => {
+                OrderCreateResponse::Collision(old_order_info)
+            }
+        }
+    } else {
+        let death = calculate_death_ts(account_lifetime);
+        let account = payment_account_key(&payment_account)?;
+        let order_info_new = OrderInfo::new(
+            query,
+            currency,
+            payment_account,
+            recipient,
+            Timestamp::now(),
+            death,
+        );
+
+        storage.insert_order(order, &order_info_new, account)?;
+        record_history(storage, order, &order_info_new, ChangeOrigin::API)?;
+        OrderCreateResponse::New(order_info_new)
+    })
+}
+
+/// Tells why the policy doesn't allow modifying the order awaiting payment as requested.
+fn modification_rejection(
+    policy: ModificationPolicy,
+    order_info: &OrderInfo,
+    properties: &CurrencyProperties,
+    query: &OrderQuery,
+) -> Option<String> {
+    if !matches!(
+        order_info.payment_status,
+        PaymentStatus::Pending | PaymentStatus::Underpaid
+    ) {
+        return None;
+    }
+
+    let currency_changed = query.currency != order_info.currency.currency;
+
+    if currency_changed {
+        // The received funds would be left behind in the previous currency.
+        if *order_info.received != 0 {
+            return Some("Currency of an order that has received funds can't be changed".into());
+        }
+
+        if !policy.allow_currency_change {
+            return Some("Currency of an existing order can't be changed".into());
+        }
+    }
+
+    if query.amount < order_info.amount && !policy.allow_amount_decrease {
+        return Some("Amount of an existing order can't be decreased".into());
+    }
+
+    // Splits given with the modification are validated by the API, while the saved ones are kept
+    // as they are, so they must still fit.
+    if query.splits.is_none() {
+        // Exact amounts are in the units of the previous currency.
+        if currency_changed
+            && order_info
+                .splits
+                .iter()
+                .any(|split| matches!(split.share, SplitShare::Fixed(_)))
+        {
+            return Some(
+                "Splits with exact amounts must be given again to change the currency".into(),
+            );
+        }
+
+        if let Some(reason) = splits_rejection(&order_info.splits, query.amount, properties) {
+            return Some(format!(
+                "Saved splits don't fit the modified order: {reason}"
+            ));
+        }
+    }
+
+    None
+}
+
+/// Overwrites the saved order and appends the changed fields to its history.
+fn save_change(
+    storage: &dyn Storage,
+    order: &str,
+    previous: &OrderInfo,
+    order_info: &OrderInfo,
+    origin: ChangeOrigin,
+) -> Result<(), DbError> {
+    let entries = history_entries(Some(previous), order_info, origin)?;
+
+    storage.save_change(order, order_info, &entries)
+}
+
+/// Appends all fields of a new order to its history.
+fn record_history(
+    storage: &dyn Storage,
+    order: &str,
+    order_info: &OrderInfo,
+    origin: ChangeOrigin,
+) -> Result<(), DbError> {
+    storage.append_history(order, &history_entries(None, order_info, origin)?)
+}
+
+/// History entries of the fields that differ from the previous version of the order. A new order
+/// has no previous version, so all of its fields are listed.
+fn history_entries(
+    previous: Option<&OrderInfo>,
+    order_info: &OrderInfo,
+    origin: ChangeOrigin,
+) -> Result<Vec<OrderHistoryEntry>, DbError> {
+    let timestamp = Timestamp::now();
+    let previous_values = previous.map(history_values).transpose()?;
+    let mut entries = Vec::new();
+
+    for (field, new) in history_values(order_info)? {
+        let previous_value = previous_values.as_ref().and_then(|values| {
+            values
+                .iter()
+                .find(|(previous_field, _)| *previous_field == field)
+                .map(|(_, value)| value.clone())
+        });
+
+        if previous_value.as_ref() == Some(&new) {
+            continue;
+        }
+
+        entries.push(OrderHistoryEntry {
+            timestamp,
+            source: origin.source,
+            block_number: origin.block_number,
+            field,
+            previous: previous_value,
+            new,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Values of the order fields that its history follows, as they're shown in the API.
+fn history_values(order_info: &OrderInfo) -> Result<Vec<(OrderField, String)>, DbError> {
+    let decimals = order_info.currency.decimals;
+
+    Ok(vec![
+        (OrderField::PaymentStatus, text(&order_info.payment_status)?),
+        (
+            OrderField::WithdrawalStatus,
+            text(&order_info.withdrawal_status)?,
+        ),
+        (OrderField::Amount, order_info.amount.format(decimals)),
+        (OrderField::Currency, order_info.currency.currency.clone()),
+        (OrderField::Death, order_info.death.0.to_string()),
+        (OrderField::Received, order_info.received.format(decimals)),
+    ])
+}
+
+fn order_history(
+    order: &str,
+    storage: &dyn Storage,
+) -> Result<Option<Vec<OrderHistoryEntry>>, DbError> {
+    if storage.order(order)?.is_none() {
+        return Ok(None);
+    }
+
+    storage.history(order).map(Some)
+}
+
+fn read_order(key: &str, storage: &dyn Storage) -> Result<Option<OrderInfo>, DbError> {
+    let Some(mut order) = storage.order(key)? else {
+        return Ok(None);
+    };
+
+    order.transactions = order_transactions(key, storage)?;
+
+    Ok(order.into())
+}
+
+/// Orders that await payment. A record that can't be decoded fails the whole listing instead of
+/// being skipped, so the order isn't silently left unwatched.
+fn active_orders(storage: &dyn Storage) -> Result<Vec<(String, OrderInfo)>, DbError> {
+    let mut active = Vec::new();
+
+    storage.for_each_order(None, &mut |order, order_info| {
+        if matches!(
+            order_info.payment_status,
+            PaymentStatus::Pending | PaymentStatus::Underpaid
+        ) {
+            active.push((order, order_info));
+        }
+
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    Ok(active)
+}
+
+fn all_orders(storage: &dyn Storage) -> Result<Vec<(String, OrderInfo)>, DbError> {
+    let mut all = Vec::new();
+
+    storage.for_each_order(None, &mut |order, mut order_info| {
+        order_info.transactions = order_transactions(&order, storage)?;
+        all.push((order, order_info));
+
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    Ok(all)
+}
+
+fn list_orders(query: &OrderListQuery, storage: &dyn Storage) -> Result<OrderList, DbError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let mut page: Vec<OrderListEntry> = Vec::new();
+    let mut next_cursor = None;
+
+    storage.for_each_order(query.cursor.as_deref(), &mut |order, mut order_info| {
+        if !order_matches(query, &order_info) {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        // There's at least one more matching order, so the page isn't the last one.
+        if page.len() >= limit {
+            next_cursor = page.last().map(|entry| entry.order.clone());
+
+            return Ok(ControlFlow::Break(()));
+        }
+
+        order_info.transactions = order_transactions(&order, storage)?;
+
+        page.push(OrderListEntry { order, order_info });
+
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    Ok(OrderList {
+        orders: page,
+        next_cursor,
+    })
+}
+
+/// Orders saved before their creation time was recorded don't have it, and they don't match any
+/// creation time filter.
+fn order_matches(query: &OrderListQuery, order_info: &OrderInfo) -> bool {
+    let created = order_info.created.map(|created| created.0);
+    let death = order_info.death.0;
+
+    query
+        .payment_status
+        .as_ref()
+        .is_none_or(|status| *status == order_info.payment_status)
+        && query
+            .withdrawal_status
+            .as_ref()
+            .is_none_or(|status| *status == order_info.withdrawal_status)
+        && query
+            .currency
+            .as_ref()
+            .is_none_or(|currency| *currency == order_info.currency.currency)
+        && query
+            .merchant
+            .as_ref()
+            .is_none_or(|merchant| Some(merchant) == order_info.merchant.as_ref())
+        && query
+            .created_from
+            .is_none_or(|from| created.is_some_and(|at| at >= from.0))
+        && query
+            .created_to
+            .is_none_or(|to| created.is_some_and(|at| at <= to.0))
+        && query.death_from.is_none_or(|from| death >= from.0)
+        && query.death_to.is_none_or(|to| death <= to.0)
+}
+
+fn order_transactions(order: &str, storage: &dyn Storage) -> Result<Vec<TransactionInfo>, DbError> {
+    Ok(storage
+        .transactions(order)?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+/// Step of the schema migration that brings records from the previous version to its own one
+struct Migration {
+    version: Version,
+    description: &'static str,
+    rewrite: fn(&Tables<'_>) -> Result<Rewrites, DbError>,
+}
+
+/// Registry of migrations in the order they're applied. The last one must bring the database to
+/// [`DB_VERSION`].
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "store amounts as exact integer balances instead of floats and add the creation time to orders",
+        rewrite: to_v1,
+    },
+    Migration {
+        version: 2,
+        description: "add the received amount to orders",
+        rewrite: to_v2,
+    },
+    Migration {
+        version: 3,
+        description: "key transactions by their transfers",
+        rewrite: to_v3,
+    },
+    Migration {
+        version: 4,
+        description: "add the merchant and the recipient to orders",
+        rewrite: to_v4,
+    },
+    Migration {
+        version: 5,
+        description: "add payout splits to orders",
+        rewrite: to_v5,
+    },
+];
+
+/// Trees that migrations rewrite
+struct Tables<'a> {
+    orders: &'a Tree,
+    transactions: &'a Tree,
+    pending_transactions: &'a Tree,
+}
+
+/// A record rewritten by a migration, possibly under a new key
+struct Rewrite {
+    old_key: IVec,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Rewrite {
+    fn in_place(key: IVec, value: Vec<u8>) -> Self {
+        Self {
+            key: key.to_vec(),
+            old_key: key,
+            value,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Rewrites {
+    orders: Vec<Rewrite>,
+    transactions: Vec<Rewrite>,
+    pending_transactions: Vec<Rewrite>,
+}
+
+/// Outcome of a single migration step
+pub struct MigrationReport {
+    pub version: Version,
+    pub description: &'static str,
+    pub rewritten_records: usize,
+}
+
+fn stored_version(database: &Db) -> Result<Version, DbError> {
+    Ok(database
+        .get(DB_VERSION_KEY)
+        .map_err(DbError::DbStartError)?
+        .map(|encoded| Version::decode(&mut &encoded[..]))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Brings records saved by older versions of the daemon up to [`DB_VERSION`] by applying the
+/// pending migrations one by one.
+///
+/// Each step is written in a single transaction along with its version, so an interrupted
+/// migration leaves the database at the last completed step, and it's resumed from there on the
+/// next start.
+fn migrate(database: &Db, tables: &Tables<'_>) -> Result<Vec<MigrationReport>, DbError> {
+    let version = stored_version(database)?;
+
+    if version > DB_VERSION {
+        return Err(DbError::UnsupportedVersion(version));
+    }
+
+    let mut reports = Vec::new();
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+    {
+        tracing::info!(
+            "Migrating the database to version {}: {}.",
+            migration.version,
+            migration.description
+        );
+
+        let rewrites = (migration.rewrite)(tables)?;
+
+        (
+            &**database,
+            tables.orders,
+            tables.transactions,
+            tables.pending_transactions,
+        )
+            .transaction(
+                |(db_tx, orders_tx, transactions_tx, pending_transactions_tx)| {
+                    for (tree, records) in [
+                        (orders_tx, &rewrites.orders),
+                        (transactions_tx, &rewrites.transactions),
+                        (pending_transactions_tx, &rewrites.pending_transactions),
+                    ] {
+                        for rewrite in records {
+                            if rewrite.old_key != rewrite.key.as_slice() {
+                                tree.remove(&rewrite.old_key)?;
+                            }
+
+                            tree.insert(rewrite.key.as_slice(), rewrite.value.as_slice())?;
+                        }
+                    }
+
+                    db_tx.insert(DB_VERSION_KEY, migration.version.encode())?;
+
+                    Ok::<_, ConflictableTransactionError<DatabaseError>>(())
+                },
+            )
+            .map_err(|error| match error {
+                TransactionError::Abort(e) | TransactionError::Storage(e) => {
+                    DbError::DbStartError(e)
+                }
+            })?;
+
+        reports.push(MigrationReport {
+            version: migration.version,
+            description: migration.description,
+            rewritten_records: rewrites
+                .orders
+                .len()
+                .saturating_add(rewrites.transactions.len())
+                .saturating_add(rewrites.pending_transactions.len()),
+        });
+    }
+
+    Ok(reports)
+}
+
+/// Rehearses the pending migrations of the database at the given path on its temporary copy, and
+/// checks that all records can be read afterwards. The database itself isn't modified.
+pub fn migration_dry_run(path: String) -> Result<Vec<MigrationReport>, DbError> {
+    tracing::info!("Opening the database at {path:?} for a migration dry run.");
+
+    // Opening a missing database would create an empty one.
+    if !Path::new(&path).exists() {
+        return Err(DbError::NotFound(path));
+    }
+
+    let database = sled::open(path).map_err(DbError::DbStartError)?;
+    let copy = sled::Config::new()
+        .temporary(true)
+        .open()
+        .map_err(DbError::DbStartError)?;
+
+    if let Some(version) = database.get(DB_VERSION_KEY)? {
+        copy.insert(DB_VERSION_KEY, version)?;
+    }
+
+    for name in [
+        ORDERS_TABLE,
+        TRANSACTIONS.as_bytes(),
+        PENDING_TRANSACTIONS.as_bytes(),
+    ] {
+        let tree = copy.open_tree(name)?;
+
+        for record in &database.open_tree(name)? {
+            let (key, value) = record?;
+
+            tree.insert(key, value)?;
+        }
+    }
+
+    let storage = SledStorage::new(copy)?;
+    let reports = migrate(&storage.database, &storage.tables())?;
+
+    storage.for_each_order(None, &mut |order, _| {
+        read_order(&order, &storage)?;
+
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    Ok(reports)
+}
+
+/// Records copied by [`migrate_to_sqlite`]
+pub struct CopyReport {
+    pub orders: usize,
+    pub transactions: usize,
+    pub webhooks: usize,
+}
+
+/// Copies all records of the sled database at `sled_path` to a new SQLite database at
+/// `sqlite_path` in a single transaction. The sled database is migrated to [`DB_VERSION`] first,
+/// and otherwise left intact.
+pub fn migrate_to_sqlite(sled_path: String, sqlite_path: String) -> Result<CopyReport, DbError> {
+    if !Path::new(&sled_path).exists() {
+        return Err(DbError::NotFound(sled_path));
+    }
+
+    let source = SledStorage::open(Some(sled_path))?;
+    let target = SqliteStorage::open(Some(sqlite_path.clone()))?;
+
+    if !target.is_empty()? {
+        return Err(DbError::NotEmpty(sqlite_path));
+    }
+
+    target.in_transaction(|sqlite| copy_records(&source, sqlite))
+}
+
+fn copy_records(source: &dyn Storage, target: &dyn Storage) -> Result<CopyReport, DbError> {
+    let mut report = CopyReport {
+        orders: 0,
+        transactions: 0,
+        webhooks: 0,
+    };
+
+    if let Some(server_info) = source.server_info()? {
+        target.save_server_info(&server_info)?;
+    }
+
+    source.for_each_order(None, &mut |order, order_info| {
+        target.insert_order(
+            &order,
+            &order_info,
+            payment_account_key(&order_info.payment_account)?,
+        )?;
+
+        if let Some(events) = source.subscription(&order)? {
+            target.subscribe(&order, &events)?;
+        }
+
+        target.append_history(&order, &source.history(&order)?)?;
+
+        for mut tx in source.transactions(&order)? {
+            if let Some(finalized_tx) = tx.inner.finalized_tx.take() {
+                target.finalize_transaction(&order, &finalized_tx, &tx)?;
+            } else {
+                target.save_pending_transaction(&order, &tx)?;
+            }
+
+            report.transactions = report.transactions.saturating_add(1);
+        }
+
+        report.orders = report.orders.saturating_add(1);
+
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    for webhook in source.webhooks()? {
+        target.save_webhook(&webhook)?;
+
+        report.webhooks = report.webhooks.saturating_add(1);
+    }
+
+    Ok(report)
+}
+
+fn to_v1(tables: &Tables<'_>) -> Result<Rewrites, DbError> {
+    let mut rewrites = Rewrites::default();
+
+    for record in tables.orders {
+        let (key, encoded) = record?;
+        let order_info = v1::OrderInfo::from(v0::OrderInfo::decode(&mut &encoded[..])?);
+
+        rewrites
+            .orders
+            .push(Rewrite::in_place(key, order_info.encode()));
+    }
+
+    for record in tables.transactions {
+        let (key, encoded) = record?;
+        let tx = TransactionInfoDb::from(v0::TransactionInfoDb::decode(&mut &encoded[..])?);
+
+        rewrites
+            .transactions
+            .push(Rewrite::in_place(key, tx.encode()));
+    }
+
+    for record in tables.pending_transactions {
+        let (key, encoded) = record?;
+        let tx =
+            TransactionInfoDbInner::from(v0::TransactionInfoDbInner::decode(&mut &encoded[..])?);
+
+        rewrites
+            .pending_transactions
+            .push(Rewrite::in_place(key, tx.encode()));
+    }
+
+    Ok(rewrites)
+}
+
+fn to_v2(tables: &Tables<'_>) -> Result<Rewrites, DbError> {
+    let mut rewrites = Rewrites::default();
+
+    for record in tables.orders {
+        let (key, encoded) = record?;
+        let order_info = v3::OrderInfo::from(v1::OrderInfo::decode(&mut &encoded[..])?);
+
+        rewrites
+            .orders
+            .push(Rewrite::in_place(key, order_info.encode()));
+    }
+
+    Ok(rewrites)
+}
+
+fn to_v3(tables: &Tables<'_>) -> Result<Rewrites, DbError> {
+    let mut rewrites = Rewrites::default();
+
+    for record in tables.transactions {
+        let (old_key, value) = record?;
+        let (order, block_number, position_in_block) =
+            <(String, BlockNumber, ExtrinsicIndex)>::decode(&mut &old_key[..])?;
+        // Older versions saved only one transfer per extrinsic.
+        let key = (
+            order,
+            block_number,
+            position_in_block,
+            TransferIndex::default(),
+        )
+            .encode();
+
+        rewrites.transactions.push(Rewrite {
+            old_key,
+            key,
+            value: value.to_vec(),
+        });
+    }
+
+    for record in tables.pending_transactions {
+        let (old_key, value) = record?;
+        let (order, transaction_bytes) = <(String, String)>::decode(&mut &old_key[..])?;
+        let recipient = TransactionInfoDbInner::decode(&mut &value[..])?.recipient;
+
+        rewrites.pending_transactions.push(Rewrite {
+            old_key,
+            key: (order, transaction_bytes, recipient).encode(),
+            value: value.to_vec(),
+        });
+    }
+
+    Ok(rewrites)
+}
+
+fn to_v4(tables: &Tables<'_>) -> Result<Rewrites, DbError> {
+    let mut rewrites = Rewrites::default();
+
+    for record in tables.orders {
+        let (key, encoded) = record?;
+        let order_info = v4::OrderInfo::from(v3::OrderInfo::decode(&mut &encoded[..])?);
+
+        rewrites
+            .orders
+            .push(Rewrite::in_place(key, order_info.encode()));
+    }
+
+    Ok(rewrites)
+}
+
+fn to_v5(tables: &Tables<'_>) -> Result<Rewrites, DbError> {
+    let mut rewrites = Rewrites::default();
+
+    for record in tables.orders {
+        let (key, encoded) = record?;
+        let order_info = OrderInfo::from(v4::OrderInfo::decode(&mut &encoded[..])?);
+
+        rewrites
+            .orders
+            .push(Rewrite::in_place(key, order_info.encode()));
+    }
+
+    Ok(rewrites)
+}
+
+fn payment_account_key(payment_account: &str) -> Result<Account, DbError> {
+    AccountId32::from_base58_string(payment_account)
+        .map(|(account, _)| account.0)
+        .map_err(|_| DbError::InvalidPaymentAccount(payment_account.into()))
+}
+
+fn index_accounts(orders: &Tree, accounts: &Tree) -> Result<(), DbError> {
+    for record in orders {
+        let (order_key, order_encoded) = record.map_err(DbError::DbStartError)?;
+        let order_info = OrderInfo::decode(&mut &order_encoded[..])?;
+
+        accounts
+            .insert(payment_account_key(&order_info.payment_account)?, order_key)
+            .map_err(DbError::DbStartError)?;
+    }
+
+    Ok(())
+}
+
+fn read_payment_account(
+    account: &Account,
+    storage: &dyn Storage,
+) -> Result<Option<PublicOrderInfo>, DbError> {
+    let Some(order_key) = storage.order_by_account(account)? else {
+        return Ok(None);
+    };
+    let Some(order) = storage.order(&order_key)? else {
+        return Err(DbError::OrderNotFound(order_key));
+    };
+
+    let mut received_amount = Balance(0);
+
+    for tx in storage.transactions(&order_key)? {
+        if let (Some(_), TxKind::Payment, Amount::Exact(amount)) =
+            (tx.inner.finalized_tx, tx.inner.kind, tx.inner.amount)
+        {
+            received_amount = Balance(received_amount.saturating_add(*amount));
+        }
+    }
+
+    Ok(Some(PublicOrderInfo {
+        payment_account: order.payment_account,
+        payment_status: order.payment_status,
+        amount: order.amount.format(order.currency.decimals),
+        received_amount: Balance((*received_amount).max(*order.received))
+            .format(order.currency.decimals),
+        currency: order.currency,
+        death: order.death,
+    }))
+}
+
+fn record_transaction(
+    storage: &dyn Storage,
+    order: &str,
+    mut tx: TransactionInfoDb,
+) -> Result<(), DbError> {
+    let finalized_info = tx
+        .inner
+        .finalized_tx
+        .take()
+        .zip(tx.inner.finalized_tx_timestamp.as_ref());
+
+    // Search the given transaction among pending ones and update it or move it to finalized
+    // transactions.
+    if let Some(pending_tx) = storage.pending_transaction(order, &tx)? {
+        if let Some((finalized_tx, _finalized_tx_timestamp)) = finalized_info {
+            tracing::debug!("moving pending tx to finalized");
+
+            // Transfer events tell only the direction of a transfer, but the pending transaction
+            // knows why it was sent.
+            tx.inner.kind = pending_tx.kind;
+
+            storage.finalize_transaction(order, &finalized_tx, &tx)?;
+        } else {
+            tracing::debug!("updating pending tx");
+
+            storage.save_pending_transaction(order, &tx)?;
+        }
+    // Save the given finalized transaction.
+    } else if let Some((finalized_tx, _finalized_tx_timestamp)) = finalized_info {
+        tracing::debug!("save finalized tx");
+
+        storage.finalize_transaction(order, &finalized_tx, &tx)?;
+
+    // Save the pending transaction.
+    } else {
+        tracing::debug!("adding pending tx");
+
+        storage.save_pending_transaction(order, &tx)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the paid order along with its transactions, so the payout knows who has paid it.
+fn mark_paid(
+    order: String,
+    origin: ChangeOrigin,
+    storage: &dyn Storage,
+) -> Result<OrderInfo, DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        match previous.payment_status {
+            PaymentStatus::Pending | PaymentStatus::Underpaid => {
+                let mut order_info = previous.clone();
+
+                order_info.payment_status = PaymentStatus::Paid;
+                save_change(storage, &order, &previous, &order_info, origin)?;
+                order_info.transactions = order_transactions(&order, storage)?;
+                Ok(order_info)
+            }
+            PaymentStatus::Paid => Err(DbError::AlreadyPaid(order)),
+            PaymentStatus::TimedOut => Err(DbError::TimedOut(order)),
+        }
+    } else {
+        Err(DbError::OrderNotFound(order))
+    }
+}
+
+fn is_marked_paid(storage: &dyn Storage, order: String) -> Result<bool, DbError> {
+    if let Some(order_info) = storage.order(&order)? {
+        Ok(order_info.payment_status == PaymentStatus::Paid)
+    } else {
+        Err(DbError::OrderNotFound(order))
+    }
+}
+
+fn mark_withdrawn(
+    order: String,
+    origin: ChangeOrigin,
+    storage: &dyn Storage,
+) -> Result<(), DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        match previous.withdrawal_status {
+            WithdrawalStatus::Waiting if previous.payment_status != PaymentStatus::Paid => {
+                Err(DbError::NotPaid(order))
+            }
+            // Forced payouts and sweeps of underpaid orders go through in any payment status.
+            WithdrawalStatus::Waiting | WithdrawalStatus::Forced => {
+                let mut order_info = previous.clone();
+
+                order_info.withdrawal_status = WithdrawalStatus::Completed;
+                save_change(storage, &order, &previous, &order_info, origin)?;
+                Ok(())
+            }
+            // Release of the excess held after the payout of a paid order, which stays as it is.
+            WithdrawalStatus::Completed if previous.payment_status == PaymentStatus::Paid => Ok(()),
+            _ => Err(DbError::WithdrawalWasAttempted(order)),
+        }
+    } else {
+        Err(DbError::OrderNotFound(order))
+    }
+}
+
+fn mark_forced(order: String, origin: ChangeOrigin, storage: &dyn Storage) -> Result<(), DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        // Forced withdrawal is possible in any payment status, it also sends funds that arrived
+        // after the order has timed out to the recipient.
+        if previous.withdrawal_status == WithdrawalStatus::Waiting {
+            let mut order_info = previous.clone();
+
+            order_info.withdrawal_status = WithdrawalStatus::Forced;
+            save_change(storage, &order, &previous, &order_info, origin)?;
+            Ok(())
+        } else {
+            Err(DbError::WithdrawalWasAttempted(order))
+        }
+    } else {
         Err(DbError::OrderNotFound(order))
     }
 }
 
-fn mark_forced(order: String, orders: &Tree) -> Result<(), DbError> {
-    let order_key = order.encode();
-    if let Some(order_info) = orders.get(order_key)? {
-        let mut order_info = OrderInfo::decode(&mut &order_info[..])?;
-        if order_info.payment_status == PaymentStatus::Pending
-            || order_info.payment_status == PaymentStatus::Paid
+fn record_received(
+    order: String,
+    received: Balance,
+    origin: ChangeOrigin,
+    storage: &dyn Storage,
+) -> Result<bool, DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        // The balance drops once the funds are moved out, but the received amount stays.
+        if !matches!(
+            previous.payment_status,
+            PaymentStatus::Pending | PaymentStatus::Underpaid
+        ) || received <= previous.received
         {
-            if order_info.withdrawal_status == WithdrawalStatus::Waiting {
-                order_info.withdrawal_status = WithdrawalStatus::Forced;
-                orders.insert(order.encode(), order_info.encode())?;
+            return Ok(false);
+        }
+
+        let mut order_info = previous.clone();
+
+        order_info.received = received;
+
+        let partial = received < order_info.amount;
+
+        if partial {
+            order_info.payment_status = PaymentStatus::Underpaid;
+        }
+
+        save_change(storage, &order, &previous, &order_info, origin)?;
+
+        Ok(partial)
+    } else {
+        Err(DbError::OrderNotFound(order))
+    }
+}
+
+fn mark_refunded(
+    order: String,
+    origin: ChangeOrigin,
+    storage: &dyn Storage,
+) -> Result<(), DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        if matches!(
+            previous.withdrawal_status,
+            WithdrawalStatus::Waiting | WithdrawalStatus::Failed
+        ) {
+            let mut order_info = previous.clone();
+
+            order_info.withdrawal_status = WithdrawalStatus::Refunded;
+            save_change(storage, &order, &previous, &order_info, origin)?;
+            Ok(())
+        } else {
+            Err(DbError::WithdrawalWasAttempted(order))
+        }
+    } else {
+        Err(DbError::OrderNotFound(order))
+    }
+}
+
+fn mark_timed_out(
+    order: String,
+    origin: ChangeOrigin,
+    storage: &dyn Storage,
+) -> Result<(), DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        match previous.payment_status {
+            PaymentStatus::Pending | PaymentStatus::Underpaid => {
+                let mut order_info = previous.clone();
+
+                order_info.payment_status = PaymentStatus::TimedOut;
+                save_change(storage, &order, &previous, &order_info, origin)?;
                 Ok(())
-            } else {
-                Err(DbError::WithdrawalWasAttempted(order))
             }
-        } else {
-            Err(DbError::NotPaid(order))
+            PaymentStatus::Paid => Err(DbError::AlreadyPaid(order)),
+            PaymentStatus::TimedOut => Err(DbError::TimedOut(order)),
         }
     } else {
         Err(DbError::OrderNotFound(order))
     }
 }
-fn mark_stuck(order: String, orders: &Tree) -> Result<(), DbError> {
-    if let Some(order_info) = orders.get(order.clone())? {
-        let mut order_info = OrderInfo::decode(&mut &order_info[..])?;
-        if order_info.payment_status == PaymentStatus::Paid {
-            if order_info.withdrawal_status == WithdrawalStatus::Waiting {
+
+fn mark_stuck(order: String, origin: ChangeOrigin, storage: &dyn Storage) -> Result<(), DbError> {
+    if let Some(previous) = storage.order(&order)? {
+        match previous.withdrawal_status {
+            // A refund of a timed out order can fail as well.
+            WithdrawalStatus::Waiting
+                if !matches!(
+                    previous.payment_status,
+                    PaymentStatus::Paid | PaymentStatus::TimedOut
+                ) =>
+            {
+                Err(DbError::NotPaid(order))
+            }
+            // A forced payout can fail in any payment status.
+            WithdrawalStatus::Waiting | WithdrawalStatus::Forced => {
+                let mut order_info = previous.clone();
+
                 order_info.withdrawal_status = WithdrawalStatus::Failed;
-                orders.insert(order.encode(), order_info.encode())?;
+                save_change(storage, &order, &previous, &order_info, origin)?;
                 Ok(())
-            } else {
-                Err(DbError::WithdrawalWasAttempted(order))
             }
-        } else {
-            Err(DbError::NotPaid(order))
+            _ => Err(DbError::WithdrawalWasAttempted(order)),
         }
     } else {
         Err(DbError::OrderNotFound(order))
     }
 }
 
-#[derive(Encode, Decode)]
+fn initialize_server_info(storage: &dyn Storage) -> Result<String, DbError> {
+    if let Some(server_info) = storage.server_info()? {
+        return Ok(server_info.instance_id);
+    }
+
+    let mut generator = Generator::default();
+    let server_info = ServerInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        instance_id: generator
+            .next()
+            .unwrap_or_else(|| "unknown-instance".to_string()),
+        debug: false,
+        kalatori_remark: None,
+    };
+
+    storage.save_server_info(&server_info)?;
+
+    Ok(server_info.instance_id)
+}
+
+fn enqueue_webhook(
+    storage: &dyn Storage,
+    order: String,
+    event: OrderEvent,
+    url: String,
+    payload: String,
+) -> Result<WebhookInfo, DbError> {
+    let now = Timestamp::now();
+    let webhook = WebhookInfo {
+        id: storage.next_webhook_id()?,
+        order,
+        event,
+        url,
+        payload,
+        status: WebhookStatus::Pending,
+        created: now,
+        next_attempt: Some(now),
+        attempts: Vec::new(),
+    };
+
+    storage.save_webhook(&webhook)?;
+
+    Ok(webhook)
+}
+
+fn list_webhooks(
+    storage: &dyn Storage,
+    query: &WebhookListQuery,
+) -> Result<Vec<WebhookInfo>, DbError> {
+    Ok(storage
+        .webhooks()?
+        .into_iter()
+        .filter(|webhook| {
+            query
+                .order
+                .as_ref()
+                .is_none_or(|order| *order == webhook.order)
+                && query.status.is_none_or(|status| status == webhook.status)
+        })
+        .collect())
+}
+
+fn redeliver_webhook(storage: &dyn Storage, id: WebhookId) -> Result<WebhookInfo, DbError> {
+    let Some(mut webhook) = storage.webhook(id)? else {
+        return Err(DbError::WebhookNotFound(id));
+    };
+
+    if webhook.status != WebhookStatus::Failed {
+        return Err(DbError::WebhookNotFailed(id));
+    }
+
+    // The failed attempts are dropped, so the webhook is retried as many times as a new one.
+    webhook.status = WebhookStatus::Pending;
+    webhook.next_attempt = Some(Timestamp::now());
+    webhook.attempts.clear();
+    storage.save_webhook(&webhook)?;
+
+    Ok(webhook)
+}
+
+#[derive(Clone, Encode, Decode)]
 pub struct TransactionInfoDbInner {
     pub finalized_tx: Option<FinalizedTxDb>,
     pub finalized_tx_timestamp: Option<String>,
@@ -608,22 +2188,22 @@ pub struct TransactionInfoDbInner {
     pub kind: TxKind,
 }
 
-#[derive(Encode, Decode)]
+#[derive(Clone, Encode, Decode)]
 pub struct TransactionInfoDb {
     pub transaction_bytes: String,
     pub inner: TransactionInfoDbInner,
 }
 
-#[derive(Encode, Decode, Debug)]
-pub enum TxKind {
-    Payment,
-    Withdrawal,
-}
+/// Position of a transfer among the transfers of a payment account in a single extrinsic
+pub type TransferIndex = u32;
 
-#[derive(Encode, Decode)]
+#[derive(Clone, Encode, Decode)]
 pub struct FinalizedTxDb {
     pub block_number: BlockNumber,
     pub position_in_block: ExtrinsicIndex,
+    /// Part of the key only, it isn't saved along with the transaction.
+    #[codec(skip)]
+    pub transfer_index: TransferIndex,
 }
 
 impl From<TransactionInfoDb> for TransactionInfo {
@@ -647,6 +2227,1198 @@ impl From<TransactionInfoDb> for TransactionInfo {
             amount: value.inner.amount,
             currency: value.inner.currency,
             status: value.inner.status,
+            kind: value.inner.kind,
+        }
+    }
+}
+
+/// Records of database version 4 that didn't have payout splits of orders.
+mod v4 {
+    use crate::definitions::{
+        api_v2::{self, CurrencyInfo, PaymentStatus, Timestamp, TransactionInfo, WithdrawalStatus},
+        Balance,
+    };
+    use codec::{Decode, Encode};
+
+    #[derive(Decode, Encode)]
+    pub struct OrderInfo {
+        pub withdrawal_status: WithdrawalStatus,
+        pub payment_status: PaymentStatus,
+        pub amount: Balance,
+        pub currency: CurrencyInfo,
+        pub callback: String,
+        pub transactions: Vec<TransactionInfo>,
+        pub payment_account: String,
+        pub death: Timestamp,
+        pub received: Balance,
+        pub merchant: Option<String>,
+        pub created: Option<Timestamp>,
+        pub recipient: Option<String>,
+    }
+
+    impl From<OrderInfo> for api_v2::OrderInfo {
+        fn from(value: OrderInfo) -> Self {
+            // Older orders were paid out to the merchant recipient as a whole.
+            Self {
+                withdrawal_status: value.withdrawal_status,
+                payment_status: value.payment_status,
+                amount: value.amount,
+                currency: value.currency,
+                callback: value.callback,
+                transactions: value.transactions,
+                payment_account: value.payment_account,
+                death: value.death,
+                received: value.received,
+                merchant: value.merchant,
+                splits: Vec::new(),
+                created: value.created,
+                recipient: value.recipient,
+            }
+        }
+    }
+}
+
+/// Records of database version 3 that didn't have the merchant of orders.
+mod v3 {
+    use crate::definitions::{
+        api_v2::{CurrencyInfo, PaymentStatus, Timestamp, TransactionInfo, WithdrawalStatus},
+        Balance,
+    };
+    use codec::{Decode, Encode};
+
+    #[derive(Decode, Encode)]
+    pub struct OrderInfo {
+        pub withdrawal_status: WithdrawalStatus,
+        pub payment_status: PaymentStatus,
+        pub amount: Balance,
+        pub currency: CurrencyInfo,
+        pub callback: String,
+        pub transactions: Vec<TransactionInfo>,
+        pub payment_account: String,
+        pub death: Timestamp,
+        pub received: Balance,
+        pub created: Option<Timestamp>,
+    }
+
+    impl From<OrderInfo> for super::v4::OrderInfo {
+        fn from(value: OrderInfo) -> Self {
+            // Older orders were all paid to the default recipient, so they use the recipient of
+            // the default merchant profile.
+            Self {
+                withdrawal_status: value.withdrawal_status,
+                payment_status: value.payment_status,
+                amount: value.amount,
+                currency: value.currency,
+                callback: value.callback,
+                transactions: value.transactions,
+                payment_account: value.payment_account,
+                death: value.death,
+                received: value.received,
+                merchant: None,
+                created: value.created,
+                recipient: None,
+            }
+        }
+    }
+}
+
+/// Records of database version 1 that didn't have the received amount of orders.
+mod v1 {
+    use crate::definitions::{
+        api_v2::{CurrencyInfo, PaymentStatus, Timestamp, TransactionInfo, WithdrawalStatus},
+        Balance,
+    };
+    use codec::{Decode, Encode};
+
+    #[derive(Decode, Encode)]
+    pub struct OrderInfo {
+        pub withdrawal_status: WithdrawalStatus,
+        pub payment_status: PaymentStatus,
+        pub amount: Balance,
+        pub currency: CurrencyInfo,
+        pub callback: String,
+        pub transactions: Vec<TransactionInfo>,
+        pub payment_account: String,
+        pub death: Timestamp,
+        pub created: Option<Timestamp>,
+    }
+
+    impl From<OrderInfo> for super::v3::OrderInfo {
+        fn from(value: OrderInfo) -> Self {
+            // Pending orders get their received amount on the next block, and the balance of
+            // others is already gone.
+            Self {
+                withdrawal_status: value.withdrawal_status,
+                payment_status: value.payment_status,
+                amount: value.amount,
+                currency: value.currency,
+                callback: value.callback,
+                transactions: value.transactions,
+                payment_account: value.payment_account,
+                death: value.death,
+                received: Balance(0),
+                created: value.created,
+            }
+        }
+    }
+}
+
+/// Records of database version 0 that stored amounts as floats.
+mod v0 {
+    use crate::definitions::{
+        api_v2::{
+            self, CurrencyInfo, Decimals, FinalizedTx, PaymentStatus, Timestamp, TxKind, TxStatus,
+            WithdrawalStatus,
+        },
+        Balance,
+    };
+    use codec::Decode;
+
+    use super::FinalizedTxDb;
+
+    /// Converts a float amount exactly the way version 0 did before sending it to the chain.
+    fn exact(amount: f64, decimals: Decimals) -> Balance {
+        let planck = (amount * 10f64.powi(decimals.into())).round();
+
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Balance(planck as _)
+    }
+
+    #[derive(Decode)]
+    pub enum Amount {
+        All,
+        Exact(f64),
+    }
+
+    impl Amount {
+        fn migrate(self, decimals: Decimals) -> api_v2::Amount {
+            match self {
+                Amount::All => api_v2::Amount::All,
+                Amount::Exact(amount) => api_v2::Amount::Exact(exact(amount, decimals)),
+            }
+        }
+    }
+
+    #[derive(Decode)]
+    pub struct OrderInfo {
+        withdrawal_status: WithdrawalStatus,
+        payment_status: PaymentStatus,
+        amount: f64,
+        currency: CurrencyInfo,
+        callback: String,
+        transactions: Vec<TransactionInfo>,
+        payment_account: String,
+        death: Timestamp,
+    }
+
+    impl From<OrderInfo> for super::v1::OrderInfo {
+        fn from(value: OrderInfo) -> Self {
+            // Version 0 didn't record the creation time, and it can't be restored from the death
+            // timestamp, as modifications move it.
+            Self {
+                withdrawal_status: value.withdrawal_status,
+                payment_status: value.payment_status,
+                amount: exact(value.amount, value.currency.decimals),
+                currency: value.currency,
+                callback: value.callback,
+                transactions: value.transactions.into_iter().map(Into::into).collect(),
+                payment_account: value.payment_account,
+                death: value.death,
+                created: None,
+            }
+        }
+    }
+
+    #[derive(Decode)]
+    pub struct TransactionInfo {
+        finalized_tx: Option<FinalizedTx>,
+        transaction_bytes: String,
+        sender: String,
+        recipient: String,
+        amount: Amount,
+        currency: CurrencyInfo,
+        status: TxStatus,
+    }
+
+    impl From<TransactionInfo> for api_v2::TransactionInfo {
+        fn from(value: TransactionInfo) -> Self {
+            // Version 0 didn't tell the transaction kind in the API. Saved orders didn't have
+            // transactions anyway, as they're read from their own trees.
+            Self {
+                finalized_tx: value.finalized_tx,
+                transaction_bytes: value.transaction_bytes,
+                sender: value.sender,
+                recipient: value.recipient,
+                amount: value.amount.migrate(value.currency.decimals),
+                currency: value.currency,
+                status: value.status,
+                kind: TxKind::Payment,
+            }
+        }
+    }
+
+    #[derive(Decode)]
+    pub struct TransactionInfoDbInner {
+        finalized_tx: Option<FinalizedTxDb>,
+        finalized_tx_timestamp: Option<String>,
+        sender: String,
+        recipient: String,
+        amount: Amount,
+        currency: CurrencyInfo,
+        status: TxStatus,
+        kind: TxKind,
+    }
+
+    impl From<TransactionInfoDbInner> for super::TransactionInfoDbInner {
+        fn from(value: TransactionInfoDbInner) -> Self {
+            Self {
+                finalized_tx: value.finalized_tx,
+                finalized_tx_timestamp: value.finalized_tx_timestamp,
+                sender: value.sender,
+                recipient: value.recipient,
+                amount: value.amount.migrate(value.currency.decimals),
+                currency: value.currency,
+                status: value.status,
+                kind: value.kind,
+            }
+        }
+    }
+
+    #[derive(Decode)]
+    pub struct TransactionInfoDb {
+        transaction_bytes: String,
+        inner: TransactionInfoDbInner,
+    }
+
+    impl From<TransactionInfoDb> for super::TransactionInfoDb {
+        fn from(value: TransactionInfoDb) -> Self {
+            Self {
+                transaction_bytes: value.transaction_bytes,
+                inner: value.inner.into(),
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        definitions::api_v2::{ChangeSource, PayoutSplit, TokenKind, WebhookAttempt},
+        webhook::{record_attempt, MAX_ATTEMPTS},
+    };
+
+    #[test]
+    fn migration_registry() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(Some(migration.version), u64::try_from(index + 1).ok());
+        }
+
+        assert_eq!(MIGRATIONS.last().map(|last| last.version), Some(DB_VERSION));
+    }
+
+    #[test]
+    fn dry_run_of_missing_database() {
+        let path = std::env::temp_dir().join("kalatori-missing-database");
+        let path_string = path.to_string_lossy().into_owned();
+
+        assert!(matches!(
+            migration_dry_run(path_string),
+            Err(DbError::NotFound(_))
+        ));
+        assert!(!path.exists());
+    }
+
+    fn currency() -> CurrencyInfo {
+        CurrencyInfo {
+            currency: "DOT".into(),
+            chain_name: "polkadot".into(),
+            kind: TokenKind::Native,
+            decimals: 10,
+            rpc_url: String::new(),
+            asset_id: None,
+            ss58: 0,
+        }
+    }
+
+    fn properties() -> CurrencyProperties {
+        CurrencyProperties {
+            chain_name: "polkadot".into(),
+            kind: TokenKind::Native,
+            decimals: 10,
+            rpc_url: String::new(),
+            asset_id: None,
+            ss58: 0,
+            existential_deposit: Balance(1),
+        }
+    }
+
+    /// Order creation request with the defaults of the tests, which only set what they check.
+    struct NewOrder {
+        query: OrderQuery,
+        properties: CurrencyProperties,
+        payment_account: String,
+        recipient: String,
+        policy: ModificationPolicy,
+    }
+
+    impl NewOrder {
+        /// Order of 10 planck in DOT with the payment account and the recipient derived from
+        /// `[1; 32]` and `[0; 32]`.
+        fn new(order: &str) -> Self {
+            Self {
+                query: OrderQuery {
+                    order: order.into(),
+                    amount: Balance(10),
+                    callback: None,
+                    currency: "DOT".into(),
+                    events: None,
+                    merchant: None,
+                    splits: None,
+                },
+                properties: properties(),
+                payment_account: AccountId32([1; 32]).to_base58_string(0),
+                recipient: AccountId32([0; 32]).to_base58_string(42),
+                policy: ModificationPolicy::default(),
+            }
+        }
+
+        fn amount(mut self, amount: u128) -> Self {
+            self.query.amount = Balance(amount);
+            self
+        }
+
+        fn callback(mut self, callback: &str) -> Self {
+            self.query.callback = Some(callback.into());
+            self
+        }
+
+        fn events(mut self, events: Vec<OrderEvent>) -> Self {
+            self.query.events = Some(events);
+            self
+        }
+
+        fn merchant(mut self, merchant: &str) -> Self {
+            self.query.merchant = Some(merchant.into());
+            self
+        }
+
+        fn currency(mut self, currency: &str, properties: CurrencyProperties) -> Self {
+            self.query.currency = currency.into();
+            self.properties = properties;
+            self
+        }
+
+        fn payment_account(mut self, key: u8) -> Self {
+            self.payment_account = AccountId32([key; 32]).to_base58_string(0);
+            self
+        }
+
+        fn recipient(mut self, key: u8) -> Self {
+            self.recipient = AccountId32([key; 32]).to_base58_string(42);
+            self
+        }
+
+        fn post(self, storage: &dyn Storage) -> OrderCreateResponse {
+            let order = self.query.order.clone();
+
+            create_order(
+                &order,
+                self.query,
+                self.properties,
+                self.payment_account,
+                self.recipient,
+                self.policy,
+                storage,
+                Timestamp(1000),
+            )
+            .unwrap()
+        }
+    }
+
+    #[test]
+    fn in_memory() {
+        let first = SledStorage::open(None).unwrap();
+        let second = SledStorage::open(None).unwrap();
+
+        first.subscribe("order", &[OrderEvent::Paid]).unwrap();
+
+        assert!(second.subscription("order").unwrap().is_none());
+    }
+
+    #[test]
+    fn sqlite_copy() {
+        let source = SledStorage::new(sled::Config::new().temporary(true).open().unwrap()).unwrap();
+        let target = SqliteStorage::open(None).unwrap();
+        let payment_account = AccountId32([1; 32]).to_base58_string(0);
+        let finalized_tx = |transfer_index, kind| TransactionInfoDb {
+            transaction_bytes: "0x00".into(),
+            inner: TransactionInfoDbInner {
+                finalized_tx: Some(FinalizedTxDb {
+                    block_number: 1,
+                    position_in_block: 2,
+                    transfer_index,
+                }),
+                finalized_tx_timestamp: Some("timestamp".into()),
+                sender: payment_account.clone(),
+                recipient: format!("recipient {transfer_index}"),
+                amount: Amount::Exact(Balance(u128::MAX)),
+                currency: currency(),
+                status: TxStatus::Finalized,
+                kind,
+            },
+        };
+        let mut pending_tx = finalized_tx(0, TxKind::Refund);
+
+        pending_tx.inner.finalized_tx = None;
+        pending_tx.inner.status = TxStatus::Pending;
+
+        NewOrder::new("order")
+            .amount(1)
+            .callback("https://example.com/callback")
+            .events(vec![OrderEvent::Paid])
+            .post(&source);
+        record_transaction(&source, "order", pending_tx).unwrap();
+        record_transaction(&source, "order", finalized_tx(0, TxKind::Withdrawal)).unwrap();
+        record_transaction(&source, "order", finalized_tx(1, TxKind::Payment)).unwrap();
+        enqueue_webhook(
+            &source,
+            "order".into(),
+            OrderEvent::Paid,
+            "https://example.com/callback".into(),
+            "{}".into(),
+        )
+        .unwrap();
+
+        let report = copy_records(&source, &target).unwrap();
+
+        assert_eq!(
+            (report.orders, report.transactions, report.webhooks),
+            (1, 2, 1)
+        );
+
+        let copied = read_order("order", &target).unwrap().unwrap();
+
+        assert_eq!(
+            read_order("order", &source).unwrap().unwrap().encode(),
+            copied.encode()
+        );
+        assert_eq!(copied.transactions[0].kind, TxKind::Refund);
+        assert_eq!(
+            target.order_by_account(&[1; 32]).unwrap().as_deref(),
+            Some("order")
+        );
+        assert_eq!(
+            target.subscription("order").unwrap(),
+            Some(vec![OrderEvent::Paid])
+        );
+        assert_eq!(
+            source.webhooks().unwrap().encode(),
+            target.webhooks().unwrap().encode()
+        );
+        assert_eq!(target.next_webhook_id().unwrap(), 1);
+        assert_eq!(
+            source.history("order").unwrap().encode(),
+            target.history("order").unwrap().encode()
+        );
+    }
+
+    #[test]
+    fn history_of_changes() {
+        let sled = SledStorage::open(None).unwrap();
+        let sqlite = SqliteStorage::open(None).unwrap();
+
+        for storage in [&sled as &dyn Storage, &sqlite] {
+            NewOrder::new("order").amount(10_000_000_000).post(storage);
+            NewOrder::new("order").amount(20_000_000_000).post(storage);
+            mark_paid("order".into(), ChangeOrigin::tracker(Some(5)), storage).unwrap();
+
+            let history = order_history("order", storage).unwrap().unwrap();
+            let changes: Vec<_> = history
+                .iter()
+                .skip(6)
+                .map(|entry| {
+                    (
+                        entry.source,
+                        entry.block_number,
+                        entry.field,
+                        entry.previous.as_deref(),
+                        entry.new.as_str(),
+                    )
+                })
+                .collect();
+
+            assert!(history[..6]
+                .iter()
+                .all(|entry| entry.source == ChangeSource::Api && entry.previous.is_none()));
+            // The death timestamp of the modified order may or may not change within a millisecond.
+            assert!(changes.contains(&(
+                ChangeSource::Api,
+                None,
+                OrderField::Amount,
+                Some("1"),
+                "2"
+            )));
+            assert_eq!(
+                changes.last(),
+                Some(&(
+                    ChangeSource::Tracker,
+                    Some(5),
+                    OrderField::PaymentStatus,
+                    Some("pending"),
+                    "paid"
+                ))
+            );
+            assert!(order_history("unknown", storage).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn modification_policy() {
+        let storage = SledStorage::open(None).unwrap();
+        let post = |amount, (currency, properties): (&str, CurrencyProperties), policy| {
+            let mut order = NewOrder::new("order")
+                .amount(amount)
+                .currency(currency, properties);
+
+            order.policy = policy;
+            order.post(&storage)
+        };
+        let dot = || ("DOT", properties());
+        let usdc = || {
+            (
+                "USDC",
+                CurrencyProperties {
+                    chain_name: "statemint".into(),
+                    ..properties()
+                },
+            )
+        };
+        let strict = ModificationPolicy {
+            allow_currency_change: false,
+            allow_amount_decrease: false,
+        };
+
+        post(10, dot(), strict);
+
+        assert!(matches!(
+            post(5, dot(), strict),
+            OrderCreateResponse::Rejected(..)
+        ));
+        assert!(matches!(
+            post(10, usdc(), strict),
+            OrderCreateResponse::Rejected(..)
+        ));
+        assert!(matches!(
+            post(20, dot(), strict),
+            OrderCreateResponse::Modified(_, previous) if previous.currency == "DOT"
+        ));
+
+        let ksm = (
+            "KSM",
+            CurrencyProperties {
+                chain_name: "kusama".into(),
+                ss58: 2,
+                ..properties()
+            },
+        );
+
+        // The payment account is shown in the address format of the new chain.
+        assert!(matches!(
+            post(20, ksm, ModificationPolicy::default()),
+            OrderCreateResponse::Modified(order_info, _)
+                if order_info.payment_account == AccountId32([1; 32]).to_base58_string(2)
+        ));
+
+        record_received(
+            "order".into(),
+            Balance(1),
+            ChangeOrigin::tracker(None),
+            &storage,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            post(20, usdc(), ModificationPolicy::default()),
+            OrderCreateResponse::Rejected(..)
+        ));
+    }
+
+    #[test]
+    fn modified_callback() {
+        let storage = SledStorage::open(None).unwrap();
+        let payment_account = AccountId32([1; 32]).to_base58_string(0);
+        let post = |order: NewOrder| {
+            let (OrderCreateResponse::New(order_info)
+            | OrderCreateResponse::Modified(order_info, _)) = order.post(&storage)
+            else {
+                panic!("the order isn't created or modified");
+            };
+
+            order_info
+        };
+
+        post(NewOrder::new("order").callback("https://example.com/old"));
+        record_transaction(
+            &storage,
+            "order",
+            TransactionInfoDb {
+                transaction_bytes: "0x00".into(),
+                inner: TransactionInfoDbInner {
+                    finalized_tx: Some(FinalizedTxDb {
+                        block_number: 1,
+                        position_in_block: 2,
+                        transfer_index: 0,
+                    }),
+                    finalized_tx_timestamp: Some("timestamp".into()),
+                    sender: "sender".into(),
+                    recipient: payment_account.clone(),
+                    amount: Amount::Exact(Balance(1)),
+                    currency: currency(),
+                    status: TxStatus::Finalized,
+                    kind: TxKind::Payment,
+                },
+            },
+        )
+        .unwrap();
+
+        let modified = post(NewOrder::new("order").callback("https://example.com/new"));
+
+        assert_eq!(modified.callback, "https://example.com/new");
+        assert_eq!(modified.transactions.len(), 1);
+        assert_eq!(
+            storage.order("order").unwrap().unwrap().callback,
+            "https://example.com/new"
+        );
+
+        // A modification without the callback keeps the saved one.
+        assert_eq!(
+            post(NewOrder::new("order")).callback,
+            "https://example.com/new"
+        );
+    }
+
+    #[test]
+    fn saved_splits() {
+        let storage = SledStorage::open(None).unwrap();
+        let split = |share| PayoutSplit {
+            recipient: AccountId32([2; 32]).to_base58_string(0),
+            share,
+        };
+        let post = |amount, currency: &str, splits| {
+            let mut order = NewOrder::new("order").amount(amount).currency(
+                currency,
+                CurrencyProperties {
+                    existential_deposit: Balance(5),
+                    ..properties()
+                },
+            );
+
+            order.query.splits = splits;
+            order.post(&storage)
+        };
+
+        post(
+            100,
+            "DOT",
+            Some(vec![
+                split(SplitShare::Fixed(Balance(40))),
+                split(SplitShare::Percent(1000)),
+            ]),
+        );
+
+        // Exact amounts of the saved splits are in the units of the previous currency.
+        assert!(matches!(
+            post(100, "USDC", None),
+            OrderCreateResponse::Rejected(..)
+        ));
+        // The saved splits exceed the decreased amount.
+        assert!(matches!(
+            post(30, "DOT", None),
+            OrderCreateResponse::Rejected(..)
+        ));
+        // The percentage split of the decreased amount is below the existential deposit.
+        assert!(matches!(
+            post(45, "DOT", None),
+            OrderCreateResponse::Rejected(..)
+        ));
+        assert!(matches!(
+            post(200, "DOT", None),
+            OrderCreateResponse::Modified(order_info, _) if order_info.splits.len() == 2
+        ));
+        // New splits replace the saved ones.
+        assert!(matches!(
+            post(
+                100,
+                "USDC",
+                Some(vec![split(SplitShare::Percent(1000))])
+            ),
+            OrderCreateResponse::Modified(order_info, _) if order_info.splits.len() == 1
+        ));
+    }
+
+    #[test]
+    fn order_recipient() {
+        let post = |storage: &dyn Storage, recipient: u8| {
+            NewOrder::new("order")
+                .merchant("shop")
+                .recipient(recipient)
+                .post(storage);
+
+            storage.order("order").unwrap().unwrap().recipient
+        };
+        let created_for = Some(AccountId32([2; 32]).to_base58_string(42));
+
+        for storage in [
+            Box::new(SledStorage::open(None).unwrap()) as Box<dyn Storage>,
+            Box::new(SqliteStorage::open(None).unwrap()),
+        ] {
+            assert_eq!(post(&*storage, 2), created_for);
+            // A changed merchant recipient applies to new orders only, since the payment account
+            // is derived for the old one.
+            assert_eq!(post(&*storage, 3), created_for);
+        }
+    }
+
+    #[test]
+    fn merchant_orders() {
+        let storage = SledStorage::open(None).unwrap();
+        let post = |order: &str, merchant: Option<&str>| {
+            let mut new_order =
+                NewOrder::new(order).payment_account(order.len().try_into().unwrap());
+
+            new_order.query.merchant = merchant.map(Into::into);
+            new_order.post(&storage)
+        };
+
+        post("shop order", Some("shop"));
+        post("default order", None);
+
+        assert!(matches!(
+            post("shop order", None),
+            OrderCreateResponse::Foreign
+        ));
+        assert!(matches!(
+            post("shop order", Some("other shop")),
+            OrderCreateResponse::Foreign
+        ));
+        assert!(matches!(
+            post("shop order", Some("shop")),
+            OrderCreateResponse::Modified(..)
+        ));
+
+        let listed = list_orders(
+            &OrderListQuery {
+                merchant: Some("shop".into()),
+                ..OrderListQuery::default()
+            },
+            &storage,
+        )
+        .unwrap();
+
+        assert_eq!(listed.orders.len(), 1);
+        assert_eq!(listed.orders[0].order, "shop order");
+        assert_eq!(
+            listed.orders[0].order_info.merchant.as_deref(),
+            Some("shop")
+        );
+    }
+
+    #[test]
+    fn creation_time_filter() {
+        let sled = SledStorage::open(None).unwrap();
+        let sqlite = SqliteStorage::open(None).unwrap();
+
+        for storage in [&sled as &dyn Storage, &sqlite] {
+            let created = |from, to| {
+                list_orders(
+                    &OrderListQuery {
+                        created_from: from,
+                        created_to: to,
+                        ..OrderListQuery::default()
+                    },
+                    storage,
+                )
+                .unwrap()
+                .orders
+                .into_iter()
+                .map(|entry| entry.order)
+                .collect::<Vec<_>>()
+            };
+            let OrderCreateResponse::New(order_info) = NewOrder::new("order").post(storage) else {
+                panic!("the order isn't new");
+            };
+            let created_at = order_info.created.unwrap();
+            let mut legacy = order_info.clone();
+
+            assert_eq!(
+                storage
+                    .order("order")
+                    .unwrap()
+                    .unwrap()
+                    .created
+                    .map(|at| at.0),
+                Some(created_at.0)
+            );
+            assert_eq!(created(Some(created_at), Some(created_at)), ["order"]);
+            assert!(created(Some(Timestamp(created_at.0 + 1)), None).is_empty());
+
+            // Modifying the order moves its death timestamp, but not the creation time.
+            legacy.death = Timestamp(order_info.death.0 + 5000);
+            storage.update_order("order", &legacy).unwrap();
+
+            assert_eq!(created(None, Some(created_at)), ["order"]);
+
+            // Older orders without the creation time match no creation time filter.
+            legacy.created = None;
+            storage.update_order("order", &legacy).unwrap();
+
+            assert!(created(None, Some(created_at)).is_empty());
+            assert!(created(Some(Timestamp(0)), None).is_empty());
+            assert_eq!(created(None, None), ["order"]);
+        }
+    }
+
+    /// Results of the order changes that the state handler emits webhooks on.
+    #[test]
+    fn lifecycle_events() {
+        let storage = SledStorage::open(None).unwrap();
+        let post = |order: &str, account| {
+            NewOrder::new(order)
+                .callback("https://example.com/callback")
+                .events(vec![OrderEvent::Paid, OrderEvent::PayoutFailed])
+                .payment_account(account)
+                .post(&storage)
+        };
+        let tracker = || ChangeOrigin::tracker(None);
+
+        post("paid", 1);
+
+        assert_eq!(
+            storage.subscription("paid").unwrap(),
+            Some(vec![OrderEvent::Paid, OrderEvent::PayoutFailed])
+        );
+        // `partially_paid` is emitted once per increase of the received amount.
+        assert!(record_received("paid".into(), Balance(4), tracker(), &storage).unwrap());
+        assert!(!record_received("paid".into(), Balance(4), tracker(), &storage).unwrap());
+        // `paid`, then `payout_submitted`.
+        mark_paid("paid".into(), tracker(), &storage).unwrap();
+        mark_withdrawn("paid".into(), tracker(), &storage).unwrap();
+        // A submitted payout doesn't fail afterwards, so no `payout_failed`.
+        assert!(matches!(
+            mark_stuck("paid".into(), tracker(), &storage),
+            Err(DbError::WithdrawalWasAttempted(_))
+        ));
+
+        post("forced", 2);
+        mark_forced("forced".into(), ChangeOrigin::ADMIN, &storage).unwrap();
+        // `payout_failed` of a forced payout, even though the order isn't paid.
+        mark_stuck("forced".into(), tracker(), &storage).unwrap();
+
+        let withdrawal_changes: Vec<_> = order_history("forced", &storage)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.field == OrderField::WithdrawalStatus)
+            .map(|entry| entry.new)
+            .collect();
+
+        assert_eq!(withdrawal_changes, ["waiting", "forced", "failed"]);
+    }
+
+    #[test]
+    fn held_excess_release() {
+        let storage = SledStorage::open(None).unwrap();
+        let tracker = || ChangeOrigin::tracker(None);
+
+        NewOrder::new("held")
+            .callback("https://example.com/callback")
+            .post(&storage);
+        mark_paid("held".into(), tracker(), &storage).unwrap();
+        mark_withdrawn("held".into(), tracker(), &storage).unwrap();
+
+        // A completed order can't be forced, but the payout releasing its held excess completes.
+        assert!(matches!(
+            mark_forced("held".into(), ChangeOrigin::ADMIN, &storage),
+            Err(DbError::WithdrawalWasAttempted(_))
+        ));
+        mark_withdrawn("held".into(), tracker(), &storage).unwrap();
+
+        let withdrawal_changes: Vec<_> = order_history("held", &storage)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.field == OrderField::WithdrawalStatus)
+            .map(|entry| entry.new)
+            .collect();
+
+        assert_eq!(withdrawal_changes, ["waiting", "completed"]);
+    }
+
+    #[test]
+    fn underpaid_sweep() {
+        let storage = SledStorage::open(None).unwrap();
+        let tracker = || ChangeOrigin::tracker(Some(5));
+
+        NewOrder::new("order").post(&storage);
+        record_received("order".into(), Balance(4), tracker(), &storage).unwrap();
+
+        // A regular payout needs the order to be paid.
+        assert!(matches!(
+            mark_withdrawn("order".into(), tracker(), &storage),
+            Err(DbError::NotPaid(_))
+        ));
+
+        // The sweep policy times out the expired order and forces its payout.
+        mark_timed_out("order".into(), tracker(), &storage).unwrap();
+        mark_forced("order".into(), tracker(), &storage).unwrap();
+        mark_withdrawn("order".into(), tracker(), &storage).unwrap();
+
+        let order_info = storage.order("order").unwrap().unwrap();
+
+        assert_eq!(order_info.payment_status, PaymentStatus::TimedOut);
+        assert_eq!(order_info.withdrawal_status, WithdrawalStatus::Completed);
+        assert!(matches!(
+            mark_withdrawn("order".into(), tracker(), &storage),
+            Err(DbError::WithdrawalWasAttempted(_))
+        ));
+    }
+
+    #[test]
+    fn webhook_redelivery() {
+        let storage = SledStorage::open(None).unwrap();
+        let webhook = enqueue_webhook(
+            &storage,
+            "order".into(),
+            OrderEvent::Paid,
+            "https://example.com/callback".into(),
+            "{}".into(),
+        )
+        .unwrap();
+        let fail = |failing: &mut WebhookInfo| {
+            record_attempt(
+                failing,
+                WebhookAttempt {
+                    timestamp: Timestamp(1000),
+                    error: Some("unavailable".into()),
+                },
+            );
+        };
+
+        assert!(matches!(
+            redeliver_webhook(&storage, webhook.id),
+            Err(DbError::WebhookNotFailed(_))
+        ));
+
+        let mut failed = webhook.clone();
+
+        for _ in 0..MAX_ATTEMPTS {
+            fail(&mut failed);
+        }
+
+        assert_eq!(failed.status, WebhookStatus::Failed);
+
+        storage.save_webhook(&failed).unwrap();
+
+        let mut redelivered = redeliver_webhook(&storage, webhook.id).unwrap();
+
+        assert_eq!(redelivered.status, WebhookStatus::Pending);
+        assert!(redelivered.attempts.is_empty());
+
+        for _ in 1..MAX_ATTEMPTS {
+            fail(&mut redelivered);
+
+            assert_eq!(redelivered.status, WebhookStatus::Pending);
+        }
+
+        fail(&mut redelivered);
+
+        assert_eq!(redelivered.status, WebhookStatus::Failed);
+        assert!(matches!(
+            redeliver_webhook(&storage, 1),
+            Err(DbError::WebhookNotFound(1))
+        ));
+    }
+
+    #[test]
+    fn public_payment_account() {
+        let storage = SledStorage::open(None).unwrap();
+        let payment_account = AccountId32([1; 32]).to_base58_string(0);
+        let tx = |transfer_index, kind, finalized: bool| TransactionInfoDb {
+            transaction_bytes: format!("0x0{transfer_index}"),
+            inner: TransactionInfoDbInner {
+                finalized_tx: finalized.then_some(FinalizedTxDb {
+                    block_number: 1,
+                    position_in_block: 2,
+                    transfer_index,
+                }),
+                finalized_tx_timestamp: finalized.then(|| "timestamp".to_owned()),
+                sender: "payer".into(),
+                recipient: payment_account.clone(),
+                amount: Amount::Exact(Balance(10_000_000_000)),
+                currency: currency(),
+                status: TxStatus::Finalized,
+                kind,
+            },
+        };
+
+        NewOrder::new("order").amount(30_000_000_000).post(&storage);
+        record_transaction(&storage, "order", tx(0, TxKind::Payment, true)).unwrap();
+        record_transaction(&storage, "order", tx(1, TxKind::Payment, true)).unwrap();
+        // Neither pending payments nor outgoing transfers count as received.
+        record_transaction(&storage, "order", tx(2, TxKind::Payment, false)).unwrap();
+        record_transaction(&storage, "order", tx(3, TxKind::Withdrawal, true)).unwrap();
+
+        let public = read_payment_account(&[1; 32], &storage).unwrap().unwrap();
+
+        assert_eq!(public.payment_account, payment_account);
+        assert_eq!(public.payment_status, PaymentStatus::Pending);
+        assert_eq!(public.amount, "3");
+        assert_eq!(public.received_amount, "2");
+
+        // The tracker may have seen more on the account balance than the recorded transfers.
+        record_received(
+            "order".into(),
+            Balance(25_000_000_000),
+            ChangeOrigin::tracker(None),
+            &storage,
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_payment_account(&[1; 32], &storage)
+                .unwrap()
+                .unwrap()
+                .received_amount,
+            "2.5"
+        );
+        assert!(read_payment_account(&[2; 32], &storage).unwrap().is_none());
+    }
+
+    #[test]
+    fn transactions_rekeyed() {
+        let database = sled::Config::new().temporary(true).open().unwrap();
+        let orders = database.open_tree(ORDERS_TABLE).unwrap();
+        let transactions = database.open_tree(TRANSACTIONS).unwrap();
+        let pending_transactions = database.open_tree(PENDING_TRANSACTIONS).unwrap();
+        let tx = TransactionInfoDbInner {
+            finalized_tx: None,
+            finalized_tx_timestamp: None,
+            sender: "sender".into(),
+            recipient: "recipient".into(),
+            amount: Amount::Exact(Balance(1)),
+            currency: currency(),
+            status: TxStatus::Pending,
+            kind: TxKind::Withdrawal,
+        };
+
+        database.insert(DB_VERSION_KEY, 2u64.encode()).unwrap();
+        transactions
+            .insert(
+                ("order", 1u32, 2u32).encode(),
+                TransactionInfoDb {
+                    transaction_bytes: "0x00".into(),
+                    inner: tx.clone(),
+                }
+                .encode(),
+            )
+            .unwrap();
+        pending_transactions
+            .insert(("order", "0x01").encode(), tx.encode())
+            .unwrap();
+
+        let reports = migrate(
+            &database,
+            &Tables {
+                orders: &orders,
+                transactions: &transactions,
+                pending_transactions: &pending_transactions,
+            },
+        )
+        .unwrap();
+
+        // Later versions have no orders to rewrite.
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].rewritten_records, 2);
+        assert!(reports[1..]
+            .iter()
+            .all(|report| report.rewritten_records == 0));
+        assert_eq!(stored_version(&database).unwrap(), DB_VERSION);
+        assert!(transactions
+            .contains_key(("order", 1u32, 2u32, 0u32).encode())
+            .unwrap());
+        assert!(pending_transactions
+            .contains_key(("order", "0x01", "recipient").encode())
+            .unwrap());
+        assert_eq!(transactions.len() + pending_transactions.len(), 2);
+    }
+
+    #[test]
+    fn float_amounts_migrated() {
+        let database = sled::Config::new().temporary(true).open().unwrap();
+        let orders = database.open_tree(ORDERS_TABLE).unwrap();
+        let transactions = database.open_tree(TRANSACTIONS).unwrap();
+        let pending_transactions = database.open_tree(PENDING_TRANSACTIONS).unwrap();
+
+        // The layouts of `OrderInfo` & `TransactionInfoDbInner` of version 0, which had no version
+        // key in the database.
+        orders
+            .insert(
+                "order".encode(),
+                (
+                    WithdrawalStatus::Waiting,
+                    PaymentStatus::Pending,
+                    1.23f64,
+                    currency(),
+                    "https://example.com/callback",
+                    Vec::<()>::new(),
+                    "payment account",
+                    Timestamp(5000),
+                )
+                    .encode(),
+            )
+            .unwrap();
+        pending_transactions
+            .insert(
+                ("order", "0x01").encode(),
+                (
+                    None::<FinalizedTxDb>,
+                    None::<String>,
+                    "sender",
+                    "recipient",
+                    // `Amount::Exact`
+                    (1u8, 0.3f64),
+                    currency(),
+                    TxStatus::Pending,
+                    TxKind::Withdrawal,
+                )
+                    .encode(),
+            )
+            .unwrap();
+
+        let reports = migrate(
+            &database,
+            &Tables {
+                orders: &orders,
+                transactions: &transactions,
+                pending_transactions: &pending_transactions,
+            },
+        )
+        .unwrap();
+        let order_info =
+            OrderInfo::decode(&mut &orders.get("order".encode()).unwrap().unwrap()[..]).unwrap();
+        let (_, tx) = pending_transactions.iter().next().unwrap().unwrap();
+
+        assert_eq!(reports.len(), usize::try_from(DB_VERSION).unwrap());
+        assert_eq!(reports[0].rewritten_records, 2);
+        assert_eq!(order_info.amount, Balance(12_300_000_000));
+        assert_eq!(order_info.received, Balance(0));
+        assert!(order_info.created.is_none());
+        assert_eq!(order_info.death.0, 5000);
+        assert_eq!(order_info.callback, "https://example.com/callback");
+        assert!(matches!(
+            TransactionInfoDbInner::decode(&mut &tx[..]).unwrap().amount,
+            Amount::Exact(Balance(3_000_000_000))
+        ));
+    }
+}
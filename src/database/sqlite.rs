@@ -0,0 +1,693 @@
+//! SQLite storage engine
+//!
+//! Records are kept in the relational schema described in `docs/DATABASE.md`, so they can be
+//! queried with any SQLite client. Balances don't fit into SQLite integers and are saved as decimal
+//! strings. Nested values that reports rarely filter on, i.e., currency details, payout splits,
+//! subscribed events, and webhook delivery attempts, are saved as JSON.
+
+use super::{
+    Account, FinalizedTxDb, Storage, TransactionInfoDb, TransactionInfoDbInner, VisitOrder,
+};
+use crate::{
+    definitions::{
+        api_v2::{
+            Amount, OrderEvent, OrderHistoryEntry, OrderInfo, PayoutSplit, ServerInfo, SplitShare,
+            Timestamp, WebhookId, WebhookInfo,
+        },
+        Balance, Version,
+    },
+    error::DbError,
+};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::any;
+
+/// Version of [`SCHEMA`], saved as the `user_version` of the database.
+const SCHEMA_VERSION: Version = 3;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS orders (
+    order_id TEXT PRIMARY KEY NOT NULL,
+    payment_status TEXT NOT NULL,
+    withdrawal_status TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    received TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    currency_info TEXT NOT NULL,
+    callback TEXT NOT NULL,
+    payment_account TEXT NOT NULL,
+    payment_account_key BLOB NOT NULL,
+    death INTEGER NOT NULL,
+    created INTEGER,
+    merchant TEXT,
+    recipient TEXT,
+    splits TEXT NOT NULL DEFAULT '[]'
+);
+CREATE INDEX IF NOT EXISTS orders_by_account ON orders (payment_account_key);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id TEXT NOT NULL,
+    chain TEXT NOT NULL,
+    block_number INTEGER,
+    position_in_block INTEGER,
+    transfer_index INTEGER,
+    timestamp TEXT,
+    transaction_bytes TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    amount TEXT,
+    currency TEXT NOT NULL,
+    currency_info TEXT NOT NULL,
+    type TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS finalized_transactions
+    ON transactions (order_id, block_number, position_in_block, transfer_index)
+    WHERE block_number IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS pending_transactions
+    ON transactions (order_id, transaction_bytes, recipient)
+    WHERE block_number IS NULL;
+
+CREATE TABLE IF NOT EXISTS order_history (
+    entry_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    block_number INTEGER,
+    field TEXT NOT NULL,
+    previous TEXT,
+    new TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS order_history_by_order ON order_history (order_id, entry_id);
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    order_id TEXT PRIMARY KEY NOT NULL,
+    events TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id INTEGER PRIMARY KEY NOT NULL,
+    order_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    url TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    next_attempt INTEGER,
+    attempts TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS instance_info (
+    instance_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    debug INTEGER NOT NULL,
+    kalatori_remark TEXT
+);
+";
+
+/// Changes of the tables that [`SCHEMA`] doesn't apply to existing databases, along with the
+/// versions they were made in.
+const UPGRADES: &[(Version, &str)] = &[
+    (
+        2,
+        "ALTER TABLE orders ADD COLUMN merchant TEXT;
+         ALTER TABLE orders ADD COLUMN recipient TEXT;",
+    ),
+    (
+        3,
+        "ALTER TABLE orders ADD COLUMN splits TEXT NOT NULL DEFAULT '[]';",
+    ),
+];
+
+const ORDER_COLUMNS: &str = "order_id, payment_status, withdrawal_status, amount, received, \
+                             currency_info, callback, payment_account, death, merchant, splits, \
+                             created, recipient";
+const TRANSACTION_COLUMNS: &str = "block_number, position_in_block, transfer_index, timestamp, \
+                                   transaction_bytes, sender, recipient, amount, currency_info, \
+                                   type, status";
+const HISTORY_COLUMNS: &str = "timestamp, source, block_number, field, previous, new";
+const WEBHOOK_COLUMNS: &str =
+    "id, order_id, event, url, payload, status, created, next_attempt, attempts";
+
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path_option: Option<String>) -> Result<Self, DbError> {
+        let connection = if let Some(path) = path_option {
+            tracing::info!("Creating/Opening the SQLite database at {path:?}.");
+
+            Connection::open(path)?
+        } else {
+            Connection::open_in_memory()?
+        };
+        let version: Version =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        if version > SCHEMA_VERSION {
+            return Err(DbError::UnsupportedVersion(version));
+        }
+
+        // A new database gets the whole schema at once.
+        if version != 0 {
+            for (upgraded, statement) in UPGRADES {
+                if version < *upgraded {
+                    connection.execute_batch(statement)?;
+                }
+            }
+        }
+
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self { connection })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, DbError> {
+        self.connection
+            .query_row("SELECT NOT EXISTS (SELECT 1 FROM orders)", [], |row| {
+                row.get(0)
+            })
+            .map_err(Into::into)
+    }
+
+    /// Runs `f` in a single transaction that is rolled back if `f` fails.
+    pub fn in_transaction<T>(
+        &self,
+        f: impl FnOnce(&Self) -> Result<T, DbError>,
+    ) -> Result<T, DbError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let result = f(self)?;
+
+        transaction.commit()?;
+
+        Ok(result)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn order(&self, order: &str) -> Result<Option<OrderInfo>, DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {ORDER_COLUMNS} FROM orders WHERE order_id = ?1"
+        ))?;
+        let mut rows = statement.query([order])?;
+
+        rows.next()?
+            .map(|row| order_from_row(row).map(|(_, order_info)| order_info))
+            .transpose()
+    }
+
+    fn for_each_order(
+        &self,
+        after: Option<&str>,
+        visit: &mut VisitOrder<'_>,
+    ) -> Result<(), DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {ORDER_COLUMNS} FROM orders WHERE ?1 IS NULL OR order_id > ?1 \
+             ORDER BY order_id"
+        ))?;
+        let mut rows = statement.query([after])?;
+
+        while let Some(row) = rows.next()? {
+            let (order, order_info) = order_from_row(row)?;
+
+            if visit(order, order_info)?.is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_order(
+        &self,
+        order: &str,
+        order_info: &OrderInfo,
+        account: Account,
+    ) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO orders (order_id, payment_status, withdrawal_status, amount, \
+             received, currency, currency_info, callback, payment_account, payment_account_key, \
+             death, merchant, splits, created, recipient) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, \
+             ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                order,
+                text(&order_info.payment_status)?,
+                text(&order_info.withdrawal_status)?,
+                order_info.amount.0.to_string(),
+                order_info.received.0.to_string(),
+                order_info.currency.currency,
+                json(&order_info.currency)?,
+                order_info.callback,
+                order_info.payment_account,
+                &account[..],
+                order_info.death.0,
+                order_info.merchant,
+                splits_json(&order_info.splits)?,
+                order_info.created.map(|created| created.0),
+                order_info.recipient,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn update_order(&self, order: &str, order_info: &OrderInfo) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE orders SET payment_status = ?2, withdrawal_status = ?3, amount = ?4, \
+             received = ?5, currency = ?6, currency_info = ?7, callback = ?8, death = ?9, \
+             merchant = ?10, splits = ?11, created = ?12, recipient = ?13 WHERE order_id = ?1",
+            params![
+                order,
+                text(&order_info.payment_status)?,
+                text(&order_info.withdrawal_status)?,
+                order_info.amount.0.to_string(),
+                order_info.received.0.to_string(),
+                order_info.currency.currency,
+                json(&order_info.currency)?,
+                order_info.callback,
+                order_info.death.0,
+                order_info.merchant,
+                splits_json(&order_info.splits)?,
+                order_info.created.map(|created| created.0),
+                order_info.recipient,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn order_by_account(&self, account: &Account) -> Result<Option<String>, DbError> {
+        self.connection
+            .query_row(
+                "SELECT order_id FROM orders WHERE payment_account_key = ?1",
+                [&account[..]],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    fn transactions(&self, order: &str) -> Result<Vec<TransactionInfoDb>, DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {TRANSACTION_COLUMNS} FROM transactions WHERE order_id = ?1 \
+             ORDER BY block_number IS NULL, block_number, position_in_block, transfer_index, \
+             transaction_bytes, recipient"
+        ))?;
+        let mut rows = statement.query([order])?;
+        let mut transactions = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            transactions.push(transaction_from_row(row)?);
+        }
+
+        Ok(transactions)
+    }
+
+    fn pending_transaction(
+        &self,
+        order: &str,
+        tx: &TransactionInfoDb,
+    ) -> Result<Option<TransactionInfoDbInner>, DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {TRANSACTION_COLUMNS} FROM transactions WHERE order_id = ?1 \
+             AND block_number IS NULL AND transaction_bytes = ?2 AND recipient = ?3"
+        ))?;
+        let mut rows = statement.query([order, &tx.transaction_bytes, &tx.inner.recipient])?;
+
+        rows.next()?
+            .map(|row| transaction_from_row(row).map(|pending_tx| pending_tx.inner))
+            .transpose()
+    }
+
+    fn save_pending_transaction(&self, order: &str, tx: &TransactionInfoDb) -> Result<(), DbError> {
+        self.insert_transaction(order, None, tx)
+    }
+
+    fn finalize_transaction(
+        &self,
+        order: &str,
+        finalized_tx: &FinalizedTxDb,
+        tx: &TransactionInfoDb,
+    ) -> Result<(), DbError> {
+        self.connection.execute(
+            "DELETE FROM transactions WHERE order_id = ?1 AND block_number IS NULL \
+             AND transaction_bytes = ?2 AND recipient = ?3",
+            [order, &tx.transaction_bytes, &tx.inner.recipient],
+        )?;
+
+        self.insert_transaction(order, Some(finalized_tx), tx)
+    }
+
+    fn append_history(&self, order: &str, entries: &[OrderHistoryEntry]) -> Result<(), DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "INSERT INTO order_history (order_id, {HISTORY_COLUMNS}) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+        ))?;
+
+        for entry in entries {
+            statement.execute(params![
+                order,
+                entry.timestamp.0,
+                text(&entry.source)?,
+                entry.block_number,
+                text(&entry.field)?,
+                entry.previous,
+                entry.new,
+            ])?;
+        }
+
+        Ok(())
+    }
+
+    fn save_change(
+        &self,
+        order: &str,
+        order_info: &OrderInfo,
+        entries: &[OrderHistoryEntry],
+    ) -> Result<(), DbError> {
+        self.in_transaction(|sqlite| {
+            sqlite.update_order(order, order_info)?;
+            sqlite.append_history(order, entries)
+        })
+    }
+
+    fn history(&self, order: &str) -> Result<Vec<OrderHistoryEntry>, DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {HISTORY_COLUMNS} FROM order_history WHERE order_id = ?1 ORDER BY entry_id"
+        ))?;
+        let mut rows = statement.query([order])?;
+        let mut entries = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            entries.push(history_entry_from_row(row)?);
+        }
+
+        Ok(entries)
+    }
+
+    fn subscription(&self, order: &str) -> Result<Option<Vec<OrderEvent>>, DbError> {
+        self.connection
+            .query_row(
+                "SELECT events FROM subscriptions WHERE order_id = ?1",
+                [order],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .map(|events| from_json(&events))
+            .transpose()
+    }
+
+    fn subscribe(&self, order: &str, events: &[OrderEvent]) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO subscriptions (order_id, events) VALUES (?1, ?2)",
+            [order, &json(&events)?],
+        )?;
+
+        Ok(())
+    }
+
+    fn next_webhook_id(&self) -> Result<WebhookId, DbError> {
+        self.connection
+            .query_row("SELECT COALESCE(MAX(id) + 1, 0) FROM webhooks", [], |row| {
+                row.get(0)
+            })
+            .map_err(Into::into)
+    }
+
+    fn webhook(&self, id: WebhookId) -> Result<Option<WebhookInfo>, DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {WEBHOOK_COLUMNS} FROM webhooks WHERE id = ?1"
+        ))?;
+        let mut rows = statement.query([id])?;
+
+        rows.next()?.map(webhook_from_row).transpose()
+    }
+
+    fn webhooks(&self) -> Result<Vec<WebhookInfo>, DbError> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT {WEBHOOK_COLUMNS} FROM webhooks ORDER BY id"
+        ))?;
+        let mut rows = statement.query([])?;
+        let mut webhooks = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            webhooks.push(webhook_from_row(row)?);
+        }
+
+        Ok(webhooks)
+    }
+
+    fn save_webhook(&self, webhook: &WebhookInfo) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO webhooks (id, order_id, event, url, payload, status, \
+             created, next_attempt, attempts) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                webhook.id,
+                webhook.order,
+                text(&webhook.event)?,
+                webhook.url,
+                webhook.payload,
+                text(&webhook.status)?,
+                webhook.created.0,
+                webhook.next_attempt.map(|next_attempt| next_attempt.0),
+                json(&webhook.attempts)?,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn server_info(&self) -> Result<Option<ServerInfo>, DbError> {
+        self.connection
+            .query_row(
+                "SELECT instance_id, version, debug, kalatori_remark FROM instance_info",
+                [],
+                |row| {
+                    Ok(ServerInfo {
+                        instance_id: row.get(0)?,
+                        version: row.get(1)?,
+                        debug: row.get(2)?,
+                        kalatori_remark: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    fn save_server_info(&self, server_info: &ServerInfo) -> Result<(), DbError> {
+        self.connection.execute("DELETE FROM instance_info", [])?;
+        self.connection.execute(
+            "INSERT INTO instance_info (instance_id, version, debug, kalatori_remark) \
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                server_info.instance_id,
+                server_info.version,
+                server_info.debug,
+                server_info.kalatori_remark,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Each statement is committed right away, so there's nothing to flush.
+    fn flush(&self) -> Result<(), DbError> {
+        Ok(())
+    }
+}
+
+impl SqliteStorage {
+    /// A conflicting record of the same transfer is replaced.
+    fn insert_transaction(
+        &self,
+        order: &str,
+        finalized_tx: Option<&FinalizedTxDb>,
+        tx: &TransactionInfoDb,
+    ) -> Result<(), DbError> {
+        let amount = match &tx.inner.amount {
+            Amount::All => None,
+            Amount::Exact(exact) => Some(exact.0.to_string()),
+        };
+
+        self.connection.execute(
+            "INSERT OR REPLACE INTO transactions (order_id, chain, block_number, \
+             position_in_block, transfer_index, timestamp, transaction_bytes, sender, recipient, \
+             amount, currency, currency_info, type, status) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                order,
+                tx.inner.currency.chain_name,
+                finalized_tx.map(|finalized| finalized.block_number),
+                finalized_tx.map(|finalized| finalized.position_in_block),
+                finalized_tx.map(|finalized| finalized.transfer_index),
+                tx.inner.finalized_tx_timestamp,
+                tx.transaction_bytes,
+                tx.inner.sender,
+                tx.inner.recipient,
+                amount,
+                tx.inner.currency.currency,
+                json(&tx.inner.currency)?,
+                text(&tx.inner.kind)?,
+                text(&tx.inner.status)?,
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn order_from_row(row: &Row<'_>) -> Result<(String, OrderInfo), DbError> {
+    Ok((
+        row.get(0)?,
+        OrderInfo {
+            payment_status: from_text(row.get(1)?)?,
+            withdrawal_status: from_text(row.get(2)?)?,
+            amount: balance(&row.get::<_, String>(3)?)?,
+            received: balance(&row.get::<_, String>(4)?)?,
+            currency: from_json(&row.get::<_, String>(5)?)?,
+            callback: row.get(6)?,
+            transactions: Vec::new(),
+            payment_account: row.get(7)?,
+            death: Timestamp(row.get(8)?),
+            merchant: row.get(9)?,
+            splits: splits_from_json(&row.get::<_, String>(10)?)?,
+            created: row.get::<_, Option<u64>>(11)?.map(Timestamp),
+            recipient: row.get(12)?,
+        },
+    ))
+}
+
+fn transaction_from_row(row: &Row<'_>) -> Result<TransactionInfoDb, DbError> {
+    let finalized_tx = match row.get(0)? {
+        Some(block_number) => Some(FinalizedTxDb {
+            block_number,
+            position_in_block: row.get(1)?,
+            transfer_index: row.get(2)?,
+        }),
+        None => None,
+    };
+    let amount = match row.get::<_, Option<String>>(7)? {
+        Some(exact) => Amount::Exact(balance(&exact)?),
+        None => Amount::All,
+    };
+
+    Ok(TransactionInfoDb {
+        transaction_bytes: row.get(4)?,
+        inner: TransactionInfoDbInner {
+            finalized_tx,
+            finalized_tx_timestamp: row.get(3)?,
+            sender: row.get(5)?,
+            recipient: row.get(6)?,
+            amount,
+            currency: from_json(&row.get::<_, String>(8)?)?,
+            kind: from_text(row.get(9)?)?,
+            status: from_text(row.get(10)?)?,
+        },
+    })
+}
+
+fn history_entry_from_row(row: &Row<'_>) -> Result<OrderHistoryEntry, DbError> {
+    Ok(OrderHistoryEntry {
+        timestamp: Timestamp(row.get(0)?),
+        source: from_text(row.get(1)?)?,
+        block_number: row.get(2)?,
+        field: from_text(row.get(3)?)?,
+        previous: row.get(4)?,
+        new: row.get(5)?,
+    })
+}
+
+fn webhook_from_row(row: &Row<'_>) -> Result<WebhookInfo, DbError> {
+    Ok(WebhookInfo {
+        id: row.get(0)?,
+        order: row.get(1)?,
+        event: from_text(row.get(2)?)?,
+        url: row.get(3)?,
+        payload: row.get(4)?,
+        status: from_text(row.get(5)?)?,
+        created: Timestamp(row.get(6)?),
+        next_attempt: row.get::<_, Option<u64>>(7)?.map(Timestamp),
+        attempts: from_json(&row.get::<_, String>(8)?)?,
+    })
+}
+
+/// Saves an enum variant under its name in the API.
+pub(super) fn text<T: Serialize>(value: &T) -> Result<String, DbError> {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => Ok(name),
+        _ => Err(DbError::SerializationError(any::type_name::<T>().into())),
+    }
+}
+
+fn from_text<T: DeserializeOwned>(name: String) -> Result<T, DbError> {
+    serde_json::from_value(Value::String(name))
+        .map_err(|e| DbError::DeserializationError(e.to_string()))
+}
+
+fn json<T: Serialize + ?Sized>(value: &T) -> Result<String, DbError> {
+    serde_json::to_string(value).map_err(|e| DbError::SerializationError(e.to_string()))
+}
+
+fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, DbError> {
+    serde_json::from_str(json).map_err(|e| DbError::DeserializationError(e.to_string()))
+}
+
+/// Payout split as it's saved in the `splits` column
+#[derive(Serialize, Deserialize)]
+struct SplitRow {
+    recipient: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<String>,
+}
+
+fn splits_json(splits: &[PayoutSplit]) -> Result<String, DbError> {
+    json(
+        &splits
+            .iter()
+            .map(|split| {
+                let (percent, amount) = match split.share {
+                    SplitShare::Percent(percent) => (Some(percent), None),
+                    SplitShare::Fixed(amount) => (None, Some(amount.0.to_string())),
+                };
+
+                SplitRow {
+                    recipient: split.recipient.clone(),
+                    percent,
+                    amount,
+                }
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn splits_from_json(splits: &str) -> Result<Vec<PayoutSplit>, DbError> {
+    from_json::<Vec<SplitRow>>(splits)?
+        .into_iter()
+        .map(|row| {
+            let share = match (row.percent, row.amount) {
+                (Some(percent), None) => SplitShare::Percent(percent),
+                (None, Some(amount)) => SplitShare::Fixed(balance(&amount)?),
+                _ => return Err(DbError::DeserializationError(format!("split {splits:?}"))),
+            };
+
+            Ok(PayoutSplit {
+                recipient: row.recipient,
+                share,
+            })
+        })
+        .collect()
+}
+
+fn balance(decimal: &str) -> Result<Balance, DbError> {
+    decimal
+        .parse()
+        .map(Balance)
+        .map_err(|_| DbError::DeserializationError(format!("balance {decimal:?}")))
+}
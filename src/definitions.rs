@@ -1,7 +1,11 @@
 //! Deaf and dumb object definitions
 
-use std::ops::{Deref, Sub};
+use std::{
+    collections::HashMap,
+    ops::{Deref, Sub},
+};
 
+use codec::{Decode, Encode};
 use serde::Deserialize;
 
 pub type Version = u64;
@@ -14,6 +18,13 @@ pub type Entropy = Vec<u8>; // TODO: maybe enforce something here
 pub struct Chain {
     pub name: String,
     pub endpoints: Vec<String>,
+    /// Number of endpoints that must confirm an order payment at the finalized block before the
+    /// order is marked as paid. A single endpoint decides if it's omitted.
+    pub quorum: Option<usize>,
+    /// Number of storage keys fetched by a single request when balances of the watched accounts
+    /// are checked.
+    #[serde(default = "query_batch_size")]
+    pub query_batch_size: usize,
     #[serde(flatten)]
     pub native_token: Option<NativeToken>,
     #[serde(default)]
@@ -26,15 +37,196 @@ pub struct NativeToken {
     #[serde(rename = "native-token")]
     pub name: String,
     pub decimals: api_v2::Decimals,
+    #[serde(default)]
+    pub overpayment_policy: OverpaymentPolicy,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct AssetInfo {
     pub name: String,
     pub id: api_v2::AssetId,
+    #[serde(default)]
+    pub overpayment_policy: OverpaymentPolicy,
+}
+
+/// API key allowed to access the daemon API
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApiKey {
+    pub name: String,
+    pub secret: String,
+    pub scopes: Vec<Scope>,
+    /// Reject bearer authentication with this key and accept only signed requests.
+    #[serde(default)]
+    pub signed_only: bool,
+    /// Merchant profile that the key is bound to. Such a key can only create and see orders of
+    /// its merchant.
+    #[serde(default)]
+    pub merchant: Option<String>,
+}
+
+/// Merchant served by the daemon along with others, each with its own recipient
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Merchant {
+    pub id: String,
+    /// Hex or SS58 address that receives payouts, and that payment accounts of the merchant
+    /// orders are derived for.
+    pub recipient: String,
+    pub remark: Option<String>,
+    /// Secrets to sign webhooks of the merchant orders with instead of the global ones.
+    pub callback_secrets: Option<Vec<String>>,
+    /// Currencies that the merchant accepts; all supported ones if omitted.
+    pub currencies: Option<Vec<String>>,
+    /// Secret of an API key bound to the merchant with the `read` and `create` scopes.
+    pub api_key: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+/// What happens to a partially paid order when it expires
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnderpaidPolicy {
+    /// Return the received funds to the payer.
+    Refund,
+    /// Send the received funds to the recipient as they are.
+    Sweep,
+    /// Keep watching the order until it's paid in full.
+    #[default]
+    Wait,
+}
+
+/// What happens to the excess of an overpaid order on payout
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverpaymentPolicy {
+    /// Send the whole balance to the recipient.
+    #[default]
+    Forward,
+    /// Send the order amount to the recipient and return the excess to the payers.
+    Refund,
+    /// Send the order amount to the recipient and leave the excess for a manual review.
+    Hold,
+}
+
+/// Changes allowed to an order that's awaiting payment when it's posted again
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModificationPolicy {
+    /// Allow switching the order to another currency. Orders that have received some funds can't
+    /// switch it regardless.
+    #[serde(default = "allowed")]
+    pub allow_currency_change: bool,
+    #[serde(default = "allowed")]
+    pub allow_amount_decrease: bool,
+}
+
+impl Default for ModificationPolicy {
+    fn default() -> Self {
+        Self {
+            allow_currency_change: true,
+            allow_amount_decrease: true,
+        }
+    }
+}
+
+fn query_batch_size() -> usize {
+    256
+}
+
+fn allowed() -> bool {
+    true
+}
+
+/// Storage engine of the database
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    #[default]
+    Sled,
+    /// Embedded SQLite with the relational schema from `docs/DATABASE.md`.
+    Sqlite,
+}
+
+/// Delivery settings of callbacks to merchants
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub mode: WebhookMode,
+    /// Secrets to sign webhooks with. Each of them makes a signature, so a secret can be rotated
+    /// by adding a new one and removing the old one once merchants have switched to the new one.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Overrides for callbacks to specific merchant hosts.
+    #[serde(default)]
+    pub merchant: Vec<MerchantWebhookConfig>,
+    /// Secrets of merchant profiles, filled in from their `callback-secrets` on startup.
+    #[serde(skip)]
+    pub merchant_secrets: HashMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MerchantWebhookConfig {
+    pub host: String,
+    pub mode: Option<WebhookMode>,
+    pub secrets: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookMode {
+    /// `POST` the order status with a signature.
+    Signed,
+    /// Bare `GET` to the callback URL for existing integrations.
+    #[default]
+    Legacy,
+}
+
+impl WebhookConfig {
+    /// Mode and signing secrets for a callback to the given host about an order of the given
+    /// merchant. Host overrides take precedence over the merchant profile secrets.
+    pub fn for_host(
+        &self,
+        host: Option<&str>,
+        merchant_id: Option<&str>,
+    ) -> (WebhookMode, &[String]) {
+        let found = self
+            .merchant
+            .iter()
+            .find(|merchant| Some(merchant.host.as_str()) == host);
+
+        (
+            found
+                .and_then(|merchant| merchant.mode)
+                .unwrap_or(self.mode),
+            found
+                .and_then(|merchant| merchant.secrets.as_deref())
+                .or_else(|| {
+                    merchant_id
+                        .and_then(|id| self.merchant_secrets.get(id))
+                        .map(Vec::as_slice)
+                })
+                .unwrap_or(&self.secrets),
+        )
+    }
+}
+
+/// Group of API endpoints an API key is allowed to access
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Status, health, and order lookups.
+    Read,
+    /// Order creation and modification.
+    Create,
+    /// Everything, including force withdrawals, audits, and investigations.
+    Admin,
+}
+
+/// Amount of a currency in its smallest indivisible units (planck for DOT).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd, Encode, Decode)]
 pub struct Balance(pub u128);
 
 impl Deref for Balance {
@@ -54,35 +246,74 @@ impl Sub for Balance {
 }
 
 impl Balance {
-    #[allow(dead_code)] // TODO: remove once populated
-    pub fn format(&self, decimals: api_v2::Decimals) -> f64 {
-        #[allow(clippy::cast_precision_loss)]
-        let float = **self as f64;
+    /// Formats the balance as an exact decimal string without trailing zeros in the fraction.
+    pub fn format(&self, decimals: api_v2::Decimals) -> String {
+        let digits = self.0.to_string();
+        let scale = usize::from(decimals);
+        let padded = if digits.len() > scale {
+            digits
+        } else {
+            format!("{digits:0>width$}", width = scale.saturating_add(1))
+        };
+        let (integer, fraction) = padded.split_at(padded.len().saturating_sub(scale));
+        let significant = fraction.trim_end_matches('0');
 
-        float / decimal_exponent_product(decimals)
+        if significant.is_empty() {
+            integer.to_owned()
+        } else {
+            format!("{integer}.{significant}")
+        }
     }
 
-    pub fn parse(float: f64, decimals: api_v2::Decimals) -> Self {
-        let parsed_float = (float * decimal_exponent_product(decimals)).round();
+    /// Parses a non-negative decimal string like `12.345`.
+    ///
+    /// Returns [`None`] if the string isn't a plain decimal number, has more fractional digits
+    /// than the currency has decimals, or doesn't fit in [`u128`].
+    pub fn parse(decimal: &str, decimals: api_v2::Decimals) -> Option<Self> {
+        let (integer, fraction) = decimal.split_once('.').unwrap_or((decimal, ""));
 
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        Self(parsed_float as _)
-    }
-}
+        if integer.is_empty()
+            || fraction.len() > usize::from(decimals)
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|byte| byte.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let padding = u32::from(decimals).checked_sub(u32::try_from(fraction.len()).ok()?)?;
+        let units = format!("{integer}{fraction}").parse::<u128>().ok()?;
 
-pub fn decimal_exponent_product(decimals: api_v2::Decimals) -> f64 {
-    10f64.powi(decimals.into())
+        units.checked_mul(10u128.checked_pow(padding)?).map(Self)
+    }
 }
 
 /// Self-sufficient schemas used by Api v2.0.0
 pub mod api_v2 {
-    use std::collections::HashMap;
+    use std::{collections::HashMap, time::SystemTime};
 
     use codec::{Decode, Encode};
     use serde::{Deserialize, Serialize, Serializer};
 
+    use super::Balance;
+
     pub const AMOUNT: &str = "amount";
     pub const CURRENCY: &str = "currency";
+    pub const PAYMENT_ACCOUNT: &str = "paymentAccount";
+    pub const FROM_BLOCK: &str = "from_block";
+    pub const TO_BLOCK: &str = "to_block";
+    pub const LIMIT: &str = "limit";
+    pub const CREATED_FROM: &str = "created_from";
+    pub const DEATH_FROM: &str = "death_from";
+    pub const WEBHOOK_ID: &str = "webhook_id";
+    pub const MERCHANT: &str = "merchant";
+    pub const SPLITS: &str = "splits";
+
+    /// Decimals of split percentages, so a percentage is kept in hundredths of a percent.
+    pub const PERCENT_DECIMALS: Decimals = 2;
+    /// The whole order amount in hundredths of a percent.
+    pub const WHOLE_SHARE: u16 = 10_000;
     pub type AssetId = u32;
     pub type Decimals = u8;
     pub type BlockNumber = u32;
@@ -91,6 +322,17 @@ pub mod api_v2 {
     #[derive(Encode, Decode, Debug, Clone, Copy, Serialize, Deserialize)]
     pub struct Timestamp(pub u64);
 
+    impl Timestamp {
+        /// Milliseconds since the Unix epoch.
+        pub fn now() -> Self {
+            let elapsed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+
+            Self(elapsed.as_millis().try_into().unwrap_or(u64::MAX))
+        }
+    }
+
     #[derive(Debug, Serialize)]
     pub struct InvalidParameter {
         pub parameter: String,
@@ -100,9 +342,19 @@ pub mod api_v2 {
     #[derive(Debug)]
     pub struct OrderQuery {
         pub order: String,
-        pub amount: f64,
-        pub callback: String,
+        pub amount: Balance,
+        /// Callback URL of the order. [`None`] keeps the saved one, or leaves a new order without
+        /// it.
+        pub callback: Option<String>,
         pub currency: String,
+        /// Events to send webhooks for. [`None`] keeps the saved subscription, or subscribes to
+        /// all events if there's none.
+        pub events: Option<Vec<OrderEvent>>,
+        /// Merchant profile of the order; [`None`] for the default recipient.
+        pub merchant: Option<String>,
+        /// Payout split rules. [`None`] keeps the saved ones, or pays everything to the merchant
+        /// recipient if there are none.
+        pub splits: Option<Vec<PayoutSplit>>,
     }
 
     #[derive(Debug, Serialize)]
@@ -126,16 +378,147 @@ pub mod api_v2 {
         pub redirect_url: String,
     }
 
-    #[derive(Clone, Debug, Serialize, Encode, Decode)]
+    #[derive(Clone, Debug, Encode, Decode)]
     pub struct OrderInfo {
         pub withdrawal_status: WithdrawalStatus,
         pub payment_status: PaymentStatus,
-        pub amount: f64,
+        pub amount: Balance,
         pub currency: CurrencyInfo,
         pub callback: String,
         pub transactions: Vec<TransactionInfo>,
         pub payment_account: String,
         pub death: Timestamp,
+        /// The payment account balance while the order was awaiting payment.
+        pub received: Balance,
+        /// Merchant profile of the order; [`None`] for the default recipient.
+        pub merchant: Option<String>,
+        /// Parts of the order amount paid out to other recipients. The rest goes to the merchant
+        /// recipient.
+        pub splits: Vec<PayoutSplit>,
+        /// Creation time of the order; [`None`] for orders saved before it was recorded.
+        pub created: Option<Timestamp>,
+        /// SS58 address of the merchant recipient the order was created for, which its payment
+        /// account is derived for; [`None`] for orders saved before it was recorded.
+        pub recipient: Option<String>,
+    }
+
+    /// Part of the order amount paid out to another recipient than the merchant one
+    #[derive(Clone, Debug, Encode, Decode, PartialEq)]
+    pub struct PayoutSplit {
+        /// SS58 address of the recipient.
+        pub recipient: String,
+        pub share: SplitShare,
+    }
+
+    #[derive(Clone, Copy, Debug, Encode, Decode, PartialEq)]
+    pub enum SplitShare {
+        /// Hundredths of a percent of the order amount, up to [`WHOLE_SHARE`].
+        Percent(u16),
+        /// Exact amount.
+        Fixed(Balance),
+    }
+
+    impl SplitShare {
+        /// Amount of the share in the given base amount. Percentages are rounded down.
+        pub fn of(self, base: Balance) -> Balance {
+            match self {
+                SplitShare::Percent(percent) => {
+                    Balance(base.checked_mul(percent.into()).map_or_else(
+                        || (*base / u128::from(WHOLE_SHARE)).saturating_mul(percent.into()),
+                        |scaled| scaled / u128::from(WHOLE_SHARE),
+                    ))
+                }
+                SplitShare::Fixed(amount) => amount,
+            }
+        }
+    }
+
+    /// Tells why the splits don't fit into the order amount: each of them is paid out in a
+    /// transfer of its own that the recipient account must be able to accept, and all of them
+    /// together can't exceed the amount.
+    pub fn splits_rejection(
+        splits: &[PayoutSplit],
+        amount: Balance,
+        properties: &CurrencyProperties,
+    ) -> Option<String> {
+        let mut total = Balance(0);
+
+        for split in splits {
+            let leg = split.share.of(amount);
+
+            if leg < properties.existential_deposit || *leg == 0 {
+                return Some(format!(
+                    "split to {} is less than the currency's existential deposit ({})",
+                    split.recipient,
+                    properties.existential_deposit.format(properties.decimals)
+                ));
+            }
+
+            total = Balance(total.saturating_add(*leg));
+        }
+
+        (total > amount).then(|| "splits exceed the order amount".into())
+    }
+
+    /// Part of [`OrderInfo`] that is safe to show to the payer, looked up by the payment account.
+    #[derive(Clone, Debug, Serialize)]
+    pub struct PublicOrderInfo {
+        pub payment_account: String,
+        pub payment_status: PaymentStatus,
+        pub amount: String,
+        pub received_amount: String,
+        pub currency: CurrencyInfo,
+        pub death: Timestamp,
+    }
+
+    /// Refreshed order status along with everything an investigation has found on chain.
+    #[derive(Debug, Serialize)]
+    pub struct InvestigationResponse {
+        #[serde(flatten)]
+        pub order_status: OrderStatus,
+        pub investigation: InvestigationInfo,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct InvestigationInfo {
+        pub from_block: BlockNumber,
+        pub to_block: BlockNumber,
+        pub balance: String,
+        pub discovered_transactions: Vec<TransactionInfo>,
+        pub unreadable_blocks: Vec<BlockNumber>,
+        pub marked_paid: bool,
+    }
+
+    /// Filters and page position of an order listing.
+    #[derive(Debug, Default, Deserialize)]
+    pub struct OrderListQuery {
+        pub payment_status: Option<PaymentStatus>,
+        pub withdrawal_status: Option<WithdrawalStatus>,
+        pub currency: Option<String>,
+        pub merchant: Option<String>,
+        /// Orders saved before their creation time was recorded match neither of the creation
+        /// time bounds.
+        pub created_from: Option<Timestamp>,
+        pub created_to: Option<Timestamp>,
+        pub death_from: Option<Timestamp>,
+        pub death_to: Option<Timestamp>,
+        pub cursor: Option<String>,
+        pub limit: Option<usize>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct OrderListEntry {
+        pub order: String,
+        #[serde(flatten)]
+        pub order_info: OrderInfo,
+    }
+
+    /// A page of an order listing. Pass `next_cursor` as `cursor` to get the next one.
+    #[derive(Debug, Serialize)]
+    pub struct OrderList {
+        pub orders: Vec<OrderListEntry>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub next_cursor: Option<String>,
     }
 
     impl OrderInfo {
@@ -143,6 +526,8 @@ pub mod api_v2 {
             query: OrderQuery,
             currency: CurrencyInfo,
             payment_account: String,
+            recipient: String,
+            created: Timestamp,
             death: Timestamp,
         ) -> Self {
             OrderInfo {
@@ -150,37 +535,257 @@ pub mod api_v2 {
                 payment_status: PaymentStatus::Pending,
                 amount: query.amount,
                 currency,
-                callback: query.callback,
+                callback: query.callback.unwrap_or_default(),
                 transactions: Vec::new(),
                 payment_account,
                 death,
+                received: Balance(0),
+                merchant: query.merchant,
+                splits: query.splits.unwrap_or_default(),
+                created: Some(created),
+                recipient: Some(recipient),
+            }
+        }
+    }
+
+    impl Serialize for OrderInfo {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            #[derive(Serialize)]
+            struct OrderInfoApi<'a> {
+                withdrawal_status: &'a WithdrawalStatus,
+                payment_status: &'a PaymentStatus,
+                amount: String,
+                currency: &'a CurrencyInfo,
+                callback: &'a str,
+                transactions: &'a [TransactionInfo],
+                payment_account: &'a str,
+                death: Timestamp,
+                received: String,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                merchant: Option<&'a str>,
+                #[serde(skip_serializing_if = "Vec::is_empty")]
+                splits: Vec<PayoutSplitApi<'a>>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                created: Option<Timestamp>,
             }
+
+            #[derive(Serialize)]
+            struct PayoutSplitApi<'a> {
+                recipient: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                percent: Option<String>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                amount: Option<String>,
+            }
+
+            OrderInfoApi {
+                withdrawal_status: &self.withdrawal_status,
+                payment_status: &self.payment_status,
+                amount: self.amount.format(self.currency.decimals),
+                currency: &self.currency,
+                callback: &self.callback,
+                transactions: &self.transactions,
+                payment_account: &self.payment_account,
+                death: self.death,
+                received: self.received.format(self.currency.decimals),
+                merchant: self.merchant.as_deref(),
+                splits: self
+                    .splits
+                    .iter()
+                    .map(|split| {
+                        let (percent, amount) = match split.share {
+                            SplitShare::Percent(percent) => {
+                                (Some(Balance(percent.into()).format(PERCENT_DECIMALS)), None)
+                            }
+                            SplitShare::Fixed(amount) => {
+                                (None, Some(amount.format(self.currency.decimals)))
+                            }
+                        };
+
+                        PayoutSplitApi {
+                            recipient: &split.recipient,
+                            percent,
+                            amount,
+                        }
+                    })
+                    .collect(),
+                created: self.created,
+            }
+            .serialize(serializer)
         }
     }
 
     pub enum OrderCreateResponse {
         New(OrderInfo),
-        Modified(OrderInfo),
+        /// The modified order along with its currency before the modification.
+        Modified(OrderInfo, CurrencyInfo),
         Collision(OrderInfo),
+        /// The modification isn't allowed by the policy, for the given reason.
+        Rejected(OrderInfo, String),
+        /// The order ID is taken by an order of another merchant.
+        Foreign,
     }
 
-    #[derive(Clone, Debug, Serialize, Decode, Encode, PartialEq)]
+    #[derive(Clone, Debug, Serialize, Deserialize, Decode, Encode, PartialEq)]
     #[serde(rename_all = "lowercase")]
     pub enum PaymentStatus {
         Pending,
         Paid,
+        /// The order has expired unpaid. Funds that arrive after that aren't counted towards it,
+        /// and can only be returned with a forced withdrawal.
+        #[serde(rename = "timed_out")]
+        TimedOut,
+        /// Some funds have arrived, but not enough to pay the order.
+        Underpaid,
     }
 
-    #[derive(Clone, Debug, Serialize, Decode, Encode, PartialEq)]
+    #[derive(Clone, Debug, Serialize, Deserialize, Decode, Encode, PartialEq)]
     #[serde(rename_all = "lowercase")]
     pub enum WithdrawalStatus {
         Waiting,
         Failed,
         Forced,
         Completed,
+        /// The received funds were returned to the payer.
+        Refunded,
     }
 
-    #[derive(Clone, Debug, Serialize, Deserialize)]
+    /// Transition in the order lifecycle that merchants are notified about
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, Encode, Decode, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum OrderEvent {
+        /// A payment has arrived, but the order isn't paid in full yet.
+        PartiallyPaid,
+        Paid,
+        /// The order has expired unpaid.
+        Expired,
+        PayoutSubmitted,
+        PayoutFinalized,
+        PayoutFailed,
+    }
+
+    /// Who has caused a change of an order.
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, Encode, Decode, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ChangeSource {
+        /// A merchant's request to create or modify the order.
+        Api,
+        /// The chain tracker, including outcomes of payouts and refunds it has submitted.
+        Tracker,
+        /// An administrator's request, e.g., a forced withdrawal or a refund.
+        Admin,
+    }
+
+    /// Source of a change of an order, along with the block it was observed at.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct ChangeOrigin {
+        pub source: ChangeSource,
+        pub block_number: Option<BlockNumber>,
+    }
+
+    impl ChangeOrigin {
+        pub const API: Self = Self {
+            source: ChangeSource::Api,
+            block_number: None,
+        };
+        pub const ADMIN: Self = Self {
+            source: ChangeSource::Admin,
+            block_number: None,
+        };
+
+        pub fn tracker(block_number: Option<BlockNumber>) -> Self {
+            Self {
+                source: ChangeSource::Tracker,
+                block_number,
+            }
+        }
+    }
+
+    /// Field of [`OrderInfo`] that the order history follows.
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, Encode, Decode, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum OrderField {
+        PaymentStatus,
+        WithdrawalStatus,
+        Amount,
+        Currency,
+        Death,
+        Received,
+    }
+
+    /// A single change of an order field. History entries are never modified or removed.
+    #[derive(Clone, Debug, Serialize, Encode, Decode)]
+    pub struct OrderHistoryEntry {
+        pub timestamp: Timestamp,
+        pub source: ChangeSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub block_number: Option<BlockNumber>,
+        pub field: OrderField,
+        /// The value before the change, or [`None`] if the order has just been created.
+        pub previous: Option<String>,
+        pub new: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct OrderHistory {
+        pub order: String,
+        pub history: Vec<OrderHistoryEntry>,
+    }
+
+    /// Body of a signed webhook.
+    #[derive(Debug, Serialize)]
+    pub struct WebhookPayload {
+        pub event: OrderEvent,
+        #[serde(flatten)]
+        pub order_status: OrderStatus,
+    }
+
+    /// A callback to the merchant queued for delivery, along with its delivery history.
+    #[derive(Clone, Debug, Serialize, Encode, Decode)]
+    pub struct WebhookInfo {
+        pub id: WebhookId,
+        pub order: String,
+        pub event: OrderEvent,
+        pub url: String,
+        /// Serialized [`WebhookPayload`] made at the moment the webhook was enqueued.
+        #[serde(skip)]
+        pub payload: String,
+        pub status: WebhookStatus,
+        pub created: Timestamp,
+        /// When the next delivery attempt is due. Only pending webhooks have it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub next_attempt: Option<Timestamp>,
+        pub attempts: Vec<WebhookAttempt>,
+    }
+
+    pub type WebhookId = u64;
+
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, Decode, Encode, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum WebhookStatus {
+        /// Waiting for the first or the next delivery attempt.
+        Pending,
+        Delivered,
+        /// All delivery attempts have failed. Only a manual redelivery sends it again.
+        Failed,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, Encode, Decode)]
+    pub struct WebhookAttempt {
+        pub timestamp: Timestamp,
+        /// Why the attempt has failed, or [`None`] if the merchant has accepted the webhook.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+    }
+
+    /// Filters of a webhook listing.
+    #[derive(Debug, Default, Deserialize)]
+    pub struct WebhookListQuery {
+        pub order: Option<String>,
+        pub status: Option<WebhookStatus>,
+    }
+
+    #[derive(Clone, Debug, Serialize)]
     pub struct ServerStatus {
         pub server_info: ServerInfo,
         pub supported_currencies: HashMap<std::string::String, CurrencyProperties>,
@@ -193,11 +798,77 @@ pub mod api_v2 {
         pub status: Health,
     }
 
+    #[derive(Debug, Serialize)]
+    pub struct AuditReport {
+        pub server_info: ServerInfo,
+        pub timestamp: Timestamp,
+        pub totals: HashMap<String, CurrencyTotals>,
+        pub discrepancies: Vec<Discrepancy>,
+    }
+
+    /// Sums over all orders in a single currency.
+    #[derive(Debug, Default, Serialize)]
+    pub struct CurrencyTotals {
+        pub orders: u64,
+        pub pending: u64,
+        pub paid: u64,
+        pub timed_out: u64,
+        pub underpaid: u64,
+        pub paid_amount: String,
+        pub balance: String,
+        pub discrepancies: u64,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Discrepancy {
+        pub order: String,
+        pub kind: DiscrepancyKind,
+        pub payment_account: String,
+        pub currency: String,
+        pub payment_status: PaymentStatus,
+        pub withdrawal_status: WithdrawalStatus,
+        pub amount: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub balance: Option<String>,
+    }
+
+    #[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum DiscrepancyKind {
+        /// The order is paid, but its funds are still in the payment account.
+        UnwithdrawnFunds,
+        /// The order is withdrawn, but the payment account still holds funds.
+        FundsAfterWithdrawal,
+        /// The order is pending, but the payment account already holds enough to pay it.
+        UnrecordedPayment,
+        /// The order has expired unpaid with some funds in the payment account.
+        ExpiredWithFunds,
+        /// The order is withdrawn, but no withdrawal transaction is recorded.
+        MissingWithdrawalTransaction,
+        /// The payment account balance couldn't be fetched.
+        BalanceUnavailable,
+    }
+
     #[derive(Debug, Serialize, Clone)]
     pub struct RpcInfo {
         pub rpc_url: String,
         pub chain_name: String,
         pub status: Health,
+        /// From 0 to 100, the weight of the endpoint in the random pick.
+        pub score: u64,
+        /// Moving average of the response time in milliseconds.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub latency: Option<u64>,
+        /// Recent consecutive failures.
+        pub failures: u32,
+        /// Blocks behind the best block of the other endpoints of the chain.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub block_lag: Option<BlockNumber>,
+        /// Payment confirmations where the endpoint reported a balance different from the
+        /// others.
+        pub disagreements: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub cooldown_until: Option<Timestamp>,
     }
 
     #[derive(Debug, Serialize, Clone, PartialEq, Copy)]
@@ -208,7 +879,7 @@ pub mod api_v2 {
         Critical,
     }
 
-    #[derive(Clone, Debug, Serialize, Decode, Encode)]
+    #[derive(Clone, Debug, Serialize, Deserialize, Decode, Encode)]
     pub struct CurrencyInfo {
         pub currency: String,
         pub chain_name: String,
@@ -221,29 +892,44 @@ pub mod api_v2 {
         pub ss58: u16,
     }
 
-    impl CurrencyInfo {
-        pub fn properties(&self) -> CurrencyProperties {
-            CurrencyProperties {
-                chain_name: self.chain_name.clone(),
-                kind: self.kind,
-                decimals: self.decimals,
-                rpc_url: self.rpc_url.clone(),
-                asset_id: self.asset_id,
-                ss58: self.ss58,
-            }
-        }
-    }
-
-    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[derive(Clone, Debug)]
     pub struct CurrencyProperties {
         pub chain_name: String,
         pub kind: TokenKind,
         pub decimals: Decimals,
         pub rpc_url: String,
-        #[serde(skip_serializing_if = "Option::is_none")]
         pub asset_id: Option<AssetId>,
-        // #[serde(skip_serializing)]
         pub ss58: u16,
+        /// The smallest balance an account can hold: `Balances::ExistentialDeposit` for the native
+        /// token, and `min_balance` for assets.
+        pub existential_deposit: Balance,
+    }
+
+    impl Serialize for CurrencyProperties {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            #[derive(Serialize)]
+            struct CurrencyPropertiesApi<'a> {
+                chain_name: &'a str,
+                kind: TokenKind,
+                decimals: Decimals,
+                rpc_url: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                asset_id: Option<AssetId>,
+                ss58: u16,
+                existential_deposit: String,
+            }
+
+            CurrencyPropertiesApi {
+                chain_name: &self.chain_name,
+                kind: self.kind,
+                decimals: self.decimals,
+                rpc_url: &self.rpc_url,
+                asset_id: self.asset_id,
+                ss58: self.ss58,
+                existential_deposit: self.existential_deposit.format(self.decimals),
+            }
+            .serialize(serializer)
+        }
     }
 
     impl CurrencyProperties {
@@ -276,17 +962,16 @@ pub mod api_v2 {
         pub kalatori_remark: Option<String>,
     }
 
-    #[derive(Clone, Debug, Serialize, Decode, Encode)]
+    #[derive(Clone, Debug, Decode, Encode)]
     pub struct TransactionInfo {
-        #[serde(skip_serializing_if = "Option::is_none", flatten)]
         pub finalized_tx: Option<FinalizedTx>, // Clearly undefined in v2.1 - TODO
         pub transaction_bytes: String,
         pub sender: String,
         pub recipient: String,
-        #[serde(serialize_with = "amount_serializer")]
         pub amount: Amount,
         pub currency: CurrencyInfo,
         pub status: TxStatus,
+        pub kind: TxKind,
     }
 
     #[derive(Clone, Debug, Serialize, Decode, Encode)]
@@ -296,42 +981,89 @@ pub mod api_v2 {
         pub timestamp: String,
     }
 
+    impl Serialize for TransactionInfo {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            #[derive(Serialize)]
+            struct TransactionInfoApi<'a> {
+                #[serde(skip_serializing_if = "Option::is_none", flatten)]
+                finalized_tx: Option<&'a FinalizedTx>,
+                transaction_bytes: &'a str,
+                sender: &'a str,
+                recipient: &'a str,
+                amount: String,
+                currency: &'a CurrencyInfo,
+                status: &'a TxStatus,
+                kind: TxKind,
+            }
+
+            TransactionInfoApi {
+                finalized_tx: self.finalized_tx.as_ref(),
+                transaction_bytes: &self.transaction_bytes,
+                sender: &self.sender,
+                recipient: &self.recipient,
+                amount: self.amount.format(self.currency.decimals),
+                currency: &self.currency,
+                status: &self.status,
+                kind: self.kind,
+            }
+            .serialize(serializer)
+        }
+    }
+
     #[derive(Clone, Debug, Decode, Encode)]
     pub enum Amount {
         All,
-        Exact(f64),
+        Exact(Balance),
     }
 
-    fn amount_serializer<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
-        match amount {
-            Amount::All => serializer.serialize_str("all"),
-            Amount::Exact(exact) => exact.serialize(serializer),
+    impl Amount {
+        /// Formats the amount for the API: `all` or an exact decimal string.
+        pub fn format(&self, decimals: Decimals) -> String {
+            match self {
+                Amount::All => "all".into(),
+                Amount::Exact(exact) => exact.format(decimals),
+            }
         }
     }
 
-    #[derive(Clone, Debug, Serialize, Decode, Encode)]
+    #[derive(Clone, Debug, Serialize, Deserialize, Decode, Encode)]
     #[serde(rename_all = "lowercase")]
     pub enum TxStatus {
         Pending,
         Finalized,
         Failed,
     }
+
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, Decode, Encode, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TxKind {
+        Payment,
+        Withdrawal,
+        /// Return of funds to the payer.
+        Refund,
+    }
 }
 
 #[cfg(test)]
 #[test]
 #[allow(
     clippy::inconsistent_digit_grouping,
-    clippy::unreadable_literal,
-    clippy::float_cmp
+    clippy::large_digit_groups,
+    clippy::unreadable_literal
 )]
-
-fn balance_insufficient_precision() {
+fn balance_exact_precision() {
     const DECIMALS: api_v2::Decimals = 10;
 
-    let float = 931395.862219815_3;
-    let parsed = Balance::parse(float, DECIMALS);
+    let parsed = Balance::parse("931395.8622198153", DECIMALS).unwrap();
+
+    assert_eq!(*parsed, 931395_8622198153);
+    assert_eq!(parsed.format(DECIMALS), "931395.8622198153");
 
-    assert_eq!(*parsed, 931395_862219815_2);
-    assert_eq!(parsed.format(DECIMALS), 931395.862219815_1);
+    assert_eq!(Balance(5).format(DECIMALS), "0.0000000005");
+    assert_eq!(Balance(40_000_000_000).format(DECIMALS), "4");
+    assert_eq!(Balance::parse("4", DECIMALS), Some(Balance(40_000_000_000)));
+    assert_eq!(Balance::parse("0.00000000001", DECIMALS), None);
+    assert_eq!(Balance::parse("-1", DECIMALS), None);
+    assert_eq!(Balance::parse(".5", DECIMALS), None);
+    assert_eq!(Balance::parse("1e3", DECIMALS), None);
 }
@@ -1,6 +1,6 @@
 use crate::{
     arguments::{OLD_SEED, SEED},
-    definitions::api_v2::OrderStatus,
+    definitions::{api_v2::WebhookId, Version},
     utils::task_tracker::TaskName,
 };
 use codec::Error as ScaleError;
@@ -38,6 +38,12 @@ pub enum Error {
     #[error("chain {0:?} doesn't have any `endpoints` in the config")]
     EmptyEndpoints(String),
 
+    #[error("`quorum` of chain {0:?} must be from 1 to the number of its `endpoints`")]
+    InvalidQuorum(String),
+
+    #[error("`query-batch-size` of chain {0:?} must be at least 1")]
+    InvalidQueryBatchSize(String),
+
     #[error("RPC server error is occurred")]
     Chain(#[from] ChainError),
 
@@ -73,6 +79,15 @@ pub enum Error {
 
     #[error("found duplicate config record for the token {0:?}")]
     DuplicateCurrency(String),
+
+    #[error("found duplicate merchant {0:?} in the config")]
+    DuplicateMerchant(String),
+
+    #[error("webhooks of {0} are `signed`, but there are no non-empty secrets to sign them with")]
+    WebhookSecrets(String),
+
+    #[error("{0} needs a sled database on the disk")]
+    NotSledDatabase(&'static str),
 }
 
 impl From<CryptoError> for Error {
@@ -94,7 +109,7 @@ impl From<CryptoError> for SignerError {
 }
 
 impl From<Error> for ChainError {
-    fn from(err: Error) -> Self {
+    fn from(_: Error) -> Self {
         ChainError::Util(UtilError::NotHex(NotHexError::BlockHash))
     }
 }
@@ -150,6 +165,9 @@ pub enum ChainError {
     #[error("no balance field in an asset record")]
     AssetBalanceNotFound,
 
+    #[error("no minimal balance field in an asset record")]
+    AssetMinBalanceNotFound,
+
     #[error("format of the fetched Base58 prefix {0:?} isn't supported")]
     Base58PrefixFormatNotSupported(String),
 
@@ -159,6 +177,12 @@ pub enum ChainError {
     #[error("unexpected block number format")]
     BlockNumberFormat,
 
+    #[error("unexpected account nonce format")]
+    NonceFormat,
+
+    #[error("unexpected transaction fee format")]
+    FeeFormat,
+
     #[error("unexpected block hash format")]
     BlockHashFormat,
 
@@ -192,6 +216,9 @@ pub enum ChainError {
     #[error("no decimals value is fetched")]
     NoDecimals,
 
+    #[error("existential deposit of the native token isn't found")]
+    NoExistentialDeposit,
+
     #[error("metadata v15 isn't available through RPC")]
     NoMetadataV15,
 
@@ -351,12 +378,18 @@ pub enum DbError {
     #[error("order {0:?} isn't found")]
     OrderNotFound(String),
 
+    #[error("payment account {0:?} couldn't be parsed")]
+    InvalidPaymentAccount(String),
+
     #[error("order {0:?} was already paid")]
     AlreadyPaid(String),
 
     #[error("order {0:?} isn't paid yet")]
     NotPaid(String),
 
+    #[error("order {0:?} has timed out")]
+    TimedOut(String),
+
     #[error("there was already an attempt to withdraw order {0:?}")]
     WithdrawalWasAttempted(String),
 
@@ -365,17 +398,44 @@ pub enum DbError {
 
     #[error("wasn't able to deserialize {0:?} table")]
     DeserializationError(String),
+
+    #[error("database version {0} is newer than the supported one")]
+    UnsupportedVersion(Version),
+
+    #[error("webhook {0} isn't found")]
+    WebhookNotFound(WebhookId),
+
+    #[error("webhook {0} hasn't failed")]
+    WebhookNotFailed(WebhookId),
+
+    #[error("SQLite database error is occurred")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("database {0:?} already has orders")]
+    NotEmpty(String),
+
+    #[error("database {0:?} doesn't exist")]
+    NotFound(String),
 }
 
 #[derive(Debug, Error)]
 #[allow(clippy::module_name_repetitions)]
 pub enum OrderError {
     #[error("invoice amount is less than the existential deposit")]
-    LessThanExistentialDeposit(f64),
+    LessThanExistentialDeposit(String),
 
     #[error("unknown currency")]
     UnknownCurrency,
 
+    #[error("unknown merchant")]
+    UnknownMerchant,
+
+    #[error("order ID is taken by another merchant")]
+    ForeignOrder,
+
+    #[error("payout splits are invalid: {0}")]
+    InvalidSplits(String),
+
     #[error("order parameter is missing: {0:?}")]
     MissingParameter(String),
 
@@ -397,6 +457,65 @@ pub enum ForceWithdrawalError {
 
     #[error("withdrawal was failed: \"{0:?}\"")]
     WithdrawalError(String),
+
+    #[error("order {0:?} isn't found")]
+    OrderNotFound(String),
+
+    #[error("there was already an attempt to withdraw order {0:?}")]
+    WithdrawalWasAttempted(String),
+
+    #[error("withdrawal couldn't be submitted: {0}")]
+    NotSubmitted(ChainError),
+
+    #[error("internal error is occurred")]
+    InternalError,
+}
+
+#[derive(Debug, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum ReleaseError {
+    #[error("order {0:?} isn't found")]
+    OrderNotFound(String),
+
+    #[error("order {0:?} isn't paid out")]
+    NotPaidOut(String),
+
+    #[error("there are no held funds on the payment account of order {0:?}")]
+    NothingHeld(String),
+
+    #[error("release couldn't be submitted: {0}")]
+    NotSubmitted(ChainError),
+
+    #[error("internal error is occurred")]
+    InternalError,
+}
+
+#[derive(Debug, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum RefundError {
+    #[error("order {0:?} isn't found")]
+    OrderNotFound(String),
+
+    #[error("order parameter is invalid: {0:?}")]
+    InvalidParameter(String),
+
+    #[error("refund amount exceeds the balance of the payment account ({0})")]
+    ExceedsBalance(String),
+
+    #[error("refund would leave less than the existential deposit ({0}) on the payment account")]
+    LessThanExistentialDeposit(String),
+
+    #[error("order {0:?} has no payments to refund")]
+    NoPayers(String),
+
+    #[error("there was already an attempt to withdraw order {0:?}")]
+    WithdrawalWasAttempted(String),
+
+    #[error("refund couldn't be submitted: {0}")]
+    NotSubmitted(ChainError),
+
+    #[error("internal error is occurred")]
+    InternalError,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -407,6 +526,37 @@ pub enum ServerError {
 
     #[error("internal threading error is occurred")]
     ThreadError,
+
+    #[error("found duplicate API key {0:?} in the config")]
+    DuplicateApiKey(String),
+}
+
+#[derive(Debug, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum AuthError {
+    #[error("request credentials are missing")]
+    MissingCredentials,
+
+    #[error("request header {0:?} is missing")]
+    MissingHeader(&'static str),
+
+    #[error("request header {0:?} is invalid")]
+    InvalidHeader(&'static str),
+
+    #[error("API key isn't found")]
+    UnknownKey,
+
+    #[error("API key accepts only signed requests")]
+    SignatureRequired,
+
+    #[error("request signature is invalid")]
+    InvalidSignature,
+
+    #[error("request timestamp is out of the allowed window")]
+    StaleTimestamp,
+
+    #[error("request nonce was already used")]
+    ReplayedNonce,
 }
 
 #[derive(Debug, Error)]
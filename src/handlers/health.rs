@@ -1,6 +1,11 @@
 use crate::definitions::api_v2::{ServerHealth, ServerStatus};
 use crate::state::State;
-use axum::{extract::State as ExtractState, http::StatusCode, Json};
+use axum::{
+    extract::State as ExtractState,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 
 pub async fn status(
     ExtractState(state): ExtractState<State>,
@@ -32,6 +37,17 @@ pub async fn health(
     }
 }
 
-pub async fn audit(ExtractState(_state): ExtractState<State>) -> StatusCode {
-    StatusCode::NOT_IMPLEMENTED
+pub async fn audit(ExtractState(state): ExtractState<State>) -> Response {
+    match state.audit().await {
+        Ok(report) => (
+            [(axum::http::header::CACHE_CONTROL, "no-store")],
+            Json(report),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to audit orders: {e:?}");
+
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
 }
@@ -1,77 +1,259 @@
 use crate::{
+    chain::investigate::MAX_BLOCKS,
     definitions::api_v2::{
-        InvalidParameter, OrderQuery, OrderResponse, OrderStatus, AMOUNT, CURRENCY,
+        splits_rejection, BlockNumber, CurrencyProperties, Decimals, InvalidParameter,
+        InvestigationResponse, OrderEvent, OrderInfo, OrderList, OrderListQuery, OrderQuery,
+        OrderResponse, OrderStatus, PayoutSplit, PublicOrderInfo, SplitShare, AMOUNT, CREATED_FROM,
+        CURRENCY, DEATH_FROM, FROM_BLOCK, LIMIT, MERCHANT, PAYMENT_ACCOUNT, PERCENT_DECIMALS,
+        SPLITS, TO_BLOCK, WHOLE_SHARE,
     },
-    error::{ForceWithdrawalError, OrderError},
+    definitions::Balance,
+    error::{Error, ForceWithdrawalError, OrderError, RefundError, ReleaseError},
+    server::auth::KeyMerchant,
     state::State,
 };
 use axum::{
-    extract::{Path, State as ExtractState},
+    extract::{rejection::QueryRejection, Extension, Path, Query, State as ExtractState},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Deserialize;
+use substrate_crypto_light::common::{AccountId32, AsBase58};
+
+/// Largest number of orders in a single page of an order listing.
+const MAX_PAGE_SIZE: usize = 500;
+/// Largest number of payout splits of an order, as all of them are paid out in a single batch.
+const MAX_SPLITS: usize = 16;
 
-const EXISTENTIAL_DEPOSIT: f64 = 0.07;
 #[derive(Debug, Deserialize)]
 pub struct OrderPayload {
-    pub amount: Option<f64>,
+    pub amount: Option<AmountPayload>,
     pub currency: Option<String>,
     pub callback: Option<String>,
+    /// Events to send webhooks for; all of them if omitted.
+    pub events: Option<Vec<OrderEvent>>,
+    /// Merchant profile to create the order for; the default recipient if omitted. Keys bound to
+    /// a merchant may only give their own.
+    pub merchant: Option<String>,
+    /// Parts of the amount paid out to other recipients; everything goes to the merchant
+    /// recipient if omitted.
+    pub splits: Option<Vec<SplitPayload>>,
+}
+
+/// Payout split with either a percentage of the order amount or an exact amount
+#[derive(Debug, Deserialize)]
+pub struct SplitPayload {
+    pub recipient: String,
+    pub percent: Option<String>,
+    pub amount: Option<AmountPayload>,
+}
+
+/// Order amount as a decimal string, or as a JSON number for compatibility with older clients.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AmountPayload {
+    Decimal(String),
+    Number(serde_json::Number),
+}
+
+impl AmountPayload {
+    fn decimal(&self) -> String {
+        match self {
+            AmountPayload::Decimal(decimal) => decimal.clone(),
+            AmountPayload::Number(number) => number.to_string(),
+        }
+    }
+
+    /// Amount in the smallest units of a currency with the given decimals. JSON numbers, including
+    /// the exponent forms like `1e-7`, are rounded to the units through [`f64`] just like the API
+    /// did before amounts became strings.
+    fn balance(&self, decimals: Decimals) -> Option<Balance> {
+        match self {
+            AmountPayload::Decimal(decimal) => Balance::parse(decimal, decimals),
+            AmountPayload::Number(number) => {
+                let units = (number.as_f64()? * 10f64.powi(decimals.into())).round();
+
+                // Casts saturate, so amounts out of the range have to be rejected beforehand.
+                #[expect(
+                    clippy::cast_possible_truncation,
+                    clippy::cast_precision_loss,
+                    clippy::cast_sign_loss
+                )]
+                (units >= 0.0 && units < u128::MAX as f64).then_some(Balance(units as u128))
+            }
+        }
+    }
+}
+
+/// Orders of other merchants are hidden from keys bound to a merchant.
+fn visible(key_merchant: Option<&KeyMerchant>, order_info: &OrderInfo) -> bool {
+    key_merchant.is_none_or(|KeyMerchant(merchant)| order_info.merchant.as_ref() == Some(merchant))
 }
 
 pub async fn process_order(
     state: State,
     order_id: String,
     payload: Option<OrderPayload>,
+    key_merchant: Option<KeyMerchant>,
 ) -> Result<OrderResponse, OrderError> {
     if let Some(payload) = payload {
+        // MERCHANT validation
+        let merchant = match (key_merchant, payload.merchant) {
+            (Some(KeyMerchant(bound)), Some(requested)) if bound != requested => {
+                return Err(OrderError::InvalidParameter(MERCHANT.into()));
+            }
+            (Some(KeyMerchant(bound)), _) => Some(bound),
+            (None, requested) => requested,
+        };
+
         // AMOUNT validation
-        if payload.amount.is_none() {
+        let Some(amount_payload) = payload.amount else {
             return Err(OrderError::MissingParameter(AMOUNT.to_string()));
-        } else if payload.amount.unwrap() < EXISTENTIAL_DEPOSIT {
-            return Err(OrderError::LessThanExistentialDeposit(EXISTENTIAL_DEPOSIT));
-        }
+        };
 
         // CURRENCY validation
-        if payload.currency.is_none() {
+        let Some(currency) = payload.currency else {
             return Err(OrderError::MissingParameter(CURRENCY.to_string()));
-        } else {
-            let currency = payload.currency.clone().unwrap();
-            if !state
-                .is_currency_supported(&currency)
-                .await
-                .map_err(|_| OrderError::InternalError)?
-            {
-                return Err(OrderError::UnknownCurrency);
-            }
-        }
+        };
+        let Some(properties) = state
+            .currency(&currency)
+            .await
+            .map_err(|_| OrderError::InternalError)?
+        else {
+            return Err(OrderError::UnknownCurrency);
+        };
+        let amount = order_amount(&amount_payload, &properties)?;
+
+        // SPLITS validation
+        let splits = payload
+            .splits
+            .map(|splits| validate_splits(splits, amount, &properties))
+            .transpose()?;
 
         state
             .create_order(OrderQuery {
                 order: order_id,
-                amount: payload.amount.unwrap(),
-                callback: payload.callback.unwrap_or_default(),
-                currency: payload.currency.unwrap(),
+                amount,
+                callback: payload.callback,
+                currency,
+                events: payload.events,
+                merchant,
+                splits,
             })
             .await
-            .map_err(|_| OrderError::InternalError)
+            .map_err(|error| match error {
+                Error::Order(order_error) => order_error,
+                _ => OrderError::InternalError,
+            })
     } else {
-        return state
-            .order_status(&order_id)
-            .await
-            .map_err(|_| OrderError::InternalError);
+        match state.order_status(&order_id).await {
+            Ok(OrderResponse::FoundOrder(order_status))
+                if !visible(key_merchant.as_ref(), &order_status.order_info) =>
+            {
+                Ok(OrderResponse::NotFound)
+            }
+            Ok(response) => Ok(response),
+            Err(_) => Err(OrderError::InternalError),
+        }
+    }
+}
+
+/// Parses the order amount, and checks that the payment account can exist with it.
+fn order_amount(
+    amount_payload: &AmountPayload,
+    properties: &CurrencyProperties,
+) -> Result<Balance, OrderError> {
+    let existential_deposit = properties.existential_deposit.format(properties.decimals);
+
+    let Some(amount) = amount_payload.balance(properties.decimals) else {
+        // Negative amounts are rejected just like the ones below the existential deposit.
+        return Err(if amount_payload.decimal().starts_with('-') {
+            OrderError::LessThanExistentialDeposit(existential_deposit)
+        } else {
+            OrderError::InvalidParameter(AMOUNT.to_string())
+        });
+    };
+
+    // The payment account must be able to exist to receive the payment.
+    if *amount == 0 || amount < properties.existential_deposit {
+        return Err(OrderError::LessThanExistentialDeposit(existential_deposit));
+    }
+
+    Ok(amount)
+}
+
+/// Checks that each split is paid out in a transfer of its own that the recipient account can
+/// accept, and that all of them fit into the order amount.
+fn validate_splits(
+    payloads: Vec<SplitPayload>,
+    amount: Balance,
+    properties: &CurrencyProperties,
+) -> Result<Vec<PayoutSplit>, OrderError> {
+    if payloads.len() > MAX_SPLITS {
+        return Err(OrderError::InvalidSplits(format!(
+            "an order can have at most {MAX_SPLITS} splits"
+        )));
+    }
+
+    let mut splits = Vec::with_capacity(payloads.len());
+
+    for payload in payloads {
+        if AccountId32::from_base58_string(&payload.recipient).is_err() {
+            return Err(OrderError::InvalidSplits(format!(
+                "recipient {:?} isn't a valid address",
+                payload.recipient
+            )));
+        }
+
+        let share = match (payload.percent, payload.amount) {
+            (Some(percent), None) => Balance::parse(&percent, PERCENT_DECIMALS)
+                .and_then(|parsed| u16::try_from(*parsed).ok())
+                .filter(|parsed| (1..=WHOLE_SHARE).contains(parsed))
+                .map(SplitShare::Percent)
+                .ok_or_else(|| {
+                    OrderError::InvalidSplits(format!(
+                        "percent {percent:?} must be a number from 0.01 to 100 with at most \
+                         {PERCENT_DECIMALS} decimals"
+                    ))
+                })?,
+            (None, Some(amount_payload)) => amount_payload
+                .balance(properties.decimals)
+                .map(SplitShare::Fixed)
+                .ok_or_else(|| {
+                    OrderError::InvalidSplits(format!(
+                        "amount {:?} must be a number with at most {} decimals",
+                        amount_payload.decimal(),
+                        properties.decimals
+                    ))
+                })?,
+            _ => {
+                return Err(OrderError::InvalidSplits(
+                    "each split must have either `percent` or `amount`".into(),
+                ))
+            }
+        };
+
+        splits.push(PayoutSplit {
+            recipient: payload.recipient,
+            share,
+        });
+    }
+
+    match splits_rejection(&splits, amount, properties) {
+        Some(reason) => Err(OrderError::InvalidSplits(reason)),
+        None => Ok(splits),
     }
 }
 
 pub async fn order(
     ExtractState(state): ExtractState<State>,
     Path(order_id): Path<String>,
+    key_merchant: Option<Extension<KeyMerchant>>,
     payload: Option<Json<OrderPayload>>,
 ) -> Response {
     let payload = payload.map(|p| p.0);
-    match process_order(state, order_id, payload).await {
+    match process_order(state, order_id, payload, key_merchant.map(|k| k.0)).await {
         Ok(order) => match order {
             OrderResponse::NewOrder(order_status) => (StatusCode::CREATED, Json(order_status)).into_response(),
             OrderResponse::FoundOrder(order_status) => (StatusCode::OK, Json(order_status)).into_response(),
@@ -96,6 +278,27 @@ pub async fn order(
                 }]),
             )
                 .into_response(),
+            OrderError::UnknownMerchant => (
+                StatusCode::BAD_REQUEST,
+                Json([InvalidParameter {
+                    parameter: MERCHANT.into(),
+                    message: "provided merchant isn't configured".into(),
+                }]),
+            )
+                .into_response(),
+            OrderError::ForeignOrder => (
+                StatusCode::CONFLICT,
+                "Order with this ID belongs to another merchant",
+            )
+                .into_response(),
+            OrderError::InvalidSplits(message) => (
+                StatusCode::BAD_REQUEST,
+                Json([InvalidParameter {
+                    parameter: SPLITS.into(),
+                    message,
+                }]),
+            )
+                .into_response(),
             OrderError::MissingParameter(parameter) => (
                 StatusCode::BAD_REQUEST,
                 Json([InvalidParameter {
@@ -117,6 +320,103 @@ pub async fn order(
     }
 }
 
+pub async fn process_list_orders(
+    state: State,
+    mut query: OrderListQuery,
+    key_merchant: Option<KeyMerchant>,
+) -> Result<OrderList, OrderError> {
+    if let Some(KeyMerchant(merchant)) = key_merchant {
+        query.merchant = Some(merchant);
+    }
+
+    // LIMIT validation
+    if query
+        .limit
+        .is_some_and(|limit| limit == 0 || limit > MAX_PAGE_SIZE)
+    {
+        return Err(OrderError::InvalidParameter(LIMIT.into()));
+    }
+
+    // TIME RANGE validation
+    if let (Some(from), Some(to)) = (query.created_from, query.created_to) {
+        if from.0 > to.0 {
+            return Err(OrderError::InvalidParameter(CREATED_FROM.into()));
+        }
+    }
+    if let (Some(from), Some(to)) = (query.death_from, query.death_to) {
+        if from.0 > to.0 {
+            return Err(OrderError::InvalidParameter(DEATH_FROM.into()));
+        }
+    }
+
+    state
+        .list_orders(query)
+        .await
+        .map_err(|_| OrderError::InternalError)
+}
+
+pub async fn list_orders(
+    ExtractState(state): ExtractState<State>,
+    key_merchant: Option<Extension<KeyMerchant>>,
+    query_result: Result<Query<OrderListQuery>, QueryRejection>,
+) -> Response {
+    let query = match query_result {
+        Ok(Query(query)) => query,
+        Err(rejection) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json([InvalidParameter {
+                    parameter: "query".into(),
+                    message: rejection.body_text(),
+                }]),
+            )
+                .into_response()
+        }
+    };
+
+    match process_list_orders(state, query, key_merchant.map(|k| k.0)).await {
+        Ok(order_list) => (StatusCode::OK, Json(order_list)).into_response(),
+        Err(OrderError::InvalidParameter(parameter)) => (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter,
+                message: "parameter's value is out of range".into(),
+            }]),
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn order_history(
+    ExtractState(state): ExtractState<State>,
+    Path(order_id): Path<String>,
+    key_merchant: Option<Extension<KeyMerchant>>,
+) -> Response {
+    if let Some(Extension(bound)) = key_merchant {
+        match state.order_status(&order_id).await {
+            Ok(OrderResponse::FoundOrder(order_status))
+                if visible(Some(&bound), &order_status.order_info) => {}
+            Ok(_) => return (StatusCode::NOT_FOUND, "Order not found").into_response(),
+            Err(e) => {
+                tracing::error!("Failed to read order {order_id}: {e:?}");
+
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    match state.order_history(order_id.clone()).await {
+        Ok(Some(history)) => (StatusCode::OK, Json(history)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Order not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read the history of order {order_id}: {e:?}");
+
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 pub async fn process_force_withdrawal(
     state: State,
     order_id: String,
@@ -133,7 +433,15 @@ pub async fn force_withdrawal(
         Ok(OrderResponse::FoundOrder(order_status)) => {
             (StatusCode::CREATED, Json(order_status)).into_response()
         }
-        Ok(OrderResponse::NotFound) => (StatusCode::NOT_FOUND, "Order not found").into_response(),
+        Ok(OrderResponse::NotFound) | Err(ForceWithdrawalError::OrderNotFound(_)) => {
+            (StatusCode::NOT_FOUND, "Order not found").into_response()
+        }
+        Err(error @ ForceWithdrawalError::WithdrawalWasAttempted(_)) => {
+            (StatusCode::CONFLICT, error.to_string()).into_response()
+        }
+        Err(error @ ForceWithdrawalError::NotSubmitted(_)) => {
+            (StatusCode::BAD_GATEWAY, error.to_string()).into_response()
+        }
         Err(ForceWithdrawalError::WithdrawalError(a)) => {
             (StatusCode::BAD_REQUEST, Json(a)).into_response()
         }
@@ -161,10 +469,273 @@ pub async fn force_withdrawal(
     }
 }
 
+pub async fn process_public_payment_account(
+    state: State,
+    payment_account: String,
+) -> Result<Option<PublicOrderInfo>, OrderError> {
+    let (account, _) = AccountId32::from_base58_string(&payment_account)
+        .map_err(|_| OrderError::InvalidParameter(PAYMENT_ACCOUNT.into()))?;
+
+    state
+        .payment_account_status(account.0)
+        .await
+        .map_err(|_| OrderError::InternalError)
+}
+
+pub async fn public_payment_account(
+    ExtractState(state): ExtractState<State>,
+    Path(payment_account): Path<String>,
+) -> Response {
+    match process_public_payment_account(state, payment_account).await {
+        Ok(Some(order_info)) => (StatusCode::OK, Json(order_info)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "").into_response(),
+        Err(OrderError::InvalidParameter(parameter)) => (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter,
+                message: "parameter's format is invalid".into(),
+            }]),
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct InvestigatePayload {
+    pub from_block: Option<BlockNumber>,
+    pub to_block: Option<BlockNumber>,
+}
+
+pub async fn process_investigate(
+    state: State,
+    order_id: String,
+    payload: Option<InvestigatePayload>,
+) -> Result<Option<InvestigationResponse>, OrderError> {
+    let InvestigatePayload {
+        from_block,
+        to_block,
+    } = payload.unwrap_or_default();
+
+    // BLOCK RANGE validation
+    if let (Some(from), Some(to)) = (from_block, to_block) {
+        if from > to {
+            return Err(OrderError::InvalidParameter(FROM_BLOCK.into()));
+        } else if to.saturating_sub(from) >= MAX_BLOCKS {
+            return Err(OrderError::InvalidParameter(TO_BLOCK.into()));
+        }
+    }
+
+    state
+        .investigate(order_id, from_block, to_block)
+        .await
+        .map_err(|_| OrderError::InternalError)
+}
+
 pub async fn investigate(
-    ExtractState(_state): ExtractState<State>,
-    Path(_order_id): Path<String>,
+    ExtractState(state): ExtractState<State>,
+    Path(order_id): Path<String>,
+    payload: Option<Json<InvestigatePayload>>,
+) -> Response {
+    match process_investigate(state, order_id, payload.map(|p| p.0)).await {
+        Ok(Some(investigation)) => (StatusCode::OK, Json(investigation)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "").into_response(),
+        Err(OrderError::InvalidParameter(parameter)) => (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter,
+                message: format!(
+                    "block range must be ordered and span at most {MAX_BLOCKS} blocks"
+                ),
+            }]),
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefundPayload {
+    /// Amount to return to the payers; the whole balance if omitted.
+    pub amount: Option<AmountPayload>,
+}
+
+pub async fn process_refund(
+    state: State,
+    order_id: String,
+    payload: Option<RefundPayload>,
+) -> Result<OrderResponse, RefundError> {
+    let OrderResponse::FoundOrder(OrderStatus { order_info, .. }) = state
+        .order_status(&order_id)
+        .await
+        .map_err(|_| RefundError::InternalError)?
+    else {
+        return Ok(OrderResponse::NotFound);
+    };
+
+    let amount = match payload.and_then(|body| body.amount) {
+        Some(amount_payload) => Some(
+            amount_payload
+                .balance(order_info.currency.decimals)
+                .ok_or_else(|| RefundError::InvalidParameter(AMOUNT.into()))?,
+        ),
+        None => None,
+    };
+
+    state.refund(order_id, amount).await
+}
+
+pub async fn refund(
+    ExtractState(state): ExtractState<State>,
+    Path(order_id): Path<String>,
+    payload: Option<Json<RefundPayload>>,
 ) -> Response {
-    // Investigation logic will be implemented here as needed
-    StatusCode::NOT_IMPLEMENTED.into_response()
+    match process_refund(state, order_id, payload.map(|p| p.0)).await {
+        Ok(OrderResponse::FoundOrder(order_status)) => {
+            (StatusCode::CREATED, Json(order_status)).into_response()
+        }
+        Ok(_) | Err(RefundError::OrderNotFound(_)) => {
+            (StatusCode::NOT_FOUND, "Order not found").into_response()
+        }
+        Err(RefundError::InvalidParameter(parameter)) => (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter,
+                message: "parameter's format is invalid".into(),
+            }]),
+        )
+            .into_response(),
+        Err(RefundError::ExceedsBalance(balance)) => (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter: AMOUNT.into(),
+                message: format!(
+                    "provided amount exceeds the balance of the payment account ({balance})"
+                ),
+            }]),
+        )
+            .into_response(),
+        Err(RefundError::LessThanExistentialDeposit(existential_deposit)) => (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter: AMOUNT.into(),
+                message: format!("the rest of the balance would be less than the currency's existential deposit ({existential_deposit})"),
+            }]),
+        )
+            .into_response(),
+        Err(error @ (RefundError::NoPayers(_) | RefundError::WithdrawalWasAttempted(_))) => {
+            (StatusCode::CONFLICT, error.to_string()).into_response()
+        }
+        Err(error @ RefundError::NotSubmitted(_)) => {
+            (StatusCode::BAD_GATEWAY, error.to_string()).into_response()
+        }
+        Err(RefundError::InternalError) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn release(
+    ExtractState(state): ExtractState<State>,
+    Path(order_id): Path<String>,
+) -> Response {
+    match state.release(order_id).await {
+        Ok(OrderResponse::FoundOrder(order_status)) => {
+            (StatusCode::CREATED, Json(order_status)).into_response()
+        }
+        Ok(_) | Err(ReleaseError::OrderNotFound(_)) => {
+            (StatusCode::NOT_FOUND, "Order not found").into_response()
+        }
+        Err(error @ (ReleaseError::NotPaidOut(_) | ReleaseError::NothingHeld(_))) => {
+            (StatusCode::CONFLICT, error.to_string()).into_response()
+        }
+        Err(error @ ReleaseError::NotSubmitted(_)) => {
+            (StatusCode::BAD_GATEWAY, error.to_string()).into_response()
+        }
+        Err(ReleaseError::InternalError) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn order_list_query_from_uri() {
+    use crate::definitions::api_v2::PaymentStatus;
+
+    let uri = "/v2/orders?payment_status=paid&currency=USDC&death_to=1700000000000&limit=10"
+        .parse()
+        .unwrap();
+    let Query(query) = Query::<OrderListQuery>::try_from_uri(&uri).unwrap();
+
+    assert_eq!(query.payment_status, Some(PaymentStatus::Paid));
+    assert_eq!(query.currency.as_deref(), Some("USDC"));
+    assert_eq!(query.death_to.map(|death| death.0), Some(1_700_000_000_000));
+    assert_eq!(query.limit, Some(10));
+    assert!(query.cursor.is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn existential_deposit_of_amounts() {
+    use crate::definitions::api_v2::TokenKind;
+
+    let properties = CurrencyProperties {
+        chain_name: "polkadot".into(),
+        kind: TokenKind::Native,
+        decimals: 10,
+        rpc_url: String::new(),
+        asset_id: None,
+        ss58: 0,
+        existential_deposit: Balance(10_000_000_000),
+    };
+    let amount = |decimal: &str| order_amount(&AmountPayload::Decimal(decimal.into()), &properties);
+    let split = |percent: &str| {
+        validate_splits(
+            vec![SplitPayload {
+                recipient: AccountId32([1; 32]).to_base58_string(0),
+                percent: Some(percent.into()),
+                amount: None,
+            }],
+            Balance(50_000_000_000),
+            &properties,
+        )
+    };
+
+    assert_eq!(amount("1").unwrap(), Balance(10_000_000_000));
+    assert_eq!(amount("12.5").unwrap(), Balance(125_000_000_000));
+    assert!(matches!(
+        amount("0.99"),
+        Err(OrderError::LessThanExistentialDeposit(deposit)) if deposit == "1"
+    ));
+    assert!(matches!(
+        amount("0"),
+        Err(OrderError::LessThanExistentialDeposit(_))
+    ));
+    assert!(matches!(
+        amount("-5"),
+        Err(OrderError::LessThanExistentialDeposit(_))
+    ));
+    assert!(matches!(
+        amount("five"),
+        Err(OrderError::InvalidParameter(_))
+    ));
+    // Older clients send JSON numbers, which may come in the exponent form.
+    for (number, units) in [
+        ("1e-7", 1000),
+        ("1.5", 15_000_000_000),
+        ("2E1", 200_000_000_000),
+    ] {
+        let AmountPayload::Number(parsed) = serde_json::from_str(number).unwrap() else {
+            panic!("{number} isn't a JSON number");
+        };
+
+        assert_eq!(
+            AmountPayload::Number(parsed).balance(properties.decimals),
+            Some(Balance(units))
+        );
+    }
+    assert!(matches!(
+        order_amount(&serde_json::from_str("-1e1").unwrap(), &properties),
+        Err(OrderError::LessThanExistentialDeposit(_))
+    ));
+    // Each split is a transfer of its own, so it must reach the existential deposit as well.
+    assert!(split("20").is_ok());
+    assert!(matches!(split("19.99"), Err(OrderError::InvalidSplits(_))));
 }
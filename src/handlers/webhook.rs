@@ -0,0 +1,76 @@
+use crate::{
+    definitions::api_v2::{InvalidParameter, WebhookId, WebhookListQuery, WEBHOOK_ID},
+    error::{DbError, Error},
+    state::State,
+};
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State as ExtractState},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+pub async fn list_webhooks(
+    ExtractState(state): ExtractState<State>,
+    query_result: Result<Query<WebhookListQuery>, QueryRejection>,
+) -> Response {
+    let query = match query_result {
+        Ok(Query(query)) => query,
+        Err(rejection) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json([InvalidParameter {
+                    parameter: "query".into(),
+                    message: rejection.body_text(),
+                }]),
+            )
+                .into_response()
+        }
+    };
+
+    match state.list_webhooks(query).await {
+        Ok(webhooks) => (
+            [(axum::http::header::CACHE_CONTROL, "no-store")],
+            Json(webhooks),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list webhooks: {e:?}");
+
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn redeliver_webhook(
+    ExtractState(state): ExtractState<State>,
+    Path(webhook_id): Path<String>,
+) -> Response {
+    let Ok(id) = webhook_id.parse::<WebhookId>() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json([InvalidParameter {
+                parameter: WEBHOOK_ID.into(),
+                message: "parameter's format is invalid".into(),
+            }]),
+        )
+            .into_response();
+    };
+
+    match state.redeliver_webhook(id).await {
+        Ok(webhook) => (StatusCode::ACCEPTED, Json(webhook)).into_response(),
+        Err(Error::Db(DbError::WebhookNotFound(_))) => {
+            (StatusCode::NOT_FOUND, "Webhook not found").into_response()
+        }
+        Err(Error::Db(DbError::WebhookNotFailed(_))) => (
+            StatusCode::CONFLICT,
+            "Only failed webhooks can be redelivered",
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to redeliver webhook {id}: {e:?}");
+
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
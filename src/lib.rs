@@ -12,3 +12,4 @@ mod server;
 mod signer;
 mod state;
 mod utils;
+mod webhook;
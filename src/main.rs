@@ -20,14 +20,18 @@ mod server;
 mod signer;
 mod state;
 mod utils;
+mod webhook;
 
-use crate::error::ChainError;
-use arguments::{CliArgs, Config, SeedEnvVars, DATABASE_DEFAULT};
+use arguments::{CliArgs, Config, SeedEnvVars, DATABASE_DEFAULT, SQLITE_DATABASE_DEFAULT};
 use chain::ChainManager;
 use database::ConfigWoChains;
+use definitions::{ApiKey, DatabaseBackend, Merchant, Scope, WebhookConfig, WebhookMode};
 use error::{Error, PrettyCause};
+use server::auth::Auth;
 use signer::Signer;
-use state::State;
+use state::{MerchantProfile, Merchants, State};
+use std::collections::HashMap;
+use webhook::Webhooks;
 
 fn main() -> ExitCode {
     let shutdown_notification = ShutdownNotification::new();
@@ -84,65 +88,260 @@ fn try_main(shutdown_notification: ShutdownNotification) -> Result<(), Error> {
 
     tracing::info!("Kalatori {} is starting...", env!("CARGO_PKG_VERSION"));
 
-    let seed_env_vars = SeedEnvVars::parse()?;
     let config = Config::parse(cli_args.config)?;
 
+    if cli_args.migrate_dry_run {
+        return migration_dry_run(config);
+    }
+
+    if let Some(sqlite_path) = cli_args.migrate_to_sqlite {
+        return migrate_to_sqlite(config, &sqlite_path);
+    }
+
+    let seed_env_vars = SeedEnvVars::parse()?;
+
     Runtime::new()
         .map_err(Error::Runtime)?
         .block_on(async_try_main(
             shutdown_notification,
-            cli_args.recipient,
+            // Required by the argument parser unless it's a dry run.
+            cli_args.recipient.unwrap_or_default(),
             cli_args.remark,
             config,
             seed_env_vars,
         ))
 }
 
+/// Reports what migrations would do to the database without applying them.
+fn migration_dry_run(config: Config) -> Result<(), Error> {
+    // The in-memory database has nothing to migrate, and SQLite has no migrations to rehearse.
+    if config.in_memory_db || config.database_backend == DatabaseBackend::Sqlite {
+        return Err(Error::NotSledDatabase("the migration dry run"));
+    }
+
+    let reports =
+        database::migration_dry_run(config.database.unwrap_or_else(|| DATABASE_DEFAULT.into()))?;
+
+    if reports.is_empty() {
+        tracing::info!("The database is up to date.");
+    }
+
+    for report in reports {
+        tracing::info!(
+            "Migration to version {} ({}) would rewrite {} record(s).",
+            report.version,
+            report.description,
+            report.rewritten_records
+        );
+    }
+
+    tracing::info!("All records are readable after the migration; nothing has been changed.");
+
+    Ok(())
+}
+
+/// Copies the sled database to a new SQLite one, after which the config can be switched to it.
+fn migrate_to_sqlite(config: Config, sqlite_path: &str) -> Result<(), Error> {
+    if config.in_memory_db || config.database_backend == DatabaseBackend::Sqlite {
+        return Err(Error::NotSledDatabase("the migration to SQLite"));
+    }
+
+    let report = database::migrate_to_sqlite(
+        config.database.unwrap_or_else(|| DATABASE_DEFAULT.into()),
+        sqlite_path.into(),
+    )?;
+
+    tracing::info!(
+        "Copied {} order(s), {} transaction(s), and {} webhook(s) to {sqlite_path:?}. Set \
+        `database-backend = \"sqlite\"` and `database = {sqlite_path:?}` in the config to use it.",
+        report.orders,
+        report.transactions,
+        report.webhooks
+    );
+
+    Ok(())
+}
+
+/// Parses merchant profiles from the config, and adds their API keys and webhook secrets to the
+/// other ones.
+fn merchants(
+    recipient_string: &str,
+    remark: Option<String>,
+    merchant_configs: Vec<Merchant>,
+    api_keys: &mut Vec<ApiKey>,
+    webhook: &mut WebhookConfig,
+) -> Result<Merchants, Error> {
+    let mut profiles = HashMap::with_capacity(merchant_configs.len());
+
+    for Merchant {
+        id,
+        recipient: merchant_recipient,
+        remark: merchant_remark,
+        callback_secrets,
+        currencies,
+        api_key,
+    } in merchant_configs
+    {
+        let address = AccountId32::from_base58_string(&merchant_recipient)
+            .map_err(|e| Error::RecipientAccount(format!("{e} (merchant {id:?})")))?
+            .0;
+
+        if let Some(secret) = api_key {
+            api_keys.push(ApiKey {
+                name: id.clone(),
+                secret,
+                scopes: vec![Scope::Read, Scope::Create],
+                signed_only: false,
+                merchant: Some(id.clone()),
+            });
+        }
+
+        if let Some(secrets) = callback_secrets {
+            webhook.merchant_secrets.insert(id.clone(), secrets);
+        }
+
+        if profiles
+            .insert(
+                id.clone(),
+                MerchantProfile {
+                    recipient: address,
+                    remark: merchant_remark,
+                    currencies,
+                },
+            )
+            .is_some()
+        {
+            return Err(Error::DuplicateMerchant(id));
+        }
+    }
+
+    check_webhook_secrets(webhook)?;
+
+    Ok(Merchants {
+        default: MerchantProfile {
+            recipient: AccountId32::from_base58_string(recipient_string)
+                .map_err(|e| Error::RecipientAccount(e.to_string()))?
+                .0,
+            remark,
+            currencies: None,
+        },
+        profiles,
+    })
+}
+
+/// Checks that webhooks in the `signed` mode always have secrets to be signed with, as unsigned
+/// ones can't be told apart from forged ones.
+fn check_webhook_secrets(webhook: &WebhookConfig) -> Result<(), Error> {
+    let usable =
+        |secrets: &[String]| !secrets.is_empty() && secrets.iter().all(|secret| !secret.is_empty());
+    let signed = webhook.mode == WebhookMode::Signed
+        || webhook
+            .merchant
+            .iter()
+            .any(|host| host.mode == Some(WebhookMode::Signed));
+
+    if webhook.mode == WebhookMode::Signed && !usable(&webhook.secrets) {
+        return Err(Error::WebhookSecrets("the config".into()));
+    }
+
+    for host in &webhook.merchant {
+        if host.mode.unwrap_or(webhook.mode) == WebhookMode::Signed
+            && !host
+                .secrets
+                .as_deref()
+                .map_or(usable(&webhook.secrets), usable)
+        {
+            return Err(Error::WebhookSecrets(format!("host {:?}", host.host)));
+        }
+    }
+
+    // Merchant secrets are used in any mode that signs webhooks.
+    if signed {
+        if let Some((id, _)) = webhook
+            .merchant_secrets
+            .iter()
+            .find(|(_, secrets)| !usable(secrets))
+        {
+            return Err(Error::WebhookSecrets(format!("merchant {id:?}")));
+        }
+    }
+
+    Ok(())
+}
+
 async fn async_try_main(
     shutdown_notification: ShutdownNotification,
     recipient_string: String,
     remark: Option<String>,
-    config: Config,
+    mut config: Config,
     seed_env_vars: SeedEnvVars,
 ) -> Result<(), Error> {
     let database_path = if config.in_memory_db {
         if config.database.is_some() {
             tracing::warn!(
-                "`database` is set in the config but ignored because `in_memory_db` is \"true\""
+                "`database` is set in the config but ignored because `in-memory-db` is \"true\""
             );
         }
 
         None
     } else {
-        Some(config.database.unwrap_or_else(|| DATABASE_DEFAULT.into()))
+        Some(config.database.unwrap_or_else(|| {
+            match config.database_backend {
+                DatabaseBackend::Sled => DATABASE_DEFAULT,
+                DatabaseBackend::Sqlite => SQLITE_DATABASE_DEFAULT,
+            }
+            .into()
+        }))
     };
 
     // Start services
 
     let (task_tracker, error_rx) = TaskTracker::new();
 
-    let recipient = AccountId32::from_base58_string(&recipient_string)
-        .map_err(|e| Error::RecipientAccount(e.to_string()))?
-        .0;
+    let merchants = merchants(
+        &recipient_string,
+        remark,
+        config.merchant,
+        &mut config.api_key,
+        &mut config.webhook,
+    )?;
+    let signer = Signer::init(task_tracker.clone(), seed_env_vars.seed)?;
 
-    let signer = Signer::init(recipient, task_tracker.clone(), seed_env_vars.seed)?;
-
-    let db =
-        database::Database::init(database_path, task_tracker.clone(), config.account_lifetime)?;
+    let db = database::Database::init(
+        config.database_backend,
+        database_path,
+        task_tracker.clone(),
+        config.account_lifetime,
+    )?;
 
     let instance_id = db.initialize_server_info().await?;
 
+    if config.webhook.mode == WebhookMode::Legacy {
+        tracing::warn!(
+            "Webhooks are in the `legacy` mode, callbacks to merchants are sent unsigned!"
+        );
+    }
+
+    let webhooks = Webhooks::init(
+        db.clone(),
+        config.webhook,
+        task_tracker.clone(),
+        shutdown_notification.token.clone(),
+    );
+
     let (cm_tx, cm_rx) = oneshot::channel();
 
     let state = State::initialise(
         signer.interface(),
         ConfigWoChains {
-            recipient,
+            merchants,
             debug: config.debug,
-            remark,
+            underpaid_policy: config.underpaid_policy,
+            modification_policy: config.order_modification,
             //depth: config.depth,
         },
         db,
+        webhooks,
         cm_rx,
         instance_id,
         task_tracker.clone(),
@@ -159,10 +358,17 @@ async fn async_try_main(
         )?)
         .map_err(|_| Error::Fatal)?;
 
+    if config.api_key.is_empty() {
+        tracing::warn!(
+            "No API keys are set in the config, the daemon API is left without authentication!"
+        );
+    }
+
     let server = server::new(
         shutdown_notification.token.clone(),
         config.host,
         state.interface(),
+        Auth::new(config.api_key, config.signature_lifetime)?,
     )
     .await?;
 
@@ -1,39 +1,68 @@
 use crate::{
+    definitions::Scope,
     error::{Error, ServerError},
     handlers::{
         health::{audit, health, status},
-        order::{force_withdrawal, investigate, order},
+        order::{
+            force_withdrawal, investigate, list_orders, order, order_history,
+            public_payment_account, refund, release,
+        },
+        webhook::{list_webhooks, redeliver_webhook},
     },
     state::State,
 };
-use axum::{
-    extract::{self, rejection::RawPathParamsRejection, MatchedPath, Query, RawPathParams},
-    response::Response,
-    routing, Router,
-};
-use axum_macros::debug_handler;
-use std::{borrow::Cow, collections::HashMap, future::Future, net::SocketAddr};
+use auth::{authenticate, Auth};
+use axum::{middleware, routing, Router};
+use std::{borrow::Cow, future::Future, net::SocketAddr, sync::Arc};
 
 use tokio::net::TcpListener;
 use tokio_util::sync::CancellationToken;
 
+pub mod auth;
+
 pub const MODULE: &str = module_path!();
 
 pub async fn new(
     shutdown_notification: CancellationToken,
     host: SocketAddr,
     state: State,
+    auth: Auth,
 ) -> Result<impl Future<Output = Result<Cow<'static, str>, Error>>, ServerError> {
-    let v2: Router<State> = Router::new()
+    let shared_auth = Arc::new(auth);
+    let read: Router<State> = Router::new()
+        .route("/orders", routing::get(list_orders))
+        .route("/order/:order_id/history", routing::get(order_history))
+        .route("/status", routing::get(status))
+        .route("/health", routing::get(health))
+        .route_layer(middleware::from_fn_with_state(
+            shared_auth.guard(Scope::Read),
+            authenticate,
+        ));
+    let create: Router<State> = Router::new()
         .route("/order/:order_id", routing::post(order))
+        .route_layer(middleware::from_fn_with_state(
+            shared_auth.guard(Scope::Create),
+            authenticate,
+        ));
+    let admin: Router<State> = Router::new()
         .route(
             "/order/:order_id/forceWithdrawal",
             routing::post(force_withdrawal),
         )
-        .route("/status", routing::get(status))
-        .route("/health", routing::get(health))
         .route("/audit", routing::get(audit))
-        .route("/order/:order_id/investigate", routing::post(investigate));
+        .route("/order/:order_id/investigate", routing::post(investigate))
+        .route("/order/:order_id/refund", routing::post(refund))
+        .route("/order/:order_id/release", routing::post(release))
+        .route("/webhooks", routing::get(list_webhooks))
+        .route(
+            "/webhooks/:webhook_id/redeliver",
+            routing::post(redeliver_webhook),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            shared_auth.guard(Scope::Admin),
+            authenticate,
+        ));
+    let v2 = read.merge(create).merge(admin);
     let app = Router::new()
         .route(
             "/public/v2/payment/:paymentAccount",
@@ -57,14 +86,3 @@ pub async fn new(
         Ok("The server module is shut down.".into())
     })
 }
-
-// TODO: Clarify what this is doing
-#[debug_handler]
-async fn public_payment_account(
-    extract::State(state): extract::State<State>,
-    matched_path: MatchedPath,
-    path_result: Result<RawPathParams, RawPathParamsRejection>,
-    query: Query<HashMap<String, String>>,
-) -> Response {
-    todo!()
-}
@@ -0,0 +1,334 @@
+//! Authentication of API requests
+//!
+//! A request is authenticated either with a static bearer key (`Authorization: Bearer <secret>`),
+//! or with an HMAC-SHA256 signature made with the key secret:
+//!
+//! ```text
+//! X-Kalatori-Key: <key name>
+//! X-Kalatori-Timestamp: <milliseconds since the Unix epoch>
+//! X-Kalatori-Nonce: <unique string>
+//! X-Kalatori-Signature: hex(HMAC-SHA256(secret, "<timestamp>\n<nonce>\n<METHOD>\n<path?query>\n<body>"))
+//! ```
+//!
+//! Signed requests are accepted only within the signature lifetime, and each nonce is accepted only
+//! once. If no API keys are set, all requests are let through.
+//!
+//! Requests made with a key bound to a merchant profile carry [`KeyMerchant`] for handlers to keep
+//! them within the orders of that merchant.
+
+use crate::{
+    definitions::{api_v2::Timestamp, ApiKey, Scope},
+    error::{AuthError, ServerError},
+};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+pub const KEY_HEADER: &str = "x-kalatori-key";
+pub const TIMESTAMP_HEADER: &str = "x-kalatori-timestamp";
+pub const NONCE_HEADER: &str = "x-kalatori-nonce";
+pub const SIGNATURE_HEADER: &str = "x-kalatori-signature";
+
+/// 5 minutes.
+const DEFAULT_SIGNATURE_LIFETIME: Timestamp = Timestamp(300_000);
+/// Requests are buffered to be verified, so their size must be limited.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+pub struct Auth {
+    keys: HashMap<String, ApiKey>,
+    signature_lifetime: Timestamp,
+    /// Nonces of accepted signed requests along with their timestamps
+    nonces: Mutex<HashMap<String, u64>>,
+}
+
+/// Merchant profile that the API key of the request is bound to
+#[derive(Clone, Debug)]
+pub struct KeyMerchant(pub String);
+
+/// Authentication state of a group of routes that require the same scope
+#[derive(Clone)]
+pub struct Guard {
+    auth: Arc<Auth>,
+    scope: Scope,
+}
+
+impl Auth {
+    pub fn new(
+        api_keys: Vec<ApiKey>,
+        signature_lifetime: Option<Timestamp>,
+    ) -> Result<Self, ServerError> {
+        let mut keys = HashMap::with_capacity(api_keys.len());
+
+        for key in api_keys {
+            if let Some(duplicate) = keys.insert(key.name.clone(), key) {
+                return Err(ServerError::DuplicateApiKey(duplicate.name));
+            }
+        }
+
+        Ok(Self {
+            keys,
+            signature_lifetime: signature_lifetime.unwrap_or(DEFAULT_SIGNATURE_LIFETIME),
+            nonces: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn guard(self: &Arc<Self>, scope: Scope) -> Guard {
+        Guard {
+            auth: self.clone(),
+            scope,
+        }
+    }
+
+    fn verify(&self, parts: &Parts, body: &[u8], now: u64) -> Result<&ApiKey, AuthError> {
+        if let Some(name) = header(parts, KEY_HEADER)? {
+            return self.verify_signature(name, parts, body, now);
+        }
+
+        let authorization =
+            header(parts, AUTHORIZATION.as_str())?.ok_or(AuthError::MissingCredentials)?;
+        let secret = authorization
+            .strip_prefix("Bearer ")
+            .ok_or(AuthError::InvalidHeader(AUTHORIZATION.as_str()))?;
+        let key = self
+            .keys
+            .values()
+            .find(|key| constant_time_eq(key.secret.as_bytes(), secret.as_bytes()))
+            .ok_or(AuthError::UnknownKey)?;
+
+        if key.signed_only {
+            return Err(AuthError::SignatureRequired);
+        }
+
+        Ok(key)
+    }
+
+    fn verify_signature(
+        &self,
+        name: &str,
+        parts: &Parts,
+        body: &[u8],
+        now: u64,
+    ) -> Result<&ApiKey, AuthError> {
+        let key = self.keys.get(name).ok_or(AuthError::UnknownKey)?;
+        let timestamp: u64 = required_header(parts, TIMESTAMP_HEADER)?
+            .parse()
+            .map_err(|_| AuthError::InvalidHeader(TIMESTAMP_HEADER))?;
+        let nonce = required_header(parts, NONCE_HEADER)?;
+        let signature = const_hex::decode(required_header(parts, SIGNATURE_HEADER)?)
+            .map_err(|_| AuthError::InvalidHeader(SIGNATURE_HEADER))?;
+
+        if now.abs_diff(timestamp) > self.signature_lifetime.0 {
+            return Err(AuthError::StaleTimestamp);
+        }
+
+        let path_and_query = parts.uri.path_and_query().map_or_else(
+            || parts.uri.path(),
+            |path_and_query| path_and_query.as_str(),
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.secret.as_bytes())
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        mac.update(
+            format!("{timestamp}\n{nonce}\n{}\n{path_and_query}\n", parts.method).as_bytes(),
+        );
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        // Nonces are remembered only after the signature check, so strangers can't burn them. Old
+        // ones are forgotten as their requests would be rejected by the timestamp check anyway.
+        let mut nonces = self.nonces.lock().unwrap_or_else(PoisonError::into_inner);
+
+        nonces.retain(|_, seen| now.abs_diff(*seen) <= self.signature_lifetime.0);
+
+        if nonces
+            .insert(format!("{name}\n{nonce}"), timestamp)
+            .is_some()
+        {
+            return Err(AuthError::ReplayedNonce);
+        }
+
+        Ok(key)
+    }
+}
+
+pub async fn authenticate(State(guard): State<Guard>, request: Request, next: Next) -> Response {
+    if guard.auth.keys.is_empty() {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_BODY_SIZE).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "request body is too large").into_response();
+    };
+    // Order lookups share the route with order creation and differ only by the empty body.
+    let scope = if guard.scope == Scope::Create && bytes.is_empty() {
+        Scope::Read
+    } else {
+        guard.scope
+    };
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| {
+            duration.as_millis().try_into().unwrap_or(u64::MAX)
+        });
+
+    match guard.auth.verify(&parts, &bytes, now) {
+        Ok(key) if allows(key, scope) => {
+            if let Some(merchant) = &key.merchant {
+                parts.extensions.insert(KeyMerchant(merchant.clone()));
+            }
+
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        Ok(key) => {
+            tracing::warn!(
+                "API key {:?} was used to access {} without the {scope:?} scope",
+                key.name,
+                parts.uri.path()
+            );
+
+            (
+                StatusCode::FORBIDDEN,
+                "API key doesn't have the required scope",
+            )
+                .into_response()
+        }
+        Err(e) => (StatusCode::UNAUTHORIZED, e.to_string()).into_response(),
+    }
+}
+
+fn allows(key: &ApiKey, scope: Scope) -> bool {
+    key.scopes.contains(&scope) || key.scopes.contains(&Scope::Admin)
+}
+
+fn header<'a>(parts: &'a Parts, name: &'static str) -> Result<Option<&'a str>, AuthError> {
+    parts
+        .headers
+        .get(name)
+        .map(|value| value.to_str().map_err(|_| AuthError::InvalidHeader(name)))
+        .transpose()
+}
+
+fn required_header<'a>(parts: &'a Parts, name: &'static str) -> Result<&'a str, AuthError> {
+    header(parts, name)?.ok_or(AuthError::MissingHeader(name))
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0, |difference, (l, r)| difference | (l ^ r))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request as HttpRequest;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn auth() -> Auth {
+        Auth::new(
+            vec![
+                ApiKey {
+                    name: "shop".into(),
+                    secret: "shop secret".into(),
+                    scopes: vec![Scope::Read, Scope::Create],
+                    signed_only: false,
+                    merchant: None,
+                },
+                ApiKey {
+                    name: "back-office".into(),
+                    secret: "back-office secret".into(),
+                    scopes: vec![Scope::Admin],
+                    signed_only: true,
+                    merchant: None,
+                },
+            ],
+            None,
+        )
+        .unwrap()
+    }
+
+    fn signed(timestamp: u64, nonce: &str, body: &[u8]) -> Parts {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"back-office secret").unwrap();
+
+        mac.update(format!("{timestamp}\n{nonce}\nPOST\n/v2/audit?full=1\n").as_bytes());
+        mac.update(body);
+
+        HttpRequest::post("/v2/audit?full=1")
+            .header(KEY_HEADER, "back-office")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce)
+            .header(
+                SIGNATURE_HEADER,
+                const_hex::encode(mac.finalize().into_bytes()),
+            )
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    #[test]
+    fn bearer() {
+        let auth = auth();
+        let parts = HttpRequest::get("/v2/status")
+            .header(AUTHORIZATION, "Bearer shop secret")
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+        let key = auth.verify(&parts, &[], NOW).unwrap();
+
+        assert!(allows(key, Scope::Create));
+        assert!(!allows(key, Scope::Admin));
+
+        let signed_only_parts = HttpRequest::get("/v2/status")
+            .header(AUTHORIZATION, "Bearer back-office secret")
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+
+        assert!(matches!(
+            auth.verify(&signed_only_parts, &[], NOW),
+            Err(AuthError::SignatureRequired)
+        ));
+    }
+
+    #[test]
+    fn signature() {
+        let auth = auth();
+        let body = b"{}";
+
+        assert!(auth.verify(&signed(NOW, "1", body), body, NOW).is_ok());
+        assert!(matches!(
+            auth.verify(&signed(NOW, "1", body), body, NOW),
+            Err(AuthError::ReplayedNonce)
+        ));
+        assert!(matches!(
+            auth.verify(&signed(NOW, "2", body), b"{ }", NOW),
+            Err(AuthError::InvalidSignature)
+        ));
+        assert!(matches!(
+            auth.verify(&signed(NOW - 300_001, "3", body), body, NOW),
+            Err(AuthError::StaleTimestamp)
+        ));
+    }
+}
@@ -30,11 +30,7 @@ pub struct Signer {
 
 impl Signer {
     /// Run once to initialize; this should do **all** secret management
-    pub fn init(
-        recipient: AccountId32,
-        task_tracker: TaskTracker,
-        seed: String,
-    ) -> Result<Self, Error> {
+    pub fn init(task_tracker: TaskTracker, seed: String) -> Result<Self, Error> {
         let (tx, mut rx) = mpsc::channel(16);
         task_tracker.spawn("Signer", async move {
             let mut seed_entropy = entropy_from_phrase(&seed)?; // TODO: shutdown on failure
@@ -45,7 +41,7 @@ impl Signer {
                             match Pair::from_entropy_and_full_derivation(
                                 &seed_entropy,
                                 // api spec says use "2" for communication, let's use it here too
-                                derivations(&recipient.to_base58_string(2), &request.id),
+                                derivations(&request.recipient.to_base58_string(2), &request.id),
                             ) {
                                 Ok(a) => Ok(a.public().to_base58_string(request.ss58)),
                                 Err(e) => Err(e.into()),
@@ -57,7 +53,7 @@ impl Signer {
                             match Pair::from_entropy_and_full_derivation(
                                 &seed_entropy,
                                 // api spec says use "2" for communication, let's use it here too
-                                derivations(&recipient.to_base58_string(2), &request.id),
+                                derivations(&request.recipient.to_base58_string(2), &request.id),
                             ) {
                                 Ok(a) => Ok(a.sign(&request.signable)),
                                 Err(e) => Err(e.into()),
@@ -77,19 +73,41 @@ impl Signer {
         Ok(Self { tx })
     }
 
-    pub async fn public(&self, id: String, ss58: u16) -> Result<String, SignerError> {
+    /// Payment account of the order paid to the recipient. Each recipient derives its own
+    /// accounts, so orders of different merchants never share one.
+    pub async fn public(
+        &self,
+        id: String,
+        recipient: AccountId32,
+        ss58: u16,
+    ) -> Result<String, SignerError> {
         let (res, rx) = oneshot::channel();
         self.tx
-            .send(SignerRequest::PublicKey(PublicKeyRequest { id, ss58, res }))
+            .send(SignerRequest::PublicKey(PublicKeyRequest {
+                id,
+                recipient,
+                ss58,
+                res,
+            }))
             .await
             .map_err(|_| SignerError::SignerDown)?;
         rx.await.map_err(|_| SignerError::SignerDown)?
     }
 
-    pub async fn sign(&self, id: String, signable: Vec<u8>) -> Result<Signature, SignerError> {
+    pub async fn sign(
+        &self,
+        id: String,
+        recipient: AccountId32,
+        signable: Vec<u8>,
+    ) -> Result<Signature, SignerError> {
         let (res, rx) = oneshot::channel();
         self.tx
-            .send(SignerRequest::Sign(Sign { id, signable, res }))
+            .send(SignerRequest::Sign(Sign {
+                id,
+                recipient,
+                signable,
+                res,
+            }))
             .await
             .map_err(|_| SignerError::SignerDown)?;
         rx.await.map_err(|_| SignerError::SignerDown)?
@@ -124,6 +142,7 @@ enum SignerRequest {
 /// Information required to generate public invoice address, with callback
 struct PublicKeyRequest {
     id: String,
+    recipient: AccountId32,
     ss58: u16,
     res: oneshot::Sender<Result<String, SignerError>>,
 }
@@ -131,6 +150,7 @@ struct PublicKeyRequest {
 /// Bytes to sign, with callback
 struct Sign {
     id: String,
+    recipient: AccountId32,
     signable: Vec<u8>,
     res: oneshot::Sender<Result<Signature, SignerError>>,
 }
@@ -1,16 +1,28 @@
-use crate::error::ForceWithdrawalError;
+use crate::error::{ForceWithdrawalError, RefundError, ReleaseError};
 use crate::{
-    chain::ChainManager,
-    database::{ConfigWoChains, Database, TransactionInfoDb},
-    definitions::api_v2::{
-        CurrencyProperties, Health, OrderCreateResponse, OrderInfo, OrderQuery, OrderResponse,
-        OrderStatus, RpcInfo, ServerHealth, ServerInfo, ServerStatus,
+    chain::{
+        definitions::{payers, Investigation},
+        payout::LOSS_TOLERANCE,
+        ChainManager,
     },
-    error::{Error, OrderError},
+    database::{Account, ConfigWoChains, Database, TransactionInfoDb},
+    definitions::{
+        api_v2::{
+            AuditReport, BlockNumber, ChangeOrigin, CurrencyProperties, CurrencyTotals, Decimals,
+            Discrepancy, DiscrepancyKind, Health, InvestigationInfo, InvestigationResponse,
+            OrderCreateResponse, OrderEvent, OrderHistory, OrderInfo, OrderList, OrderListQuery,
+            OrderQuery, OrderResponse, OrderStatus, PaymentStatus, PublicOrderInfo, RpcInfo,
+            ServerHealth, ServerInfo, ServerStatus, Timestamp, TransactionInfo, TxKind, WebhookId,
+            WebhookInfo, WebhookListQuery, WebhookPayload, WithdrawalStatus, AMOUNT,
+        },
+        Balance, ModificationPolicy, UnderpaidPolicy,
+    },
+    error::{DbError, Error, OrderError},
     signer::Signer,
     utils::task_tracker::TaskTracker,
+    webhook::Webhooks,
 };
-use std::collections::HashMap;
+use std::{collections::HashMap, time::SystemTime};
 use substrate_crypto_light::common::{AccountId32, AsBase58};
 use tokio::sync::oneshot;
 use tokio_util::sync::CancellationToken;
@@ -23,15 +35,17 @@ pub struct State {
 }
 
 impl State {
-    #[expect(clippy::too_many_lines)]
+    #[expect(clippy::too_many_lines, clippy::too_many_arguments)]
     pub fn initialise(
         signer: Signer,
         ConfigWoChains {
-            recipient,
+            merchants,
             debug,
-            remark,
+            underpaid_policy,
+            modification_policy,
         }: ConfigWoChains,
         db: Database,
+        webhooks: Webhooks,
         chain_manager: oneshot::Receiver<ChainManager>,
         instance_id: String,
         task_tracker: TaskTracker,
@@ -55,7 +69,7 @@ impl State {
             version: env!("CARGO_PKG_VERSION").to_string(),
             instance_id: instance_id.clone(),
             debug: debug.unwrap_or_default(),
-            kalatori_remark: remark.clone(),
+            kalatori_remark: merchants.default.remark.clone(),
         };
 
         // Remember to always spawn async here or things might deadlock
@@ -66,20 +80,32 @@ impl State {
             let currencies = HashMap::new();
             let mut state = StateData {
                 currencies,
-                recipient,
+                merchants,
                 server_info,
                 db,
+                webhooks,
                 chain_manager,
                 signer,
+                underpaid_policy,
+                modification_policy,
             };
 
             // TODO: consider doing this even more lazy
             let order_list = db_wakeup.order_list().await?;
+            let merchants_wakeup = state.merchants.clone();
             task_tracker.spawn("Restore saved orders", async move {
                 for (order, order_details) in order_list {
-                    chain_manager_wakeup
-                        .add_invoice(order, order_details, state.recipient)
-                        .await;
+                    let restored = match merchants_wakeup.recipient(&order_details) {
+                        Ok(recipient) => chain_manager_wakeup
+                            .add_invoice(order.clone(), order_details, recipient)
+                            .await
+                            .map_err(Error::from),
+                        Err(e) => Err(e.into()),
+                    };
+
+                    if let Err(e) = restored {
+                        tracing::error!("Failed to restore order {order}: {e}");
+                    }
                 }
                 Ok("All saved orders restored")
             });
@@ -104,15 +130,42 @@ impl State {
                                     .send(state.get_invoice_status(request.order).await)
                                     .map_err(|_| Error::Fatal)?;
                             }
+                            StateAccessRequest::GetPaymentAccountStatus(GetPaymentAccountStatus { account, res }) => {
+                                res.send(state.db.read_payment_account(account).await.map_err(Into::into))
+                                    .map_err(|_| Error::Fatal)?;
+                            }
+                            StateAccessRequest::ListOrders(ListOrders { query, res }) => {
+                                res.send(state.db.list_orders(query).await.map_err(Into::into))
+                                    .map_err(|_| Error::Fatal)?;
+                            }
+                            StateAccessRequest::OrderHistory(ReadOrderHistory { order, res }) => {
+                                let result = state.db.order_history(order.clone()).await;
+
+                                res.send(result.map(|found| found.map(|history| OrderHistory { order, history })).map_err(Into::into))
+                                    .map_err(|_| Error::Fatal)?;
+                            }
+                            StateAccessRequest::ListWebhooks(ListWebhooks { query, res }) => {
+                                res.send(state.db.list_webhooks(query).await.map_err(Into::into))
+                                    .map_err(|_| Error::Fatal)?;
+                            }
+                            StateAccessRequest::RedeliverWebhook(RedeliverWebhook { id, res }) => {
+                                let result = state.db.redeliver_webhook(id).await;
+
+                                if result.is_ok() {
+                                    state.webhooks.wake_up();
+                                }
+
+                                res.send(result.map_err(Into::into)).map_err(|_| Error::Fatal)?;
+                            }
                             StateAccessRequest::CreateInvoice(request) => {
                                 request
                                     .res
                                     .send(state.create_invoice(request.order_query).await)
                                     .map_err(|_| Error::Fatal)?;
                             }
-                            StateAccessRequest::IsCurrencySupported { currency, res } => {
-                                let supported = state.currencies.contains_key(&currency);
-                                res.send(supported).map_err(|_| Error::Fatal)?;
+                            StateAccessRequest::GetCurrency { currency, res } => {
+                                let properties = state.currencies.get(&currency).cloned();
+                                res.send(properties).map_err(|_| Error::Fatal)?;
                             }
                             StateAccessRequest::ServerStatus(res) => {
                                 let server_status = ServerStatus {
@@ -130,22 +183,44 @@ impl State {
                                 };
                                 res.send(server_health).map_err(|_| Error::Fatal)?;
                             }
-                            StateAccessRequest::OrderPaid(id) => {
+                            StateAccessRequest::Audit(res) => {
+                                // Audit walks the whole database and the chain, so it must not
+                                // block the state handler.
+                                let audit = audit(
+                                    state.db.clone(),
+                                    state.chain_manager.clone(),
+                                    state.merchants.clone(),
+                                    state.server_info.clone(),
+                                );
+
+                                tokio::spawn(async move {
+                                    drop(res.send(audit.await));
+                                });
+                            }
+                            StateAccessRequest::Investigate(investigation) => {
+                                // Rescan may take a while, so it must not block the state handler
+                                // either.
+                                match state.merchants.recipient(&investigation.order_info) {
+                                    Ok(recipient) => {
+                                        tokio::spawn(investigate(
+                                            state.chain_manager.clone(),
+                                            recipient,
+                                            investigation,
+                                        ));
+                                    }
+                                    Err(e) => drop(investigation.res.send(Err(e.into()))),
+                                }
+                            }
+                            StateAccessRequest::OrderPaid(id, origin) => {
                                 // Only perform actions if the record is saved in ledger
-                                match state.db.mark_paid(id.clone()).await {
+                                match state.db.mark_paid(id.clone(), origin).await {
                                     Ok(order) => {
-                                        if !order.callback.is_empty() {
-                                            let callback = order.callback.clone();
-                                            tokio::spawn(async move {
-                                                tracing::info!("Sending callback to: {}", callback);
-
-                                                // fire and forget
-                                                if let Err(e) = reqwest::Client::new().get(&callback).send().await {
-                                                    tracing::error!("Failed to send callback to {}: {:?}", callback, e);
-                                                }
-                                            });
+                                        state.emit(id.clone(), OrderEvent::Paid).await;
+
+                                        match state.merchants.recipient(&order) {
+                                            Ok(recipient) => drop(state.chain_manager.reap(id, order, recipient).await),
+                                            Err(e) => tracing::error!("Failed to pay out order {id}: {e}"),
                                         }
-                                        drop(state.chain_manager.reap(id, order, state.recipient).await);
                                     }
                                     Err(e) => {
                                         tracing::error!(
@@ -155,51 +230,117 @@ impl State {
                                 }
                             }
                             StateAccessRequest::RecordTransaction { order, tx: new_tx } => {
-                                if let Err(e) = state.db.record_transaction(order, new_tx).await {
-                                    tracing::error!(
-                                        "Found a transaction related to an order, but this could not be recorded! {e:?}"
-                                    )
+                                let finalized_withdrawal = new_tx.inner.finalized_tx.is_some() && new_tx.inner.kind == TxKind::Withdrawal;
+
+                                match state.db.record_transaction(order.clone(), new_tx).await {
+                                    Ok(()) => {
+                                        if finalized_withdrawal {
+                                            state.emit(order, OrderEvent::PayoutFinalized).await;
+                                        }
+                                    }
+                                    Err(e) => {
+                                        tracing::error!(
+                                            "Found a transaction related to an order, but this could not be recorded! {e:?}"
+                                        )
+                                    }
                                 }
                             }
-                            StateAccessRequest::OrderWithdrawn(id) => {
-                                match state.db.mark_withdrawn(id.clone()).await {
-                                    Ok(order) => {
-                                        tracing::info!("Order {id} successfully marked as withdrawn");
+                            StateAccessRequest::PaymentReceived { order, received, block_number } => {
+                                match state.db.record_received(order.clone(), received, ChangeOrigin::tracker(Some(block_number))).await {
+                                    Ok(true) => {
+                                        state.emit(order, OrderEvent::PartiallyPaid).await;
                                     }
+                                    Ok(false) => {}
                                     Err(e) => {
                                         tracing::error!(
-                                            "Order was withdrawn but this could not be recorded! {e:?}"
+                                            "Received funds for order {order}, but this could not be recorded! {e:?}"
                                         )
                                     }
                                 }
                             }
-                            StateAccessRequest::ForceWithdrawal(id) => {
-                                match state.db.read_order(id.clone()).await {
-                                    Ok(Some(order_info)) => {
-                                        match state.chain_manager.reap(id.clone(), order_info.clone(), state.recipient).await {
-                                            Ok(_) => {
-                                                match state.db.mark_forced(id.clone()).await {
-                                                    Ok(_) => {
-                                                        tracing::info!("Order {id} successfully marked as force withdrawn");
-                                                    }
-                                                    Err(e) => {
-                                                        tracing::error!("Failed to mark order {id} as forced: {e:?}");
-                                                    }
-                                                }
-                                            }
-                                            Err(e) => {
-                                                tracing::error!("Failed to initiate forced payout for order {id}: {e:?}");
-                                            }
-                                        }
+                            StateAccessRequest::OrderExpired(id, block_number, res) => {
+                                let _unused = res.send(state.expire_order(id, ChangeOrigin::tracker(Some(block_number))).await);
+                            }
+                            StateAccessRequest::Refund(RefundOrder { order, amount, res }) => {
+                                // Refund waits for the chain, so it must not block the state
+                                // handler.
+                                let refund = refund(
+                                    state.db.clone(),
+                                    state.chain_manager.clone(),
+                                    state.merchants.clone(),
+                                    state.currencies.clone(),
+                                    order,
+                                    amount,
+                                    ChangeOrigin::ADMIN,
+                                );
+
+                                tokio::spawn(async move {
+                                    drop(res.send(refund.await));
+                                });
+                            }
+                            StateAccessRequest::OrderRefunded(id) => {
+                                match state.db.mark_refunded(id.clone(), ChangeOrigin::tracker(None)).await {
+                                    Ok(()) => {
+                                        tracing::info!("Order {id} successfully marked as refunded");
+                                    }
+                                    Err(e) => {
+                                        tracing::error!(
+                                            "Order was refunded but this could not be recorded! {e:?}"
+                                        )
+                                    }
+                                }
+                            }
+                            StateAccessRequest::PayoutFailed(id) => {
+                                match state.db.mark_stuck(id.clone(), ChangeOrigin::tracker(None)).await {
+                                    Ok(()) => {
+                                        tracing::info!("Order {id} marked as failed to withdraw");
+                                        state.emit(id, OrderEvent::PayoutFailed).await;
+                                    }
+                                    Err(e) => {
+                                        tracing::error!(
+                                            "Payout has failed but this could not be recorded! {e:?}"
+                                        )
                                     }
-                                    Ok(None) => {
-                                        tracing::error!("Order {id} not found in database");
+                                }
+                            }
+                            StateAccessRequest::OrderWithdrawn(id) => {
+                                match state.db.mark_withdrawn(id.clone(), ChangeOrigin::tracker(None)).await {
+                                    Ok(_) => {
+                                        tracing::info!("Order {id} successfully marked as withdrawn");
+                                        state.emit(id, OrderEvent::PayoutSubmitted).await;
                                     }
                                     Err(e) => {
-                                        tracing::error!("Error reading order {id} from database: {e:?}");
+                                        tracing::error!(
+                                            "Order was withdrawn but this could not be recorded! {e:?}"
+                                        )
                                     }
                                 }
                             }
+                            StateAccessRequest::ForceWithdrawal(ForceWithdrawOrder { order, res }) => {
+                                let forced = force_withdrawal(
+                                    state.db.clone(),
+                                    state.chain_manager.clone(),
+                                    state.merchants.clone(),
+                                    order,
+                                    ChangeOrigin::ADMIN,
+                                );
+
+                                tokio::spawn(async move {
+                                    drop(res.send(forced.await));
+                                });
+                            }
+                            StateAccessRequest::Release(ReleaseOrder { order, res }) => {
+                                let released = release(
+                                    state.db.clone(),
+                                    state.chain_manager.clone(),
+                                    state.merchants.clone(),
+                                    order,
+                                );
+
+                                tokio::spawn(async move {
+                                    drop(res.send(released.await));
+                                });
+                            }
                             StateAccessRequest::IsOrderPaid(id, res) => {
                                 match state.db.is_marked_paid(id).await {
                                     Ok(paid) => {
@@ -270,6 +411,67 @@ impl State {
         rx.await.map_err(|_| Error::Fatal)?
     }
 
+    pub async fn payment_account_status(
+        &self,
+        account: Account,
+    ) -> Result<Option<PublicOrderInfo>, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::GetPaymentAccountStatus(
+                GetPaymentAccountStatus { account, res },
+            ))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)?
+    }
+
+    pub async fn list_orders(&self, query: OrderListQuery) -> Result<OrderList, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::ListOrders(ListOrders { query, res }))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)?
+    }
+
+    /// Returns [`None`] if the order isn't found.
+    pub async fn order_history(&self, order: String) -> Result<Option<OrderHistory>, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::OrderHistory(ReadOrderHistory {
+                order,
+                res,
+            }))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)?
+    }
+
+    pub async fn list_webhooks(&self, query: WebhookListQuery) -> Result<Vec<WebhookInfo>, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::ListWebhooks(ListWebhooks {
+                query,
+                res,
+            }))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)?
+    }
+
+    /// Queue a failed webhook for delivery again.
+    pub async fn redeliver_webhook(&self, id: WebhookId) -> Result<WebhookInfo, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::RedeliverWebhook(RedeliverWebhook {
+                id,
+                res,
+            }))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)?
+    }
+
     pub async fn server_status(&self) -> Result<ServerStatus, Error> {
         let (res, rx) = oneshot::channel();
         self.tx
@@ -288,6 +490,93 @@ impl State {
         rx.await.map_err(|_| Error::Fatal)
     }
 
+    pub async fn audit(&self) -> Result<AuditReport, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::Audit(res))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)?
+    }
+
+    /// Rescan the chain for transfers of the order, record the missing ones, and re-evaluate its
+    /// payment status.
+    pub async fn investigate(
+        &self,
+        order: String,
+        from: Option<BlockNumber>,
+        to: Option<BlockNumber>,
+    ) -> Result<Option<InvestigationResponse>, Error> {
+        let OrderResponse::FoundOrder(OrderStatus { order_info, .. }) =
+            self.order_status(&order).await?
+        else {
+            return Ok(None);
+        };
+
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::Investigate(InvestigateOrder {
+                order: order.clone(),
+                order_info: order_info.clone(),
+                from,
+                to,
+                res,
+            }))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        let investigation = rx.await.map_err(|_| Error::Fatal)??;
+
+        let mut discovered_transactions = Vec::new();
+
+        for transaction in investigation.transactions {
+            let is_known = transaction
+                .inner
+                .finalized_tx
+                .as_ref()
+                .is_some_and(|found| {
+                    order_info.transactions.iter().any(|known| {
+                        known.finalized_tx.as_ref().is_some_and(|recorded| {
+                            recorded.block_number == found.block_number
+                                && recorded.position_in_block == found.position_in_block
+                        })
+                    })
+                });
+
+            if !is_known {
+                discovered_transactions.push(TransactionInfo::from(transaction.clone()));
+                self.record_transaction(transaction, order.clone()).await?;
+            }
+        }
+
+        let decimals = order_info.currency.decimals;
+        let marked_paid = matches!(
+            order_info.payment_status,
+            PaymentStatus::Pending | PaymentStatus::Underpaid
+        ) && investigation.balance >= order_info.amount;
+
+        if marked_paid {
+            self.order_paid(order.clone(), ChangeOrigin::ADMIN).await;
+        }
+
+        // State requests are handled in order, so the status below already reflects all the
+        // updates above.
+        let OrderResponse::FoundOrder(order_status) = self.order_status(&order).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(InvestigationResponse {
+            order_status,
+            investigation: InvestigationInfo {
+                from_block: investigation.from,
+                to_block: investigation.to,
+                balance: investigation.balance.format(decimals),
+                discovered_transactions,
+                unreadable_blocks: investigation.unreadable_blocks,
+                marked_paid,
+            },
+        }))
+    }
+
     pub async fn create_order(&self, order_query: OrderQuery) -> Result<OrderResponse, Error> {
         let (res, rx) = oneshot::channel();
         /*
@@ -308,10 +597,11 @@ impl State {
         rx.await.map_err(|_| Error::Fatal)?
     }
 
-    pub async fn is_currency_supported(&self, currency: &str) -> Result<bool, Error> {
+    /// Properties of a supported currency, or `None` if the currency isn't supported.
+    pub async fn currency(&self, currency: &str) -> Result<Option<CurrencyProperties>, Error> {
         let (res, rx) = oneshot::channel();
         self.tx
-            .send(StateAccessRequest::IsCurrencySupported {
+            .send(StateAccessRequest::GetCurrency {
                 currency: currency.to_string(),
                 res,
             })
@@ -329,10 +619,91 @@ impl State {
         rx.await.map_err(|_| Error::Fatal)
     }
 
-    pub async fn order_paid(&self, order: String) {
+    pub async fn order_paid(&self, order: String, origin: ChangeOrigin) {
+        if self
+            .tx
+            .send(StateAccessRequest::OrderPaid(order, origin))
+            .await
+            .is_err()
+        {
+            tracing::warn!("Data race on shutdown; please restart the daemon for cleaning up");
+        };
+    }
+
+    /// Report the payment account balance of an order awaiting payment at the given block.
+    pub async fn payment_received(
+        &self,
+        order: String,
+        received: Balance,
+        block_number: BlockNumber,
+    ) {
         if self
             .tx
-            .send(StateAccessRequest::OrderPaid(order))
+            .send(StateAccessRequest::PaymentReceived {
+                order,
+                received,
+                block_number,
+            })
+            .await
+            .is_err()
+        {
+            tracing::warn!("Data race on shutdown; please restart the daemon for cleaning up");
+        };
+    }
+
+    /// Report that the order has expired before it was paid in full. Returns whether the order
+    /// should still be watched.
+    pub async fn order_expired(
+        &self,
+        order: String,
+        block_number: BlockNumber,
+    ) -> Result<bool, Error> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::OrderExpired(order, block_number, res))
+            .await
+            .map_err(|_| Error::Fatal)?;
+        rx.await.map_err(|_| Error::Fatal)
+    }
+
+    /// Return the funds of the order to its payers, either all of them or the given amount.
+    pub async fn refund(
+        &self,
+        order: String,
+        amount: Option<Balance>,
+    ) -> Result<OrderResponse, RefundError> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::Refund(RefundOrder {
+                order: order.clone(),
+                amount,
+                res,
+            }))
+            .await
+            .map_err(|_| RefundError::InternalError)?;
+        rx.await.map_err(|_| RefundError::InternalError)??;
+
+        self.order_status(&order)
+            .await
+            .map_err(|_| RefundError::InternalError)
+    }
+
+    pub async fn order_refunded(&self, order: String) {
+        if self
+            .tx
+            .send(StateAccessRequest::OrderRefunded(order))
+            .await
+            .is_err()
+        {
+            tracing::warn!("Data race on shutdown; please restart the daemon for cleaning up");
+        };
+    }
+
+    /// Report that the payout of the order couldn't be made.
+    pub async fn payout_failed(&self, order: String) {
+        if self
+            .tx
+            .send(StateAccessRequest::PayoutFailed(order))
             .await
             .is_err()
         {
@@ -355,15 +726,37 @@ impl State {
         &self,
         order: String,
     ) -> Result<OrderResponse, ForceWithdrawalError> {
+        let (res, rx) = oneshot::channel();
         self.tx
-            .send(StateAccessRequest::ForceWithdrawal(order.clone()))
+            .send(StateAccessRequest::ForceWithdrawal(ForceWithdrawOrder {
+                order: order.clone(),
+                res,
+            }))
             .await
-            .map_err(|_| ForceWithdrawalError::InvalidParameter(order.clone()))?;
+            .map_err(|_| ForceWithdrawalError::InternalError)?;
+        rx.await
+            .map_err(|_| ForceWithdrawalError::InternalError)??;
 
-        match self.order_status(&order).await {
-            Ok(order_status) => Ok(order_status),
-            Err(_) => Ok(OrderResponse::NotFound),
-        }
+        self.order_status(&order)
+            .await
+            .map_err(|_| ForceWithdrawalError::InternalError)
+    }
+
+    /// Send the excess held on the payment account of a paid out order to the recipient.
+    pub async fn release(&self, order: String) -> Result<OrderResponse, ReleaseError> {
+        let (res, rx) = oneshot::channel();
+        self.tx
+            .send(StateAccessRequest::Release(ReleaseOrder {
+                order: order.clone(),
+                res,
+            }))
+            .await
+            .map_err(|_| ReleaseError::InternalError)?;
+        rx.await.map_err(|_| ReleaseError::InternalError)??;
+
+        self.order_status(&order)
+            .await
+            .map_err(|_| ReleaseError::InternalError)
     }
     pub fn interface(&self) -> Self {
         State {
@@ -386,21 +779,38 @@ impl State {
 enum StateAccessRequest {
     ConnectChain(HashMap<String, CurrencyProperties>),
     GetInvoiceStatus(GetInvoiceStatus),
+    GetPaymentAccountStatus(GetPaymentAccountStatus),
+    ListOrders(ListOrders),
+    OrderHistory(ReadOrderHistory),
+    ListWebhooks(ListWebhooks),
+    RedeliverWebhook(RedeliverWebhook),
     CreateInvoice(CreateInvoice),
-    IsCurrencySupported {
+    GetCurrency {
         currency: String,
-        res: oneshot::Sender<bool>,
+        res: oneshot::Sender<Option<CurrencyProperties>>,
     },
     ServerStatus(oneshot::Sender<ServerStatus>),
     ServerHealth(oneshot::Sender<ServerHealth>),
-    OrderPaid(String),
+    Audit(oneshot::Sender<Result<AuditReport, Error>>),
+    Investigate(InvestigateOrder),
+    OrderPaid(String, ChangeOrigin),
     IsOrderPaid(String, oneshot::Sender<bool>),
     RecordTransaction {
         order: String,
         tx: TransactionInfoDb,
     },
+    PaymentReceived {
+        order: String,
+        received: Balance,
+        block_number: BlockNumber,
+    },
+    OrderExpired(String, BlockNumber, oneshot::Sender<bool>),
+    Refund(RefundOrder),
+    OrderRefunded(String),
+    PayoutFailed(String),
     OrderWithdrawn(String),
-    ForceWithdrawal(String),
+    ForceWithdrawal(ForceWithdrawOrder),
+    Release(ReleaseOrder),
 }
 
 struct GetInvoiceStatus {
@@ -408,18 +818,110 @@ struct GetInvoiceStatus {
     pub res: oneshot::Sender<Result<OrderResponse, Error>>,
 }
 
+struct GetPaymentAccountStatus {
+    pub account: Account,
+    pub res: oneshot::Sender<Result<Option<PublicOrderInfo>, Error>>,
+}
+
+struct ListOrders {
+    pub query: OrderListQuery,
+    pub res: oneshot::Sender<Result<OrderList, Error>>,
+}
+
+struct ReadOrderHistory {
+    pub order: String,
+    pub res: oneshot::Sender<Result<Option<OrderHistory>, Error>>,
+}
+
+struct ListWebhooks {
+    pub query: WebhookListQuery,
+    pub res: oneshot::Sender<Result<Vec<WebhookInfo>, Error>>,
+}
+
+struct RedeliverWebhook {
+    pub id: WebhookId,
+    pub res: oneshot::Sender<Result<WebhookInfo, Error>>,
+}
+
+struct InvestigateOrder {
+    pub order: String,
+    pub order_info: OrderInfo,
+    pub from: Option<BlockNumber>,
+    pub to: Option<BlockNumber>,
+    pub res: oneshot::Sender<Result<Investigation, Error>>,
+}
+
+struct RefundOrder {
+    pub order: String,
+    pub amount: Option<Balance>,
+    pub res: oneshot::Sender<Result<(), RefundError>>,
+}
+
+struct ForceWithdrawOrder {
+    pub order: String,
+    pub res: oneshot::Sender<Result<(), ForceWithdrawalError>>,
+}
+
+struct ReleaseOrder {
+    pub order: String,
+    pub res: oneshot::Sender<Result<(), ReleaseError>>,
+}
+
 struct CreateInvoice {
     pub order_query: OrderQuery,
     pub res: oneshot::Sender<Result<OrderResponse, Error>>,
 }
 
+/// Recipient, remark, and accepted currencies of a merchant served by the daemon
+#[derive(Clone, Debug)]
+pub struct MerchantProfile {
+    pub recipient: AccountId32,
+    pub remark: Option<String>,
+    /// [`None`] accepts all supported currencies.
+    pub currencies: Option<Vec<String>>,
+}
+
+/// Profiles of the merchants served by the daemon. Orders without a merchant belong to the default
+/// profile made of the recipient and the remark from the command line.
+#[derive(Clone, Debug)]
+pub struct Merchants {
+    pub default: MerchantProfile,
+    pub profiles: HashMap<String, MerchantProfile>,
+}
+
+impl Merchants {
+    fn profile(&self, merchant: Option<&str>) -> Result<&MerchantProfile, OrderError> {
+        match merchant {
+            Some(id) => self.profiles.get(id).ok_or(OrderError::UnknownMerchant),
+            None => Ok(&self.default),
+        }
+    }
+
+    /// Recipient of the order payout, which its payment account is derived for. Orders keep the
+    /// recipient they were created for, so changing or removing their merchant doesn't orphan
+    /// their payment accounts. Older orders that haven't recorded it use their merchant profile.
+    fn recipient(&self, order_info: &OrderInfo) -> Result<AccountId32, OrderError> {
+        match &order_info.recipient {
+            Some(recipient) => AccountId32::from_base58_string(recipient)
+                .map(|(account, _)| account)
+                .map_err(|_| OrderError::InternalError),
+            None => self
+                .profile(order_info.merchant.as_deref())
+                .map(|profile| profile.recipient),
+        }
+    }
+}
+
 struct StateData {
     currencies: HashMap<String, CurrencyProperties>,
-    recipient: AccountId32,
+    merchants: Merchants,
     server_info: ServerInfo,
     db: Database,
+    webhooks: Webhooks,
     chain_manager: ChainManager,
     signer: Signer,
+    underpaid_policy: UnderpaidPolicy,
+    modification_policy: ModificationPolicy,
 }
 
 impl StateData {
@@ -430,65 +932,579 @@ impl StateData {
     async fn get_invoice_status(&self, order: String) -> Result<OrderResponse, Error> {
         if let Some(order_info) = self.db.read_order(order.clone()).await? {
             let message = String::new(); //TODO
-            Ok(OrderResponse::FoundOrder(OrderStatus {
-                order,
-                message,
-                recipient: self.recipient.clone().to_base58_string(2), // TODO maybe but spec says use "2"
-                server_info: self.server_info.clone(),
-                order_info,
-                payment_page: String::new(),
-                redirect_url: String::new(),
-            }))
+            Ok(OrderResponse::FoundOrder(
+                self.order_status(order, order_info, message)?,
+            ))
         } else {
             Ok(OrderResponse::NotFound)
         }
     }
 
+    /// Notify the merchant about the order event if they've subscribed to it. Delivery is retried
+    /// from the outbox until the merchant accepts it.
+    async fn emit(&self, order: String, event: OrderEvent) {
+        if let Err(e) = self.enqueue_webhook(order.clone(), event).await {
+            tracing::error!("Failed to enqueue the {event:?} webhook of order {order}: {e:?}");
+        }
+    }
+
+    async fn enqueue_webhook(&self, order: String, event: OrderEvent) -> Result<(), Error> {
+        let OrderResponse::FoundOrder(order_status) =
+            self.get_invoice_status(order.clone()).await?
+        else {
+            return Err(DbError::OrderNotFound(order).into());
+        };
+        let callback = order_status.order_info.callback.clone();
+
+        if callback.is_empty()
+            || self
+                .db
+                .subscription(order.clone())
+                .await?
+                .is_some_and(|events| !events.contains(&event))
+        {
+            return Ok(());
+        }
+
+        let payload = serde_json::to_string(&WebhookPayload {
+            event,
+            order_status,
+        })
+        .map_err(|e| DbError::SerializationError(e.to_string()))?;
+
+        self.db
+            .enqueue_webhook(order, event, callback, payload)
+            .await?;
+        self.webhooks.wake_up();
+
+        Ok(())
+    }
+
+    /// Times out the expired order, or keeps it awaiting payment if it's underpaid and the policy
+    /// says so. Returns whether the order should still be watched.
+    async fn expire_order(&self, order: String, origin: ChangeOrigin) -> bool {
+        let order_info = match self.db.read_order(order.clone()).await {
+            Ok(Some(order_info)) => order_info,
+            Ok(None) => return false,
+            Err(e) => {
+                tracing::error!("Failed to read expired order {order}: {e:?}");
+
+                return false;
+            }
+        };
+        let underpaid = match order_info.payment_status {
+            PaymentStatus::Pending => false,
+            PaymentStatus::Underpaid => true,
+            // Already settled, e.g., refunded before the expiry.
+            PaymentStatus::Paid | PaymentStatus::TimedOut => return false,
+        };
+
+        if underpaid && self.underpaid_policy == UnderpaidPolicy::Wait {
+            tracing::debug!(
+                "Order {order} has expired underpaid, waiting for the rest of the payment"
+            );
+
+            return true;
+        }
+
+        if let Err(e) = self.db.mark_timed_out(order.clone(), origin).await {
+            tracing::error!("Order has expired but this could not be recorded! {e:?}");
+
+            return false;
+        }
+
+        tracing::info!("Order {order} has timed out");
+        self.emit(order.clone(), OrderEvent::Expired).await;
+
+        // The tracker awaits the reply, so anything that goes through it must be spawned.
+        match (underpaid, self.underpaid_policy) {
+            (true, UnderpaidPolicy::Refund) => {
+                let refund = refund(
+                    self.db.clone(),
+                    self.chain_manager.clone(),
+                    self.merchants.clone(),
+                    self.currencies.clone(),
+                    order.clone(),
+                    None,
+                    origin,
+                );
+
+                tokio::spawn(async move {
+                    if let Err(e) = refund.await {
+                        tracing::error!("Failed to initiate refund for order {order}: {e:?}");
+                    }
+                });
+            }
+            (true, UnderpaidPolicy::Sweep) => match self.merchants.recipient(&order_info) {
+                Ok(recipient) => {
+                    tokio::spawn(sweep(
+                        self.db.clone(),
+                        self.chain_manager.clone(),
+                        recipient,
+                        order,
+                        order_info,
+                        origin,
+                    ));
+                }
+                Err(e) => tracing::error!("Failed to sweep underpaid order {order}: {e}"),
+            },
+            _ => {}
+        }
+
+        false
+    }
+
     async fn create_invoice(&self, order_query: OrderQuery) -> Result<OrderResponse, Error> {
         let order = order_query.order.clone();
-        let currency = self
+        let profile = self.merchants.profile(order_query.merchant.as_deref())?;
+
+        if profile
+            .currencies
+            .as_ref()
+            .is_some_and(|currencies| !currencies.contains(&order_query.currency))
+        {
+            return Err(OrderError::UnknownCurrency.into());
+        }
+
+        let recipient = profile.recipient;
+        let properties = self
             .currencies
             .get(&order_query.currency)
-            .ok_or(OrderError::UnknownCurrency)?;
-        let currency = currency.info(order_query.currency.clone());
-        let payment_account = self.signer.public(order.clone(), currency.ss58).await?;
+            .ok_or(OrderError::UnknownCurrency)?
+            .clone();
+        let payment_account = self
+            .signer
+            .public(order.clone(), recipient, properties.ss58)
+            .await?;
         match self
             .db
-            .create_order(order.clone(), order_query, currency, payment_account)
+            .create_order(
+                order.clone(),
+                order_query,
+                properties,
+                payment_account,
+                recipient.to_base58_string(42),
+                self.modification_policy,
+            )
             .await?
         {
             OrderCreateResponse::New(new_order_info) => {
                 self.chain_manager
-                    .add_invoice(order.clone(), new_order_info.clone(), self.recipient)
+                    .add_invoice(order.clone(), new_order_info.clone(), recipient)
                     .await?;
                 Ok(OrderResponse::NewOrder(self.order_status(
                     order,
                     new_order_info,
                     String::new(),
-                )))
+                )?))
+            }
+            OrderCreateResponse::Modified(order_info, previous_currency) => {
+                // The order keeps the recipient it was created for.
+                self.chain_manager
+                    .update_invoice(
+                        order.clone(),
+                        order_info.clone(),
+                        self.merchants.recipient(&order_info)?,
+                        previous_currency,
+                    )
+                    .await?;
+                Ok(OrderResponse::ModifiedOrder(self.order_status(
+                    order,
+                    order_info,
+                    String::new(),
+                )?))
             }
-            OrderCreateResponse::Modified(order_info) => Ok(OrderResponse::ModifiedOrder(
-                self.order_status(order, order_info, String::new()),
-            )),
             OrderCreateResponse::Collision(order_status) => {
                 Ok(OrderResponse::CollidedOrder(self.order_status(
                     order,
                     order_status,
                     String::from("Order with this ID was already processed"),
-                )))
+                )?))
             }
+            OrderCreateResponse::Rejected(order_info, reason) => Ok(OrderResponse::CollidedOrder(
+                self.order_status(order, order_info, reason)?,
+            )),
+            OrderCreateResponse::Foreign => Err(OrderError::ForeignOrder.into()),
         }
     }
 
-    fn order_status(&self, order: String, order_info: OrderInfo, message: String) -> OrderStatus {
-        OrderStatus {
+    /// Status of the order with its recipient and the remark of its merchant. Orders of a removed
+    /// merchant have no remark.
+    fn order_status(
+        &self,
+        order: String,
+        order_info: OrderInfo,
+        message: String,
+    ) -> Result<OrderStatus, Error> {
+        let recipient = self.merchants.recipient(&order_info)?;
+        let remark = self
+            .merchants
+            .profile(order_info.merchant.as_deref())
+            .ok()
+            .and_then(|profile| profile.remark.clone());
+
+        Ok(OrderStatus {
             order,
             message,
-            recipient: self.recipient.clone().to_base58_string(2), // TODO maybe but spec says use "2"
-            server_info: self.server_info.clone(),
+            recipient: recipient.to_base58_string(2), // TODO maybe but spec says use "2"
+            server_info: ServerInfo {
+                kalatori_remark: remark,
+                ..self.server_info.clone()
+            },
             order_info,
             payment_page: String::new(),
             redirect_url: String::new(),
+        })
+    }
+}
+
+/// Forward an investigation to the chain manager and wait for its outcome.
+async fn investigate(
+    chain_manager: ChainManager,
+    recipient: AccountId32,
+    request: InvestigateOrder,
+) {
+    let investigation = chain_manager
+        .investigate(
+            request.order,
+            request.order_info,
+            recipient,
+            request.from,
+            request.to,
+        )
+        .await
+        .map_err(Into::into);
+
+    drop(request.res.send(investigation));
+}
+
+/// Validate the refund request and forward it to the chain manager. Pending orders are timed out
+/// once the refund is submitted, so they can't get paid after being refunded.
+async fn refund(
+    db: Database,
+    chain_manager: ChainManager,
+    merchants: Merchants,
+    currencies: HashMap<String, CurrencyProperties>,
+    order: String,
+    amount: Option<Balance>,
+    origin: ChangeOrigin,
+) -> Result<(), RefundError> {
+    let order_info = db
+        .read_order(order.clone())
+        .await
+        .map_err(|_| RefundError::InternalError)?
+        .ok_or_else(|| RefundError::OrderNotFound(order.clone()))?;
+    let recipient = merchants
+        .recipient(&order_info)
+        .map_err(|_| RefundError::InternalError)?;
+
+    // A paid order that's waiting for withdrawal is being paid out right now.
+    let refundable = match order_info.withdrawal_status {
+        WithdrawalStatus::Waiting => order_info.payment_status != PaymentStatus::Paid,
+        WithdrawalStatus::Failed => true,
+        WithdrawalStatus::Forced | WithdrawalStatus::Completed | WithdrawalStatus::Refunded => {
+            false
         }
+    };
+
+    if !refundable {
+        return Err(RefundError::WithdrawalWasAttempted(order));
+    }
+
+    if payers(&order_info.transactions).is_empty() {
+        return Err(RefundError::NoPayers(order));
     }
+
+    let decimals = order_info.currency.decimals;
+    let existential_deposit = currencies
+        .get(&order_info.currency.currency)
+        .map_or(Balance(0), |currency| currency.existential_deposit);
+    let balance = chain_manager
+        .balance(order.clone(), order_info.clone(), recipient)
+        .await
+        .map_err(|_| RefundError::InternalError)?;
+
+    if let Some(requested) = amount {
+        if *requested == 0 {
+            return Err(RefundError::InvalidParameter(AMOUNT.into()));
+        }
+
+        let Some(left) = balance.checked_sub(*requested) else {
+            return Err(RefundError::ExceedsBalance(balance.format(decimals)));
+        };
+
+        if left != 0 && Balance(left) < existential_deposit {
+            return Err(RefundError::LessThanExistentialDeposit(
+                existential_deposit.format(decimals),
+            ));
+        }
+    } else if *balance == 0 {
+        return Err(RefundError::ExceedsBalance(balance.format(decimals)));
+    }
+
+    let unpaid = matches!(
+        order_info.payment_status,
+        PaymentStatus::Pending | PaymentStatus::Underpaid
+    );
+
+    chain_manager
+        .refund(order.clone(), order_info, recipient, amount)
+        .await
+        .map_err(RefundError::NotSubmitted)?;
+
+    if unpaid {
+        db.mark_timed_out(order, origin)
+            .await
+            .map_err(|_| RefundError::InternalError)?;
+    }
+
+    Ok(())
+}
+
+/// Send the funds of the order to the recipient as they are.
+async fn sweep(
+    db: Database,
+    chain_manager: ChainManager,
+    recipient: AccountId32,
+    order: String,
+    order_info: OrderInfo,
+    origin: ChangeOrigin,
+) {
+    match chain_manager
+        .force_reap(order.clone(), order_info, recipient)
+        .await
+    {
+        Ok(()) => {
+            if let Err(e) = db.mark_forced(order.clone(), origin).await {
+                tracing::error!("Failed to mark order {order} as forced: {e:?}");
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to initiate payout for underpaid order {order}: {e:?}");
+        }
+    }
+}
+
+/// Send the whole balance of the order payment account to the recipient and mark the order as
+/// forced. Orders that were withdrawn, refunded, or forced before are left as they are.
+async fn force_withdrawal(
+    db: Database,
+    chain_manager: ChainManager,
+    merchants: Merchants,
+    order: String,
+    origin: ChangeOrigin,
+) -> Result<(), ForceWithdrawalError> {
+    let order_info = db
+        .read_order(order.clone())
+        .await
+        .map_err(|_| ForceWithdrawalError::InternalError)?
+        .ok_or_else(|| ForceWithdrawalError::OrderNotFound(order.clone()))?;
+
+    if order_info.withdrawal_status != WithdrawalStatus::Waiting {
+        return Err(ForceWithdrawalError::WithdrawalWasAttempted(order));
+    }
+
+    let recipient = merchants
+        .recipient(&order_info)
+        .map_err(|_| ForceWithdrawalError::InternalError)?;
+
+    chain_manager
+        .force_reap(order.clone(), order_info, recipient)
+        .await
+        .map_err(ForceWithdrawalError::NotSubmitted)?;
+
+    db.mark_forced(order, origin)
+        .await
+        .map_err(|_| ForceWithdrawalError::InternalError)
+}
+
+/// Send the funds held on the payment account of a paid out order to the recipient. The splits
+/// have got their part with the payout already, so the whole balance goes to the recipient.
+async fn release(
+    db: Database,
+    chain_manager: ChainManager,
+    merchants: Merchants,
+    order: String,
+) -> Result<(), ReleaseError> {
+    let mut order_info = db
+        .read_order(order.clone())
+        .await
+        .map_err(|_| ReleaseError::InternalError)?
+        .ok_or_else(|| ReleaseError::OrderNotFound(order.clone()))?;
+
+    if order_info.payment_status != PaymentStatus::Paid
+        || order_info.withdrawal_status != WithdrawalStatus::Completed
+    {
+        return Err(ReleaseError::NotPaidOut(order));
+    }
+
+    let recipient = merchants
+        .recipient(&order_info)
+        .map_err(|_| ReleaseError::InternalError)?;
+    let held = chain_manager
+        .balance(order.clone(), order_info.clone(), recipient)
+        .await
+        .map_err(|_| ReleaseError::InternalError)?;
+
+    if *held == 0 {
+        return Err(ReleaseError::NothingHeld(order));
+    }
+
+    order_info.splits.clear();
+
+    chain_manager
+        .force_reap(order, order_info, recipient)
+        .await
+        .map_err(ReleaseError::NotSubmitted)
+}
+
+/// Walk all saved orders and reconcile them with current balances of their payment accounts.
+async fn audit(
+    db: Database,
+    chain_manager: ChainManager,
+    merchants: Merchants,
+    server_info: ServerInfo,
+) -> Result<AuditReport, Error> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| Error::Fatal)?
+        .as_millis()
+        .try_into()
+        .map_err(|_| Error::Fatal)?;
+    let mut totals: HashMap<String, CurrencyTotals> = HashMap::new();
+    // Paid amounts and balances are summed exactly and formatted once all orders are counted.
+    let mut sums: HashMap<String, (Decimals, Balance, Balance)> = HashMap::new();
+    let mut discrepancies = Vec::new();
+
+    for (order, order_info) in db.all_orders().await? {
+        let fetched = match merchants.recipient(&order_info) {
+            Ok(recipient) => chain_manager
+                .balance(order.clone(), order_info.clone(), recipient)
+                .await
+                .map_err(Error::from),
+            Err(e) => Err(e.into()),
+        };
+        let balance = match fetched {
+            Ok(found) => Some(found),
+            Err(e) => {
+                tracing::warn!("Failed to fetch the balance of order {order} for audit: {e}");
+                None
+            }
+        };
+        let decimals = order_info.currency.decimals;
+        let currency_totals = totals
+            .entry(order_info.currency.currency.clone())
+            .or_default();
+        let (_, paid_amount, balance_sum) = sums
+            .entry(order_info.currency.currency.clone())
+            .or_insert((decimals, Balance(0), Balance(0)));
+
+        currency_totals.orders = currency_totals.orders.saturating_add(1);
+
+        match order_info.payment_status {
+            PaymentStatus::Pending => {
+                currency_totals.pending = currency_totals.pending.saturating_add(1);
+            }
+            PaymentStatus::Paid => {
+                currency_totals.paid = currency_totals.paid.saturating_add(1);
+                *paid_amount = Balance(paid_amount.saturating_add(*order_info.amount));
+            }
+            PaymentStatus::TimedOut => {
+                currency_totals.timed_out = currency_totals.timed_out.saturating_add(1);
+            }
+            PaymentStatus::Underpaid => {
+                currency_totals.underpaid = currency_totals.underpaid.saturating_add(1);
+            }
+        }
+
+        if let Some(found) = balance {
+            *balance_sum = Balance(balance_sum.saturating_add(*found));
+        }
+
+        for kind in audit_order(&order_info, balance, Timestamp(now)) {
+            currency_totals.discrepancies = currency_totals.discrepancies.saturating_add(1);
+
+            discrepancies.push(Discrepancy {
+                order: order.clone(),
+                kind,
+                payment_account: order_info.payment_account.clone(),
+                currency: order_info.currency.currency.clone(),
+                payment_status: order_info.payment_status.clone(),
+                withdrawal_status: order_info.withdrawal_status.clone(),
+                amount: order_info.amount.format(decimals),
+                balance: balance.map(|found| found.format(decimals)),
+            });
+        }
+    }
+
+    for (currency, (decimals, paid_amount, balance)) in sums {
+        if let Some(currency_totals) = totals.get_mut(&currency) {
+            currency_totals.paid_amount = paid_amount.format(decimals);
+            currency_totals.balance = balance.format(decimals);
+        }
+    }
+
+    Ok(AuditReport {
+        server_info,
+        timestamp: Timestamp(now),
+        totals,
+        discrepancies,
+    })
+}
+
+/// Find everything that doesn't add up between the saved order and its payment account balance.
+fn audit_order(
+    order_info: &OrderInfo,
+    balance: Option<Balance>,
+    now: Timestamp,
+) -> Vec<DiscrepancyKind> {
+    let mut found = Vec::new();
+    let withdrawn = matches!(
+        order_info.withdrawal_status,
+        WithdrawalStatus::Completed | WithdrawalStatus::Forced | WithdrawalStatus::Refunded
+    );
+
+    if withdrawn
+        && !order_info
+            .transactions
+            .iter()
+            .any(|tx| matches!(tx.kind, TxKind::Withdrawal | TxKind::Refund))
+    {
+        found.push(DiscrepancyKind::MissingWithdrawalTransaction);
+    }
+
+    let Some(current) = balance else {
+        found.push(DiscrepancyKind::BalanceUnavailable);
+
+        return found;
+    };
+    let has_funds = *current > LOSS_TOLERANCE;
+
+    if withdrawn {
+        if has_funds {
+            found.push(DiscrepancyKind::FundsAfterWithdrawal);
+        }
+    } else {
+        match order_info.payment_status {
+            PaymentStatus::Paid => {
+                if has_funds {
+                    found.push(DiscrepancyKind::UnwithdrawnFunds);
+                }
+            }
+            PaymentStatus::Pending | PaymentStatus::Underpaid => {
+                if current >= order_info.amount {
+                    found.push(DiscrepancyKind::UnrecordedPayment);
+                } else if has_funds && order_info.death.0 <= now.0 {
+                    found.push(DiscrepancyKind::ExpiredWithFunds);
+                }
+            }
+            PaymentStatus::TimedOut => {
+                if has_funds {
+                    found.push(DiscrepancyKind::ExpiredWithFunds);
+                }
+            }
+        }
+    }
+
+    found
 }
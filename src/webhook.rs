@@ -0,0 +1,296 @@
+//! Webhook delivery worker
+//!
+//! Callbacks to merchants are saved to the outbox in the database first, and this worker delivers
+//! them from there, so a webhook survives both an unavailable merchant and a daemon restart.
+//! Failed deliveries are retried with an exponential backoff until [`MAX_ATTEMPTS`] is reached.
+//!
+//! In the signed mode, a webhook is a `POST` of the order status JSON with the headers:
+//!
+//! ```text
+//! X-Kalatori-Timestamp: <milliseconds since the Unix epoch>
+//! X-Kalatori-Signature: hex(HMAC-SHA256(secret, "<timestamp>\n<body>")),...
+//! ```
+//!
+//! with one signature per configured secret. The legacy mode is a bare `GET` to the callback URL.
+
+use crate::{
+    database::Database,
+    definitions::{
+        api_v2::{Timestamp, WebhookAttempt, WebhookInfo, WebhookStatus},
+        WebhookConfig, WebhookMode,
+    },
+    error::Error,
+    server::auth::{SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    utils::task_tracker::TaskTracker,
+};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::Notify, time};
+use tokio_util::sync::CancellationToken;
+
+pub const MODULE: &str = module_path!();
+
+/// Number of delivery attempts after which a webhook is marked as failed.
+pub const MAX_ATTEMPTS: u32 = 10;
+
+/// Delay before the first retry in milliseconds, doubled on each following one.
+const INITIAL_BACKOFF: u64 = 10_000;
+/// The longest delay between retries in milliseconds.
+const MAX_BACKOFF: u64 = 60 * 60 * 1000;
+/// How often the outbox is checked even if nothing wakes the worker up.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook delivery worker handle
+#[derive(Clone, Debug)]
+pub struct Webhooks {
+    wakeup: Arc<Notify>,
+}
+
+impl Webhooks {
+    pub fn init(
+        db: Database,
+        config: WebhookConfig,
+        task_tracker: TaskTracker,
+        shutdown_notification: CancellationToken,
+    ) -> Self {
+        let wakeup = Arc::new(Notify::new());
+        let worker_wakeup = wakeup.clone();
+
+        task_tracker.spawn("Webhook delivery", async move {
+            let client = Client::new();
+
+            loop {
+                let wait = match deliver_due(&db, &client, &config).await {
+                    Ok(wait) => wait,
+                    Err(e) => {
+                        tracing::error!("Failed to process the webhook outbox: {e:?}");
+
+                        POLL_INTERVAL
+                    }
+                };
+
+                tokio::select! {
+                    biased;
+                    () = shutdown_notification.cancelled() => break,
+                    () = worker_wakeup.notified() => {}
+                    () = time::sleep(wait) => {}
+                }
+            }
+
+            Ok("Webhook delivery is shut down.")
+        });
+
+        Self { wakeup }
+    }
+
+    /// Makes the worker check the outbox right away, e.g., after a webhook is enqueued.
+    pub fn wake_up(&self) {
+        self.wakeup.notify_one();
+    }
+}
+
+/// Delivers all webhooks that are due, and returns the time until the next one is.
+async fn deliver_due(
+    db: &Database,
+    client: &Client,
+    config: &WebhookConfig,
+) -> Result<Duration, Error> {
+    let mut wait = POLL_INTERVAL;
+
+    for mut webhook in db.pending_webhooks().await? {
+        let now = Timestamp::now();
+        let due = webhook
+            .next_attempt
+            .map_or(now.0, |next_attempt| next_attempt.0);
+
+        if due > now.0 {
+            wait = wait.min(Duration::from_millis(due.saturating_sub(now.0)));
+
+            continue;
+        }
+
+        // Orders of merchant profiles may have their own secrets.
+        let merchant = db
+            .read_order(webhook.order.clone())
+            .await?
+            .and_then(|order_info| order_info.merchant);
+        let error = deliver(client, config, &webhook, merchant.as_deref(), now)
+            .await
+            .err();
+
+        if let Some(reason) = &error {
+            tracing::warn!(
+                "Failed to deliver webhook {} of order {} to {}: {reason}",
+                webhook.id,
+                webhook.order,
+                webhook.url
+            );
+        }
+
+        record_attempt(
+            &mut webhook,
+            WebhookAttempt {
+                timestamp: now,
+                error,
+            },
+        );
+
+        if let Some(next_attempt) = webhook.next_attempt {
+            wait = wait.min(Duration::from_millis(next_attempt.0.saturating_sub(now.0)));
+        }
+
+        db.save_webhook(webhook).await?;
+    }
+
+    Ok(wait)
+}
+
+async fn deliver(
+    client: &Client,
+    config: &WebhookConfig,
+    webhook: &WebhookInfo,
+    merchant: Option<&str>,
+    now: Timestamp,
+) -> Result<(), String> {
+    tracing::info!("Sending callback to: {}", webhook.url);
+
+    let url = Url::parse(&webhook.url).map_err(|e| e.to_string())?;
+    let request = match config.for_host(url.host_str(), merchant) {
+        (WebhookMode::Legacy, _) => client.get(url),
+        (WebhookMode::Signed, secrets) => {
+            let request = client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .header(TIMESTAMP_HEADER, now.0);
+
+            if secrets.is_empty() {
+                request
+            } else {
+                request.header(SIGNATURE_HEADER, sign(secrets, now, &webhook.payload))
+            }
+            .body(webhook.payload.clone())
+        }
+    };
+
+    request
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Comma-separated signatures of the payload made with each of the secrets.
+fn sign(secrets: &[String], timestamp: Timestamp, payload: &str) -> String {
+    secrets
+        .iter()
+        .filter_map(|secret| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+
+            mac.update(format!("{}\n", timestamp.0).as_bytes());
+            mac.update(payload.as_bytes());
+
+            Some(const_hex::encode(mac.finalize().into_bytes()))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Appends the attempt to the webhook history and schedules the next one if needed.
+pub fn record_attempt(webhook: &mut WebhookInfo, attempt: WebhookAttempt) {
+    let now = attempt.timestamp;
+    let delivered = attempt.error.is_none();
+
+    webhook.attempts.push(attempt);
+
+    let attempts = u32::try_from(webhook.attempts.len()).unwrap_or(u32::MAX);
+
+    (webhook.status, webhook.next_attempt) = if delivered {
+        (WebhookStatus::Delivered, None)
+    } else if attempts >= MAX_ATTEMPTS {
+        (WebhookStatus::Failed, None)
+    } else {
+        (
+            WebhookStatus::Pending,
+            Some(Timestamp(now.0.saturating_add(backoff(attempts)))),
+        )
+    };
+}
+
+/// Delay in milliseconds after the given number of failed attempts.
+fn backoff(attempts: u32) -> u64 {
+    2u64.checked_pow(attempts.saturating_sub(1))
+        .map_or(MAX_BACKOFF, |factor| INITIAL_BACKOFF.saturating_mul(factor))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::api_v2::OrderEvent;
+
+    #[test]
+    fn signatures() {
+        let secrets = ["new secret".to_owned(), "old secret".to_owned()];
+        let signatures = sign(&secrets, Timestamp(1000), "{}");
+        let (new, old) = signatures.split_once(',').unwrap();
+
+        for (secret, signature) in secrets.iter().zip([new, old]) {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+
+            mac.update(b"1000\n{}");
+            mac.verify_slice(&const_hex::decode(signature).unwrap())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn retries() {
+        assert_eq!(backoff(1), INITIAL_BACKOFF);
+        assert_eq!(backoff(2), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff(100), MAX_BACKOFF);
+
+        let mut webhook = WebhookInfo {
+            id: 0,
+            order: "order".into(),
+            event: OrderEvent::Paid,
+            url: "https://example.com/callback".into(),
+            payload: "{}".into(),
+            status: WebhookStatus::Pending,
+            created: Timestamp(0),
+            next_attempt: Some(Timestamp(0)),
+            attempts: Vec::new(),
+        };
+
+        for attempt in 1..MAX_ATTEMPTS {
+            record_attempt(
+                &mut webhook,
+                WebhookAttempt {
+                    timestamp: Timestamp(1000),
+                    error: Some("unavailable".into()),
+                },
+            );
+
+            assert_eq!(webhook.status, WebhookStatus::Pending);
+            assert_eq!(
+                webhook.next_attempt.map(|next_attempt| next_attempt.0),
+                Some(1000 + backoff(attempt))
+            );
+        }
+
+        record_attempt(
+            &mut webhook,
+            WebhookAttempt {
+                timestamp: Timestamp(1000),
+                error: Some("unavailable".into()),
+            },
+        );
+
+        assert_eq!(webhook.status, WebhookStatus::Failed);
+        assert!(webhook.next_attempt.is_none());
+    }
+}
@@ -48,7 +48,7 @@ describe('Order Endpoint Blackbox Tests', () => {
     expect(orderResponseObject).toHaveProperty('server_info');
     expect(orderResponseObject).toHaveProperty('withdrawal_status', 'waiting');
     expect(orderResponseObject).toHaveProperty('payment_status', 'pending');
-    expect(orderResponseObject).toHaveProperty('amount', orderData.amount);
+    expect(orderResponseObject).toHaveProperty('amount', String(orderData.amount));
 
     expect(orderResponseObject).toHaveProperty('callback', orderData.callback);
     expect(orderResponseObject).toHaveProperty('transactions');